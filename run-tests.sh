@@ -12,12 +12,8 @@ cargo build
 # Run internal Rust tests
 cargo check
 
-# Run the integration tests with avr-gcc.
-# We expect this to always pass. If this fails, something is likely
+# Run the integration tests with both avr-gcc and LLVM.
+# We expect avr-gcc to always pass. If it fails, something is likely
 # wrong with the tests
-echo "Running tests with avr-gcc"
-cargo run --bin avr-lit --quiet -- --avr-gcc tests/
-
-echo "Running tests with LLVM"
-cargo run --bin avr-lit --quiet -- --llvm-sysroot "$LLVM_SYSROOT" tests/
+cargo run --bin avr-lit --quiet -- --avr-gcc --llvm-sysroot "$LLVM_SYSROOT" tests/
 
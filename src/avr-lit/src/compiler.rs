@@ -0,0 +1,142 @@
+//! The compilers that tests can be built with.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Information about a compiler.
+#[derive(Clone, Debug)]
+pub struct Compiler {
+    /// A short name that identifies the compiler in reports, such as `gcc`.
+    pub name: String,
+    pub kind: CompilerKind,
+    /// The C compiler.
+    pub cc: PathBuf,
+    /// The C++ compiler.
+    pub cxx: PathBuf,
+    /// Flags to be passed to both C and C++ compilers, apart from the MCU.
+    pub compiler_flags: Vec<String>,
+    /// Flags only passed to the C compiler, such as `-std=c11`.
+    pub c_flags: Vec<String>,
+    /// Flags only passed to the C++ compiler, such as `-std=c++11`.
+    pub cxx_flags: Vec<String>,
+    /// Flags passed when linking, which RUN lines refer to as `@ldflags`.
+    pub linker_flags: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompilerKind {
+    Gcc,
+    Llvm,
+}
+
+impl CompilerKind {
+    /// Gets the name of the compiler family, such as `llvm`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CompilerKind::Gcc => "gcc",
+            CompilerKind::Llvm => "llvm",
+        }
+    }
+}
+
+impl Compiler {
+    /// Inserts the constants that RUN lines use to invoke the compiler for an MCU.
+    pub fn insert_constants(&self, mcu: &str, constants: &mut HashMap<String, String>) {
+        constants.insert("cc".to_owned(),
+                         self.cc.display().to_string());
+        constants.insert("cflags".to_owned(), self.flags_for(mcu, &self.c_flags));
+
+        constants.insert("cxx".to_owned(),
+                         self.cxx.display().to_string());
+        constants.insert("cxxflags".to_owned(), self.flags_for(mcu, &self.cxx_flags));
+
+        constants.insert("ldflags".to_owned(), self.linker_flags.join(" "));
+        constants.insert("mcu".to_owned(), mcu.to_owned());
+    }
+
+    fn flags_for(&self, mcu: &str, language_flags: &[String]) -> String {
+        let mut flags = vec![format!("-mmcu={}", mcu)];
+        flags.extend(self.compiler_flags.iter().chain(language_flags).cloned());
+        flags.join(" ")
+    }
+}
+
+/// Finds avr-gcc in a directory, or in `PATH` if no directory is given. The
+/// compiler is named `name` in reports.
+pub fn gnu_tools(bin_dir: Option<&Path>, name: String) -> Option<Compiler> {
+    let cc_path = match bin_dir {
+        Some(bin_dir) => Some(bin_dir.join("avr-gcc")).filter(|path| path.is_file()),
+        None => util::find_in_path("avr-gcc"),
+    };
+
+    match cc_path {
+        Some(cc_path) => {
+            let exec_dir = cc_path.parent().unwrap();
+
+            Some(Compiler {
+                name,
+                kind: CompilerKind::Gcc,
+                cc: cc_path.to_owned(),
+                cxx: exec_dir.join("avr-g++"),
+                compiler_flags: all_compiler_flags(&[]),
+                c_flags: Vec::new(),
+                cxx_flags: Vec::new(),
+                linker_flags: Vec::new(),
+            })
+        },
+        None => None,
+    }
+}
+
+fn all_compiler_flags(other_flags: &[&'static str]) -> Vec<String> {
+    let mut flags = vec![
+        "-ffunction-sections",
+        "-g",
+    ];
+
+    flags.extend(other_flags);
+    flags.into_iter().map(ToOwned::to_owned).collect()
+}
+
+/// Finds clang in an LLVM sysroot. The compiler is named `name` in reports.
+pub fn detect_compiler(sysroot: &Path, name: String) -> Option<Compiler> {
+    let bin_dir = sysroot.join("bin");
+
+    if bin_dir.join("clang").exists() {
+        Some(Compiler {
+            name,
+            kind: CompilerKind::Llvm,
+            cc: bin_dir.join("clang"),
+            cxx: bin_dir.join("clang++"),
+            compiler_flags: all_compiler_flags(&[
+                "-target", "avr-unknown-unknown",
+            ]),
+            c_flags: Vec::new(),
+            cxx_flags: Vec::new(),
+            linker_flags: Vec::new(),
+        })
+    } else {
+        None
+    }
+}
+
+mod util {
+    use std::path::{Path, PathBuf};
+    use std::env;
+
+    // Adapted from
+    // https://stackoverflow.com/a/37499032
+    pub fn find_in_path<P>(exe_name: P) -> Option<PathBuf>
+        where P: AsRef<Path> {
+        env::var_os("PATH").and_then(|paths| {
+            env::split_paths(&paths).filter_map(|dir| {
+                let full_path = dir.join(&exe_name);
+                if full_path.is_file() {
+                    Some(full_path)
+                } else {
+                    None
+                }
+            }).next()
+        })
+    }
+}
@@ -0,0 +1,165 @@
+//! The combinations of compiler, target and optimization level that tests are run with.
+
+use crate::compiler::Compiler;
+use std::collections::HashMap;
+
+/// The MCU that tests are compiled for if none is given on the command line.
+pub const DEFAULT_MCU: &str = "atmega328p";
+
+/// The optimization level that tests are compiled at if none is given on the command line.
+pub const DEFAULT_OPT_LEVEL: &str = "O0";
+
+/// Every optimization level that can be asked for, without the leading dash.
+pub const OPT_LEVELS: &[&str] = &["O0", "O1", "O2", "O3", "Os", "Oz", "Og"];
+
+/// A compiler, the MCU it compiles for, and how much it optimizes.
+#[derive(Clone, Debug)]
+pub struct Configuration {
+    /// Identifies the configuration in reports, such as `gcc` or `llvm/attiny85/Os`.
+    pub name: String,
+    pub compiler: Compiler,
+    /// The MCU that tests are compiled for and simulated on, such as `atmega328p`.
+    pub mcu: String,
+    /// The optimization level without the leading dash, such as `Os`.
+    pub opt_level: String,
+}
+
+impl Configuration {
+    /// Inserts the constants that RUN lines use, such as `@cc`, `@mcu` and `@optflags`.
+    pub fn insert_constants(&self, constants: &mut HashMap<String, String>) {
+        self.compiler.insert_constants(&self.mcu, constants);
+        constants.insert("optflags".to_owned(), format!("-{}", self.opt_level));
+    }
+
+    /// Gets the features that `XFAIL`, `UNSUPPORTED` and `REQUIRES` directives
+    /// can test for, such as `llvm`, `attiny85`, `Os` and `mul-instruction`.
+    pub fn features(&self) -> Vec<String> {
+        let mut features = vec![self.compiler.name.clone(), self.compiler.kind.name().to_owned(), self.mcu.clone(), self.opt_level.clone()];
+        features.extend(mcu_features(&self.mcu).iter().map(|f| f.to_string()));
+        features.dedup();
+        features
+    }
+
+    /// Checks if the only difference between two configurations is the compiler.
+    pub fn differs_only_in_compiler(&self, other: &Configuration) -> bool {
+        self.mcu == other.mcu && self.opt_level == other.opt_level
+    }
+}
+
+/// Builds every combination of the given compilers, MCUs and optimization levels.
+///
+/// Configurations are only named after the settings that vary, so a single
+/// MCU and optimization level gives names such as `gcc` rather than
+/// `gcc/atmega328p/O0`.
+pub fn all(compilers: &[Compiler], mcus: &[String], opt_levels: &[String]) -> Vec<Configuration> {
+    let mut configurations = Vec::new();
+
+    for compiler in compilers {
+        for mcu in mcus {
+            for opt_level in opt_levels {
+                let mut parts = Vec::new();
+                if compilers.len() > 1 { parts.push(&compiler.name[..]); }
+                if mcus.len() > 1 { parts.push(mcu); }
+                if opt_levels.len() > 1 { parts.push(opt_level); }
+
+                let name = if parts.is_empty() { compiler.name.clone() } else { parts.join("/") };
+
+                configurations.push(Configuration {
+                    name,
+                    compiler: compiler.clone(),
+                    mcu: mcu.clone(),
+                    opt_level: opt_level.clone(),
+                });
+            }
+        }
+    }
+
+    configurations
+}
+
+/// Parses an optimization level such as `Os` or `-Os`.
+pub fn parse_opt_level(opt_level: &str) -> Result<String, String> {
+    let opt_level = opt_level.trim().trim_start_matches('-');
+
+    if OPT_LEVELS.contains(&opt_level) {
+        Ok(opt_level.to_owned())
+    } else {
+        Err(format!("unknown optimization level '{}', expected one of {}", opt_level, OPT_LEVELS.join(", ")))
+    }
+}
+
+/// Gets the features of an MCU that tests may depend on.
+///
+/// These are derived from the name of the MCU, so they only cover the
+/// families avr-lit is commonly used with.
+fn mcu_features(mcu: &str) -> Vec<&'static str> {
+    let mcu = mcu.to_lowercase();
+    let mut features = Vec::new();
+
+    // The classic AT90S parts predate the hardware multiplier and only have a UART.
+    let is_mega = mcu.starts_with("atmega") || (mcu.starts_with("at90") && !mcu.starts_with("at90s"));
+    let is_xmega = mcu.starts_with("atxmega");
+    let is_reduced_core = ["attiny4", "attiny5", "attiny9", "attiny10", "attiny20", "attiny40", "attiny102", "attiny104"].contains(&&mcu[..]);
+
+    if is_mega || is_xmega {
+        features.extend(&["mul-instruction", "usart"]);
+    }
+    if is_xmega {
+        features.push("xmega");
+    }
+    if is_reduced_core {
+        features.push("reduced-core");
+    }
+    if ["atmega256", "atxmega192", "atxmega256", "atxmega384"].iter().any(|prefix| mcu.starts_with(prefix)) {
+        // More than 128 KiB of program memory needs a 22-bit program counter.
+        features.push("22-bit-pc");
+    }
+    if ["attiny2313", "attiny4313", "attiny1634", "attiny441", "attiny841"].iter().any(|prefix| mcu.starts_with(prefix)) {
+        features.push("usart");
+    }
+    if USART0_FAMILIES.iter().any(|family| is_in_family(&mcu, family)) {
+        // Older parts such as the ATmega8 name their only USART's registers `UCSRA`, `UDR` and so on.
+        features.push("usart0");
+    }
+
+    features
+}
+
+/// Families of MCUs whose first USART has numbered registers such as `UCSR0A` and `UDR0`.
+const USART0_FAMILIES: &[&str] = &[
+    "atmega48", "atmega88", "atmega168", "atmega328",
+    "atmega164", "atmega324", "atmega644", "atmega1284",
+    "atmega640", "atmega1280", "atmega1281", "atmega2560", "atmega2561",
+    "atmega64", "atmega128", "atmega162",
+    "attiny441", "attiny841", "attiny1634",
+];
+
+/// Checks if an MCU is a member of a family, such as `atmega328p` of `atmega328`.
+fn is_in_family(mcu: &str, family: &str) -> bool {
+    match mcu.strip_prefix(family) {
+        Some(variant) => ["", "a", "p", "pa", "pb", "v"].contains(&variant),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbered_usart_registers_are_a_separate_feature() {
+        for mcu in &["atmega328p", "ATmega2560", "atmega1284p", "attiny841"] {
+            assert!(mcu_features(mcu).contains(&"usart0"), "{}", mcu);
+        }
+        for mcu in &["atmega8", "atmega32", "atmega32u4", "atmega4809", "atxmega128a1", "attiny2313"] {
+            assert!(!mcu_features(mcu).contains(&"usart0"), "{}", mcu);
+        }
+        assert!(mcu_features("atmega8").contains(&"usart"));
+    }
+
+    #[test]
+    fn at90s_parts_have_no_multiplier_or_usart() {
+        assert!(mcu_features("at90s8515").is_empty());
+        assert_eq!(vec!["mul-instruction", "usart"], mcu_features("at90usb1287"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Comparing the output of a test between compilers.
+//!
+//! Every compiler should produce a program that behaves the same way, so any
+//! difference in what the simulator prints is a likely miscompilation, even
+//! if no `CHECK` covers it.
+
+use crate::run::TestResult;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Lines that legitimately differ between compilers, because they depend on
+/// how many cycles the program takes rather than what it does.
+const TIMING_LINE_PATTERNS: &[&str] = &[
+    r"^Dumping watches values changed in CPU cycle #\d+",
+    r"^Simulated \d+ CPU cycles",
+    r"^=+$",
+];
+
+/// The most cells the table of common subsequence lengths may have. Outputs
+/// that differ in more lines than this allows only have their first
+/// difference reported.
+const MAX_DIFF_TABLE_SIZE: usize = 1 << 20;
+
+/// Compares the output of every `RUN` line with the output for the reference compiler.
+///
+/// Returns a description of the differences, or `None` if the outputs match.
+/// Lines matching any of `ignore` are left out of the comparison.
+pub fn compare(reference_name: &str, reference: &TestResult, result: &TestResult, ignore: &[Regex]) -> Option<String> {
+    let timing_patterns = timing_patterns();
+    let mut differences = String::new();
+
+    for (index, (expected, actual)) in reference.runs.iter().zip(result.runs.iter()).enumerate() {
+        let expected_lines = observable_lines(&expected.stdout, timing_patterns, ignore);
+        let actual_lines = observable_lines(&actual.stdout, timing_patterns, ignore);
+
+        if expected_lines != actual_lines {
+            differences += &format!("output of RUN line {} differs from {} ('-' is {}, '+' is this compiler):\n\n", index + 1, reference_name, reference_name);
+            differences += &diff(&expected_lines, &actual_lines);
+            differences += "\n";
+        }
+    }
+
+    if differences.is_empty() { None } else { Some(differences.trim_end().to_owned()) }
+}
+
+fn timing_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| TIMING_LINE_PATTERNS.iter().map(|p| Regex::new(p).unwrap()).collect())
+}
+
+fn observable_lines<'a>(output: &'a str, timing_patterns: &[Regex], ignore: &[Regex]) -> Vec<&'a str> {
+    output.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !timing_patterns.iter().chain(ignore.iter()).any(|p| p.is_match(line)))
+        .collect()
+}
+
+/// Formats the difference between two lists of lines, showing a line of
+/// context around each change.
+fn diff(expected: &[&str], actual: &[&str]) -> String {
+    // Only the lines between the common prefix and suffix need comparing.
+    let prefix = expected.iter().zip(actual).take_while(|(e, a)| e == a).count();
+    let suffix = expected[prefix..].iter().rev().zip(actual[prefix..].iter().rev()).take_while(|(e, a)| e == a).count();
+    let expected_middle = &expected[prefix..expected.len() - suffix];
+    let actual_middle = &actual[prefix..actual.len() - suffix];
+
+    let mut lines = expected[..prefix].iter().map(|line| (' ', *line)).collect::<Vec<_>>();
+
+    if expected_middle.len().saturating_mul(actual_middle.len()) > MAX_DIFF_TABLE_SIZE {
+        lines.extend(expected_middle.first().map(|line| ('-', *line)));
+        lines.extend(actual_middle.first().map(|line| ('+', *line)));
+
+        return format_diff(&lines) + &format!("    ... {} more lines differ, only the first difference is shown\n",
+                                             (expected_middle.len() + actual_middle.len()).saturating_sub(2));
+    }
+
+    lines.extend(edit_script(expected_middle, actual_middle));
+    lines.extend(expected[expected.len() - suffix..].iter().map(|line| (' ', *line)));
+    format_diff(&lines)
+}
+
+/// Finds the shortest list of lines to remove and add to turn `expected`
+/// into `actual`, as the lines of a diff.
+fn edit_script<'a>(expected: &[&'a str], actual: &[&'a str]) -> Vec<(char, &'a str)> {
+    // The length of the longest common subsequence of every pair of suffixes.
+    let mut lengths = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lengths[i][j] = if expected[i] == actual[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push((' ', expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(('-', expected[i]));
+            i += 1;
+        } else {
+            lines.push(('+', actual[j]));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+fn format_diff(lines: &[(char, &str)]) -> String {
+    let is_near_change = |index: usize| {
+        lines[index.saturating_sub(1)..(index + 2).min(lines.len())].iter().any(|(kind, _)| *kind != ' ')
+    };
+
+    let mut formatted = String::new();
+    let mut skipped = false;
+    for (index, (kind, line)) in lines.iter().enumerate() {
+        if is_near_change(index) {
+            if skipped {
+                formatted += "    ...\n";
+                skipped = false;
+            }
+            formatted += &format!("  {} {}\n", kind, line);
+        } else {
+            skipped = true;
+        }
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timing_lines_are_not_observable() {
+        let output = "Dumping watches values changed in CPU cycle #12 (0.75 µs)\nOUTPUT_VALUE = 7\n\n=====\nSimulated 40 CPU cycles\n";
+
+        assert_eq!(vec!["OUTPUT_VALUE = 7"], observable_lines(output, timing_patterns(), &[]));
+        assert!(observable_lines(output, timing_patterns(), &[Regex::new("^OUTPUT").unwrap()]).is_empty());
+    }
+
+    #[test]
+    fn diffs_show_a_line_of_context_around_each_change() {
+        let expected = ["a", "b", "c", "d", "e", "f", "g"];
+        let actual = ["a", "b", "x", "d", "e", "f", "g", "h"];
+
+        let expected_diff = ["    ...", "    b", "  - c", "  + x", "    d", "    ...", "    g", "  + h", ""].join("\n");
+        assert_eq!(expected_diff, diff(&expected, &actual));
+    }
+
+    #[test]
+    fn diffs_of_outputs_that_differ_throughout_show_the_first_difference() {
+        let expected = (0..2000).map(|i| format!("expected {}", i)).collect::<Vec<_>>();
+        let actual = (0..2000).map(|i| format!("actual {}", i)).collect::<Vec<_>>();
+        let mut expected = expected.iter().map(String::as_str).collect::<Vec<_>>();
+        let mut actual = actual.iter().map(String::as_str).collect::<Vec<_>>();
+        expected.insert(0, "same");
+        actual.insert(0, "same");
+
+        assert_eq!("    same\n  - expected 0\n  + actual 0\n    ... 3998 more lines differ, only the first difference is shown\n",
+                   diff(&expected, &actual));
+    }
+}
@@ -0,0 +1,249 @@
+//! Boolean expressions over features, as written in `XFAIL`, `UNSUPPORTED`
+//! and `REQUIRES` directives.
+//!
+//! A feature is true if the configuration a test is run with has it, such as
+//! `llvm`, `O0`, `attiny85` or `mul-instruction`. Features can be combined with
+//! `&&`, `||`, `!` and parentheses, and `*` is always true.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression {
+    Feature(String),
+    /// `*`, which matches every configuration.
+    Any,
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Evaluates the expression. Features are compared without regard to case.
+    pub fn evaluate(&self, features: &[String]) -> bool {
+        match *self {
+            Expression::Feature(ref name) => features.iter().any(|f| f.eq_ignore_ascii_case(name)),
+            Expression::Any => true,
+            Expression::Not(ref inner) => !inner.evaluate(features),
+            Expression::And(ref a, ref b) => a.evaluate(features) && b.evaluate(features),
+            Expression::Or(ref a, ref b) => a.evaluate(features) || b.evaluate(features),
+        }
+    }
+
+    /// Parses a comma separated list of expressions, such as `llvm && O0, attiny85`.
+    pub fn parse_list(text: &str) -> Result<Vec<Expression>, String> {
+        text.split(',').map(str::trim).filter(|s| !s.is_empty()).map(|s| s.parse()).collect()
+    }
+}
+
+impl std::str::FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens: &tokens, position: 0 };
+        let expression = parser.or()?;
+
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected '{}' in expression '{}'", token, s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Feature(String),
+    Any,
+    Not,
+    And,
+    Or,
+    OpenParen,
+    CloseParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+
+    while !rest.is_empty() {
+        let (token, length) = if rest.starts_with("&&") {
+            (Token::And, 2)
+        } else if rest.starts_with("||") {
+            (Token::Or, 2)
+        } else if rest.starts_with('!') {
+            (Token::Not, 1)
+        } else if rest.starts_with('(') {
+            (Token::OpenParen, 1)
+        } else if rest.starts_with(')') {
+            (Token::CloseParen, 1)
+        } else if rest.starts_with('*') {
+            (Token::Any, 1)
+        } else {
+            let length = rest.find(|c: char| !is_feature_char(c)).unwrap_or(rest.len());
+            if length == 0 {
+                return Err(format!("unexpected '{}' in expression '{}'", rest.chars().next().unwrap(), s));
+            }
+            (Token::Feature(rest[..length].to_owned()), length)
+        };
+
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn is_feature_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_.:+/".contains(c)
+}
+
+/// A recursive descent parser where `!` binds tighter than `&&`, which binds tighter than `||`.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.eat(&Token::Or) {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.not()?;
+        while self.eat(&Token::And) {
+            expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<Expression, String> {
+        if self.eat(&Token::Not) {
+            Ok(Expression::Not(Box::new(self.not()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expression, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        match token {
+            Some(Token::Feature(name)) => Ok(Expression::Feature(name)),
+            Some(Token::Any) => Ok(Expression::Any),
+            Some(Token::OpenParen) => {
+                let expression = self.or()?;
+                if self.eat(&Token::CloseParen) {
+                    Ok(expression)
+                } else {
+                    Err("expected ')' in expression".to_owned())
+                }
+            },
+            Some(token) => Err(format!("expected a feature but found '{}'", token)),
+            None => Err("expected a feature at the end of the expression".to_owned()),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.position) == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Feature(ref name) => write!(fmt, "{}", name),
+            Token::Any => write!(fmt, "*"),
+            Token::Not => write!(fmt, "!"),
+            Token::And => write!(fmt, "&&"),
+            Token::Or => write!(fmt, "||"),
+            Token::OpenParen => write!(fmt, "("),
+            Token::CloseParen => write!(fmt, ")"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feature(name: &str) -> Box<Expression> {
+        Box::new(Expression::Feature(name.to_owned()))
+    }
+
+    fn parse(s: &str) -> Expression {
+        s.parse().unwrap()
+    }
+
+    fn features(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(Expression::Or(feature("a"), Box::new(Expression::And(feature("b"), feature("c")))), parse("a || b && c"));
+        assert_eq!(Expression::Or(Box::new(Expression::And(feature("a"), feature("b"))), feature("c")), parse("a && b || c"));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and() {
+        assert_eq!(Expression::And(Box::new(Expression::Not(feature("a"))), feature("b")), parse("!a && b"));
+        assert_eq!(Expression::Not(Box::new(Expression::Not(feature("a")))), parse("!!a"));
+    }
+
+    #[test]
+    fn parentheses_group_expressions() {
+        assert_eq!(Expression::And(Box::new(Expression::Or(feature("a"), feature("b"))), feature("c")), parse("(a || b) && c"));
+        assert_eq!(Expression::Not(Box::new(Expression::And(feature("a"), feature("b")))), parse("!(a&&b)"));
+    }
+
+    #[test]
+    fn can_parse_features_with_punctuation() {
+        assert_eq!(Expression::Feature("mul-instruction".to_owned()), parse("mul-instruction"));
+        assert_eq!(Expression::Feature("c++".to_owned()), parse(" c++ "));
+    }
+
+    #[test]
+    fn star_matches_every_configuration() {
+        assert_eq!(Expression::Any, parse("*"));
+        assert!(parse("*").evaluate(&[]));
+        assert!(!parse("!*").evaluate(&features(&["llvm"])));
+    }
+
+    #[test]
+    fn features_are_matched_without_regard_to_case() {
+        let configuration = features(&["llvm", "O0", "atmega328p"]);
+
+        assert!(parse("LLVM && o0").evaluate(&configuration));
+        assert!(parse("gcc || ATmega328P").evaluate(&configuration));
+        assert!(!parse("llvm && !O0").evaluate(&configuration));
+    }
+
+    #[test]
+    fn lists_are_split_on_commas() {
+        assert_eq!(Ok(vec![Expression::And(feature("llvm"), feature("O0")), *feature("attiny85")]),
+                   Expression::parse_list("llvm && O0, attiny85"));
+        assert_eq!(Ok(vec![*feature("a"), *feature("b")]), Expression::parse_list(" a ,, b, "));
+        assert_eq!(Ok(vec![]), Expression::parse_list(""));
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        assert_eq!(Err("expected a feature at the end of the expression".to_owned()), "llvm &&".parse::<Expression>());
+        assert_eq!(Err("expected ')' in expression".to_owned()), "(O0".parse::<Expression>());
+        assert_eq!(Err("unexpected ')' in expression 'O0)'".to_owned()), "O0)".parse::<Expression>());
+        assert_eq!(Err("unexpected 'gcc' in expression 'llvm gcc'".to_owned()), "llvm gcc".parse::<Expression>());
+        assert_eq!(Err("expected a feature but found '||'".to_owned()), "|| llvm".parse::<Expression>());
+        assert_eq!(Err("unexpected '&' in expression 'a & b'".to_owned()), "a & b".parse::<Expression>());
+        assert!(Expression::parse_list("llvm, (O0").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Running every test file with every configuration on several threads.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs `job` for every combination of test file and configuration, with up
+/// to `job_count` jobs running at once.
+///
+/// `on_test_finished` is called on the current thread with the results of a
+/// test file once it has run with every configuration. Test files are
+/// finished in order, so the output is the same however many jobs there are.
+pub fn run<T, C, R>(test_files: &[T], configurations: &[C], job_count: usize,
+                    job: impl Fn(&T, &C) -> R + Sync,
+                    mut on_test_finished: impl FnMut(&T, Vec<R>))
+    where T: Sync, C: Sync, R: Send {
+    if configurations.is_empty() {
+        return;
+    }
+
+    let total = test_files.len() * configurations.len();
+    let next_job = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..job_count.clamp(1, total.max(1)) {
+            let (sender, next_job, job) = (sender.clone(), &next_job, &job);
+
+            scope.spawn(move || loop {
+                let index = next_job.fetch_add(1, Ordering::Relaxed);
+                if index >= total {
+                    break;
+                }
+
+                let result = job(&test_files[index / configurations.len()], &configurations[index % configurations.len()]);
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        // Only the jobs hold senders now, so the loop below ends when they are all done.
+        drop(sender);
+
+        let mut results: Vec<Option<R>> = (0..total).map(|_| None).collect();
+        let mut next_test_file = 0;
+
+        for (index, result) in receiver {
+            results[index] = Some(result);
+
+            while next_test_file < test_files.len() {
+                let test_results = &mut results[next_test_file * configurations.len()..(next_test_file + 1) * configurations.len()];
+                if test_results.iter().any(Option::is_none) {
+                    break;
+                }
+
+                on_test_finished(&test_files[next_test_file], test_results.iter_mut().map(|r| r.take().unwrap()).collect());
+                next_test_file += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Runs jobs that each sleep for a pseudorandom time, so that they finish
+    /// out of order, returning the test files and results in callback order.
+    fn run_shuffled(test_files: &[usize], configurations: &[char], job_count: usize) -> (Vec<(usize, Vec<String>)>, Vec<String>) {
+        let finished = Mutex::new(Vec::new());
+        let mut callbacks = Vec::new();
+
+        run(test_files, configurations, job_count, |&test_file, &configuration| {
+            let seed = (test_file as u64 * 31 + configuration as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            thread::sleep(Duration::from_millis(seed >> 61));
+
+            let result = format!("{}{}", test_file, configuration);
+            finished.lock().unwrap().push(result.clone());
+            result
+        }, |&test_file, results| callbacks.push((test_file, results)));
+
+        (callbacks, finished.into_inner().unwrap())
+    }
+
+    #[test]
+    fn results_are_grouped_by_test_file_in_order() {
+        let test_files = (0..12).collect::<Vec<_>>();
+        let configurations = ['a', 'b', 'c'];
+
+        let (callbacks, finished) = run_shuffled(&test_files, &configurations, 4);
+
+        let expected = test_files.iter().map(|&test_file| {
+            (test_file, configurations.iter().map(|configuration| format!("{}{}", test_file, configuration)).collect::<Vec<_>>())
+        }).collect::<Vec<_>>();
+        assert_eq!(expected, callbacks);
+
+        // Otherwise the test would not show that out of order results are put back in order.
+        assert_ne!(expected.iter().flat_map(|(_, results)| results.clone()).collect::<Vec<_>>(), finished);
+
+        let mut sorted = finished;
+        sorted.sort_by_key(|result| (result[..result.len() - 1].parse::<usize>().unwrap(), result.chars().last()));
+        assert_eq!(expected.into_iter().flat_map(|(_, results)| results).collect::<Vec<_>>(), sorted);
+    }
+
+    #[test]
+    fn a_single_job_gives_the_same_results() {
+        let test_files = (0..5).collect::<Vec<_>>();
+
+        assert_eq!(run_shuffled(&test_files, &['a', 'b'], 8).0, run_shuffled(&test_files, &['a', 'b'], 1).0);
+    }
+
+    #[test]
+    fn nothing_runs_without_configurations() {
+        let (callbacks, finished) = run_shuffled(&[0, 1], &[], 4);
+
+        assert!(callbacks.is_empty());
+        assert!(finished.is_empty());
+    }
+}
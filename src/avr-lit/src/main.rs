@@ -1,20 +1,28 @@
-use clap::{App, Arg};
-use std::collections::HashMap;
+mod compiler;
+mod configuration;
+mod differential;
+mod expression;
+mod jobs;
+mod project;
+mod report;
+mod run;
+mod test_file;
+mod toml;
+
+use clap::{App, Arg, ArgMatches};
 use std::path::{Path, PathBuf};
 use std::process;
+use std::time::Duration;
+
+use self::compiler::Compiler;
+use self::configuration::Configuration;
+use self::project::Project;
+use self::report::{Report, Reporter};
 
 const CRATE_PATH: &'static str = env!("CARGO_MANIFEST_DIR");
 
-/// Information about a compiler.
-#[derive(Debug)]
-struct Compiler {
-    /// The C compiler.
-    cc: PathBuf,
-    /// The C++ compiler.
-    cxx: PathBuf,
-    /// Flags to be passed to both C and C++ compilers.
-    compiler_flags: Vec<&'static str>,
-}
+/// The C++ standard that tests are compiled against if none is given on the command line.
+const DEFAULT_CXX_STANDARD: &str = "c++11";
 
 fn main() {
     let app = App::new("avr-lit")
@@ -27,8 +35,83 @@ fn main() {
         .arg(Arg::with_name("llvm-sysroot")
             .long("llvm-sysroot")
             .value_name("SYSROOT")
-            .help("Compile tests with an LLVM sysroot")
+            .help("Compile tests with an LLVM sysroot. Can be given more than once, and together with --avr-gcc, to run every test with each compiler")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("mcu")
+            .long("mcu")
+            .value_name("MCU")
+            .help("Compiles and simulates tests for an MCU. Can be given more than once to run every test on each MCU. Defaults to atmega328p")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("opt-levels")
+            .long("opt-levels")
+            .value_name("LEVELS")
+            .help("Compiles tests at each of a comma separated list of optimization levels, such as 'O0,O1,O2,Os,Oz'. Defaults to O0")
+            .takes_value(true)
+            .use_delimiter(true))
+        .arg(Arg::with_name("libavrlit")
+            .long("libavrlit")
+            .value_name("DIR")
+            .help("Sets the libavrlit directory that tests include headers from. Defaults to the one in this repository")
+            .takes_value(true))
+        .arg(Arg::with_name("c-std")
+            .long("c-std")
+            .value_name("STANDARD")
+            .help("Compiles C tests against a language standard, such as 'c11'. Defaults to the compiler's default")
+            .takes_value(true))
+        .arg(Arg::with_name("cxx-std")
+            .long("cxx-std")
+            .value_name("STANDARD")
+            .help("Compiles C++ tests against a language standard, such as 'c++17'. Defaults to c++11")
             .takes_value(true))
+        .arg(Arg::with_name("extra-cflags")
+            .long("extra-cflags")
+            .value_name("FLAGS")
+            .help("Adds flags to @cflags, which C tests are compiled with")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("extra-cxxflags")
+            .long("extra-cxxflags")
+            .value_name("FLAGS")
+            .help("Adds flags to @cxxflags, which C++ tests are compiled with")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("extra-ldflags")
+            .long("extra-ldflags")
+            .value_name("FLAGS")
+            .help("Adds flags to @ldflags, which every test is linked with")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("timeout")
+            .long("timeout")
+            .value_name("SECONDS")
+            .help("Fails a test if one of its RUN lines takes longer than this")
+            .takes_value(true))
+        .arg(Arg::with_name("jobs")
+            .short("j")
+            .long("jobs")
+            .value_name("N")
+            .help("Runs up to N tests at once. Defaults to the number of CPUs")
+            .takes_value(true))
+        .arg(Arg::with_name("report")
+            .long("report")
+            .value_name("FORMAT")
+            .help("Writes a report of every result once the tests have run. FORMAT is 'junit:<file>' for JUnit XML, or 'tap' or 'tap:<file>' for the Test Anything Protocol. A TAP report without a file is written to standard output")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("differential")
+            .long("differential")
+            .help("Compares the complete output of every test with the output when compiled with avr-gcc, or the first compiler if avr-gcc is not used. Any difference fails the test, even if no CHECK covers it"))
         .arg(Arg::with_name("TESTS")
             .help("Sets the tests to run")
             .required(false)
@@ -36,126 +119,259 @@ fn main() {
     let app = lit::config::clap::mount_inside_app(app, true);
     let matches = app.get_matches();
 
-    let avr_gcc_enabled = matches.is_present("avr-gcc");
-    let llvm_sysroot = matches.value_of("llvm-sysroot");
+    let tests_path = matches.value_of("TESTS").map(PathBuf::from).unwrap_or_else(|| {
+        // No tests explicitly passed, default to all.
+        repository_path().join("tests")
+    });
 
-    let compiler = match (avr_gcc_enabled, llvm_sysroot) {
-        // GCC enabled, no LLVM sysroot given
-        (true, None) => match self::gnu_tools() {
-            Some(compiler) => compiler,
-            None => {
-                eprintln!("error: cannot find avr-gcc in PATH");
+    // Settings in the project file apply unless they are overridden on the command line.
+    let project = match Project::find(&tests_path) {
+        Some(path) => Project::load(&path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }),
+        None => Project::default(),
+    };
+
+    let mut compilers = self::compilers(&project, matches.is_present("avr-gcc"), matches.values_of("llvm-sysroot").map(|v| v.collect()).unwrap_or_default());
+    let libavrlit_path = matches.value_of("libavrlit").map(PathBuf::from)
+        .or_else(|| project.libavrlit.clone())
+        .unwrap_or_else(|| repository_path().join("src/libavrlit"));
+    if !libavrlit_path.join("include/avrlit").is_dir() {
+        eprintln!("error: '{}' does not look like libavrlit, it has no 'include/avrlit' directory", libavrlit_path.display());
+        process::exit(1);
+    }
+    add_flags(&mut compilers, &libavrlit_path.join("include"), &project, &matches);
+
+    let mcus = match matches.values_of("mcu") {
+        Some(mcus) => mcus.map(ToOwned::to_owned).collect(),
+        None if !project.mcus.is_empty() => project.mcus.clone(),
+        None => vec![configuration::DEFAULT_MCU.to_owned()],
+    };
+    let opt_levels = match matches.values_of("opt-levels") {
+        Some(opt_levels) => opt_levels.map(configuration::parse_opt_level).collect::<Result<Vec<_>, _>>().unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }),
+        None if !project.opt_levels.is_empty() => project.opt_levels.clone(),
+        None => vec![configuration::DEFAULT_OPT_LEVEL.to_owned()],
+    };
+    let configurations = configuration::all(&compilers, &mcus, &opt_levels);
+    let differential = matches.is_present("differential");
+
+    if differential && compilers.len() < 2 {
+        eprintln!("error: differential testing needs at least two compilers to compare");
+        process::exit(1);
+    }
+
+    let timeout = match matches.value_of("timeout") {
+        Some(timeout) => match timeout.parse() {
+            Ok(seconds) if seconds > 0 => Some(Duration::from_secs(seconds)),
+            _ => {
+                eprintln!("error: invalid timeout '{}', expected a whole number of seconds", timeout);
                 process::exit(1);
             },
         },
-        // GCC not enabled, LLVM sysroot given
-        (false, Some(llvm_sysroot)) => {
-            match self::detect_compiler(Path::new(llvm_sysroot)) {
-                Some(compiler) => compiler,
-                None => {
-                    eprintln!("error: LLVM sysroot does not look like an LLVM sysroot");
-                    process::exit(1);
-                },
-            }
+        None => project.timeout,
+    };
+
+    let job_count = match matches.value_of("jobs") {
+        Some(jobs) => match jobs.parse() {
+            Ok(job_count) if job_count > 0 => job_count,
+            _ => {
+                eprintln!("error: invalid number of jobs '{}', expected a whole number above zero", jobs);
+                process::exit(1);
+            },
         },
-        (true, Some(..)) => {
-            eprintln!("error: cannot compile with both GCC and LLVM");
+        None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    };
+
+    let reports = matches.values_of("report").into_iter().flatten()
+        .map(str::parse::<Report>).collect::<Result<Vec<_>, _>>().unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
             process::exit(1);
-        },
-        (false, None) => {
-            eprintln!("error: either LLVM or GCC must be chosen");
+        });
+
+    let mut config = lit::Config::default();
+    config.add_search_path(tests_path.display().to_string());
+    config.add_extensions(&["c", "cpp"]);
+    // Inserted before parsing the arguments so that constants given on the command line win.
+    config.constants.insert("avr_sim".to_owned(), avr_sim_path().display().to_string());
+    config.constants.extend(project.constants.clone());
+    lit::config::clap::parse_arguments(&matches, &mut config);
+
+    let test_files = match test_file::find(&config.test_paths, &config.supported_file_extensions) {
+        Ok(test_files) => test_files,
+        Err(e) => {
+            eprintln!("error: could not find test files: {}", e);
             process::exit(1);
         },
     };
+    if test_files.is_empty() {
+        eprintln!("error: could not find any tests");
+        process::exit(1);
+    }
 
-    // Gets a value for config if supplied by user, or defaults to "default.conf"
+    let mut reporter = Reporter::new(configurations.clone(), config.always_show_stderr, reports);
+    reporter.on_test_suite_started(test_files.len());
 
-    lit::run::tests(lit::event_handler::Default::default(), |config| {
-        if let Some(tests_path) = matches.value_of("TESTS") {
-            config.add_search_path(tests_path);
-        } else {
-            // No tests explicitly passed, default to all.
-            config.add_search_path(format!("{}/tests", CRATE_PATH));
+    jobs::run(&test_files, &configurations, job_count, |test_file, configuration| {
+        let mut constants = config.constants.clone();
+        configuration.insert_constants(&mut constants);
+
+        let settings = run::Settings {
+            constants,
+            executable_search_paths: config.extra_executable_search_paths.clone(),
+            cleanup_temporary_files: config.cleanup_temporary_files,
+            context_lines: config.truncate_output_context_to_number_of_lines,
+            timeout,
+            features: configuration.features(),
+            expected_failure: project.is_expected_failure(&test_file.path, configuration),
+        };
+
+        run::test(test_file, &settings)
+    }, |test_file, mut results| {
+        if differential {
+            compare_with_reference(test_file, &configurations, &mut results);
         }
-        config.add_extensions(&["c", "cpp"]);
 
-        insert_constants(&mut config.constants, &compiler);
-        lit::config::clap::parse_arguments(&matches, config);
-    }).expect("failed tests");
+        for (configuration, result) in configurations.iter().zip(results) {
+            reporter.on_test_finished(test_file.relative_path.clone(), configuration, result);
+        }
+    });
+
+    if !reporter.on_test_suite_finished() {
+        process::exit(1);
+    }
 }
 
-fn insert_constants(constants: &mut HashMap<String, String>, compiler: &Compiler) {
-    constants.insert("cc".to_owned(),
-                     compiler.cc.display().to_string());
-    constants.insert("cflags".to_owned(), compiler.compiler_flags.join(" "));
+/// Marks every passing result whose output differs from the output with the reference compiler.
+///
+/// Results are only compared with results for the same MCU and optimization
+/// level. The reference is avr-gcc if it is used, because it is the more
+/// mature compiler, otherwise it is the first compiler.
+fn compare_with_reference(test_file: &test_file::TestFile, configurations: &[Configuration], results: &mut [run::TestResult]) {
+    let ignore = test_file.diff_ignore_patterns();
 
-    constants.insert("cxx".to_owned(),
-                     compiler.cxx.display().to_string());
-    constants.insert("cxxflags".to_owned(), compiler.compiler_flags.join(" "));
-}
+    for (first_index, first) in configurations.iter().enumerate() {
+        // Compare each group of configurations that only differ in compiler once, from its first member.
+        if configurations[..first_index].iter().any(|c| c.differs_only_in_compiler(first)) {
+            continue;
+        }
 
-fn gnu_tools() -> Option<Compiler> {
-    match util::find_in_path("avr-gcc") {
-        Some(cc_path) => {
-            let exec_dir = cc_path.parent().unwrap();
+        let indices = (0..configurations.len()).filter(|&i| configurations[i].differs_only_in_compiler(first)).collect::<Vec<_>>();
+        let reference_index = indices.iter().cloned().find(|&i| configurations[i].compiler.kind == compiler::CompilerKind::Gcc).unwrap_or(first_index);
+        let reference_name = &configurations[reference_index].name;
+        let reference = results[reference_index].clone();
 
-            Some(Compiler {
-                cc: cc_path.to_owned(),
-                cxx: exec_dir.join("avr-g++"),
-                compiler_flags: all_compiler_flags(&[]),
-            })
-        },
-        None => None,
+        // There is nothing to compare against if the program does not work with the reference compiler.
+        if reference.outcome != run::Outcome::Pass {
+            continue;
+        }
+
+        for index in indices {
+            let result = &mut results[index];
+            if index == reference_index || result.outcome != run::Outcome::Pass {
+                continue;
+            }
+
+            if let Some(differences) = differential::compare(reference_name, &reference, result, &ignore) {
+                result.outcome = run::Outcome::Diverged(run::Failure {
+                    summary: format!("output differs from the output with {}", reference_name),
+                    details: differences,
+                });
+            }
+        }
     }
 }
 
-fn all_compiler_flags(other_flags: &[&'static str]) -> Vec<&'static str> {
-    let mut flags = vec![
-        "-mmcu=atmega328p",
-        "-Isrc/libavrlit/include",
-        "-std=c++11",
-        "-ffunction-sections",
-        "-g",
-    ];
-
-    flags.extend(other_flags);
-    flags
+/// Gets the root of the repository that avr-lit was built from.
+fn repository_path() -> PathBuf {
+    // avr-lit lives in 'src/avr-lit'.
+    Path::new(CRATE_PATH).parent().and_then(Path::parent).unwrap().to_owned()
 }
 
-fn detect_compiler(sysroot: &Path) -> Option<Compiler> {
-    let bin_dir = sysroot.join("bin");
-
-    if bin_dir.join("clang").exists() {
-        Some(Compiler {
-            cc: bin_dir.join("clang"),
-            cxx: bin_dir.join("clang++"),
-            compiler_flags: all_compiler_flags(&[
-                "-target", "avr-unknown-unknown", "-O",
-            ]),
-        })
-    } else {
-        None
+/// Finds the avr-sim that was built alongside avr-lit, so that tests use the
+/// matching simulator. Falls back to the one in `PATH`.
+fn avr_sim_path() -> PathBuf {
+    let file_name = format!("avr-sim{}", std::env::consts::EXE_SUFFIX);
+
+    match std::env::current_exe() {
+        Ok(path) if path.with_file_name(&file_name).is_file() => path.with_file_name(&file_name),
+        _ => PathBuf::from(file_name),
     }
 }
 
-mod util {
-    use std::path::{Path, PathBuf};
-    use std::env;
-
-    // Adapted from
-    // https://stackoverflow.com/a/37499032
-    pub fn find_in_path<P>(exe_name: P) -> Option<PathBuf>
-        where P: AsRef<Path> {
-        env::var_os("PATH").and_then(|paths| {
-            env::split_paths(&paths).filter_map(|dir| {
-                let full_path = dir.join(&exe_name);
-                if full_path.is_file() {
-                    Some(full_path)
-                } else {
-                    None
-                }
-            }).next()
-        })
+/// Adds the libavrlit headers, language standards and extra flags to every
+/// compiler. Flags from the command line come after flags from the project
+/// file, so that they take precedence.
+fn add_flags(compilers: &mut [Compiler], libavrlit_include_path: &Path, project: &Project, matches: &ArgMatches) {
+    let flags = |name: &str| -> Vec<String> {
+        matches.values_of(name).map(|values| values.flat_map(str::split_whitespace).map(ToOwned::to_owned).collect()).unwrap_or_default()
+    };
+
+    for compiler in compilers.iter_mut() {
+        compiler.compiler_flags.insert(0, format!("-I{}", libavrlit_include_path.display()));
+
+        if let Some(c_standard) = matches.value_of("c-std").or(project.c_std.as_deref()) {
+            compiler.c_flags.push(format!("-std={}", c_standard));
+        }
+        let cxx_standard = matches.value_of("cxx-std").or(project.cxx_std.as_deref()).unwrap_or(DEFAULT_CXX_STANDARD);
+        compiler.cxx_flags.push(format!("-std={}", cxx_standard));
+
+        compiler.c_flags.extend(project.c_flags.iter().cloned());
+        compiler.cxx_flags.extend(project.cxx_flags.iter().cloned());
+        compiler.linker_flags.extend(project.linker_flags.iter().cloned());
+
+        compiler.c_flags.extend(flags("extra-cflags"));
+        compiler.cxx_flags.extend(flags("extra-cxxflags"));
+        compiler.linker_flags.extend(flags("extra-ldflags"));
     }
 }
 
+/// Finds every compiler that was asked for on the command line, or declared
+/// in the project file if none were.
+fn compilers(project: &Project, avr_gcc_enabled: bool, llvm_sysroots: Vec<&str>) -> Vec<Compiler> {
+    let mut compilers = Vec::new();
+
+    if !avr_gcc_enabled && llvm_sysroots.is_empty() && !project.compilers.is_empty() {
+        return project.find_compilers().unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        });
+    }
+
+    if avr_gcc_enabled {
+        match compiler::gnu_tools(None, "gcc".to_owned()) {
+            Some(compiler) => compilers.push(compiler),
+            None => {
+                eprintln!("error: cannot find avr-gcc in PATH");
+                process::exit(1);
+            },
+        }
+    }
+
+    for llvm_sysroot in llvm_sysroots.iter() {
+        // Several LLVM builds are told apart by the name of their sysroot directory.
+        let name = if llvm_sysroots.len() == 1 {
+            "llvm".to_owned()
+        } else {
+            format!("llvm:{}", Path::new(llvm_sysroot).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default())
+        };
+
+        match compiler::detect_compiler(Path::new(llvm_sysroot), name) {
+            Some(compiler) => compilers.push(compiler),
+            None => {
+                eprintln!("error: LLVM sysroot '{}' does not look like an LLVM sysroot", llvm_sysroot);
+                process::exit(1);
+            },
+        }
+    }
+
+    if compilers.is_empty() {
+        eprintln!("error: either LLVM or GCC must be chosen, or declared in {}", project::FILE_NAME);
+        process::exit(1);
+    }
+
+    compilers
+}
@@ -0,0 +1,292 @@
+//! Settings shared by everyone who runs a test suite, from an `avr-lit.toml`
+//! file at the root of the tests.
+//!
+//! ```toml
+//! mcus = ["atmega328p", "atmega2560"]
+//! opt-levels = ["O0", "Os"]
+//! cxx-std = "c++17"
+//! cflags = ["-Wall"]
+//! # The longest a single RUN line may take, in seconds.
+//! timeout = 60
+//!
+//! [constants]
+//! board = "uno"
+//!
+//! [[compiler]]
+//! name = "gcc"
+//! kind = "gcc"
+//! # The directory containing avr-gcc. Defaults to searching PATH.
+//! path = "/opt/avr-gcc/bin"
+//!
+//! [[compiler]]
+//! name = "llvm"
+//! kind = "llvm"
+//! # The LLVM sysroot.
+//! path = "/opt/llvm"
+//! cxxflags = ["-fno-exceptions"]
+//!
+//! [[expected-failure]]
+//! tests = ["unit/bugfix/misc-D78439-fix-zext-add-miscompilation.c"]
+//! compiler = "llvm"
+//! opt-level = "Os"
+//! ```
+//!
+//! Relative paths are relative to the directory of the file.
+
+use crate::compiler::{self, Compiler, CompilerKind};
+use crate::configuration::{self, Configuration};
+use crate::toml::{self, Table, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The name of the file that settings are read from.
+pub const FILE_NAME: &str = "avr-lit.toml";
+
+/// The settings in an `avr-lit.toml` file.
+#[derive(Clone, Debug, Default)]
+pub struct Project {
+    pub compilers: Vec<CompilerSettings>,
+    pub mcus: Vec<String>,
+    pub opt_levels: Vec<String>,
+    pub c_std: Option<String>,
+    pub cxx_std: Option<String>,
+    /// Flags added to every C compiler.
+    pub c_flags: Vec<String>,
+    /// Flags added to every C++ compiler.
+    pub cxx_flags: Vec<String>,
+    /// Flags added to every link.
+    pub linker_flags: Vec<String>,
+    pub libavrlit: Option<PathBuf>,
+    /// Values for `@name` constants in RUN lines.
+    pub constants: BTreeMap<String, String>,
+    /// The longest a single RUN line may take.
+    pub timeout: Option<Duration>,
+    pub expected_failures: Vec<ExpectedFailure>,
+}
+
+/// A compiler declared with `[[compiler]]`.
+#[derive(Clone, Debug)]
+pub struct CompilerSettings {
+    /// Identifies the compiler in reports.
+    pub name: String,
+    pub kind: CompilerKind,
+    /// The directory containing avr-gcc, or the LLVM sysroot.
+    pub path: Option<PathBuf>,
+    /// Flags passed to both the C and C++ compilers.
+    pub flags: Vec<String>,
+    pub c_flags: Vec<String>,
+    pub cxx_flags: Vec<String>,
+    pub linker_flags: Vec<String>,
+}
+
+/// Tests that are known to fail, declared with `[[expected-failure]]`.
+///
+/// The test fails as if it was marked with `XFAIL`, but only in the
+/// configurations that match every setting that is given.
+#[derive(Clone, Debug)]
+pub struct ExpectedFailure {
+    pub tests: Vec<PathBuf>,
+    pub compiler: Option<String>,
+    pub mcu: Option<String>,
+    pub opt_level: Option<String>,
+}
+
+impl Project {
+    /// Finds the `avr-lit.toml` that applies to a test path, by looking in
+    /// the path itself and then in every directory above it.
+    pub fn find(test_path: &Path) -> Option<PathBuf> {
+        let test_path = test_path.canonicalize().ok()?;
+        let first_directory = if test_path.is_dir() { Some(test_path.as_path()) } else { test_path.parent() };
+
+        first_directory?.ancestors().map(|directory| directory.join(FILE_NAME)).find(|path| path.is_file())
+    }
+
+    /// Reads and validates an `avr-lit.toml` file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+        let table = toml::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new("."));
+
+        Project::from_table(table, directory).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_table(mut table: Table, directory: &Path) -> Result<Self, String> {
+        let project = Project {
+            compilers: take_tables(&mut table, "compiler")?.into_iter()
+                .map(|table| CompilerSettings::from_table(table, directory)).collect::<Result<_, _>>()?,
+            mcus: take_strings(&mut table, "mcus")?.unwrap_or_default(),
+            opt_levels: take_strings(&mut table, "opt-levels")?.unwrap_or_default().iter()
+                .map(|opt_level| configuration::parse_opt_level(opt_level)).collect::<Result<_, _>>()?,
+            c_std: take_string(&mut table, "c-std")?,
+            cxx_std: take_string(&mut table, "cxx-std")?,
+            c_flags: take_strings(&mut table, "cflags")?.unwrap_or_default(),
+            cxx_flags: take_strings(&mut table, "cxxflags")?.unwrap_or_default(),
+            linker_flags: take_strings(&mut table, "ldflags")?.unwrap_or_default(),
+            libavrlit: take_string(&mut table, "libavrlit")?.map(|path| directory.join(path)),
+            constants: match table.remove("constants") {
+                Some(Value::Table(constants)) => constants.into_iter().map(|(name, value)| match value {
+                    Value::String(value) => Ok((name, value)),
+                    Value::Integer(value) => Ok((name, value.to_string())),
+                    value => Err(format!("constant '{}' must be a string, not {}", name, value.type_name())),
+                }).collect::<Result<_, _>>()?,
+                Some(value) => return Err(format!("'constants' must be a table, not {}", value.type_name())),
+                None => BTreeMap::new(),
+            },
+            timeout: match table.remove("timeout") {
+                Some(Value::Integer(seconds)) if seconds > 0 => Some(Duration::from_secs(seconds as u64)),
+                Some(Value::Integer(seconds)) => return Err(format!("'timeout' must be a positive number of seconds, not {}", seconds)),
+                Some(value) => return Err(format!("'timeout' must be a positive number of seconds, not {}", value.type_name())),
+                None => None,
+            },
+            expected_failures: take_tables(&mut table, "expected-failure")?.into_iter()
+                .map(|table| ExpectedFailure::from_table(table, directory)).collect::<Result<_, _>>()?,
+        };
+
+        no_unknown_keys(&table, "")?;
+        Ok(project)
+    }
+
+    /// Finds every compiler declared in the file.
+    pub fn find_compilers(&self) -> Result<Vec<Compiler>, String> {
+        self.compilers.iter().map(|settings| {
+            let compiler = match settings.kind {
+                CompilerKind::Gcc => compiler::gnu_tools(settings.path.as_deref(), settings.name.clone()),
+                CompilerKind::Llvm => compiler::detect_compiler(settings.path.as_deref().unwrap(), settings.name.clone()),
+            };
+
+            let mut compiler = compiler.ok_or_else(|| match settings.path {
+                Some(ref path) => format!("cannot find compiler '{}' in '{}'", settings.name, path.display()),
+                None => format!("cannot find compiler '{}' in PATH", settings.name),
+            })?;
+
+            compiler.compiler_flags.extend(settings.flags.iter().cloned());
+            compiler.c_flags.extend(settings.c_flags.iter().cloned());
+            compiler.cxx_flags.extend(settings.cxx_flags.iter().cloned());
+            compiler.linker_flags.extend(settings.linker_flags.iter().cloned());
+            Ok(compiler)
+        }).collect()
+    }
+
+    /// Checks if the file expects a test to fail with a configuration.
+    pub fn is_expected_failure(&self, test_path: &Path, configuration: &Configuration) -> bool {
+        let test_path = match test_path.canonicalize() {
+            Ok(path) => path,
+            Err(..) => return false,
+        };
+
+        self.expected_failures.iter().any(|expected_failure| expected_failure.matches(&test_path, configuration))
+    }
+}
+
+impl CompilerSettings {
+    fn from_table(mut table: Table, directory: &Path) -> Result<Self, String> {
+        let name = take_string(&mut table, "name")?.ok_or("every [[compiler]] needs a 'name'")?;
+        let kind = match take_string(&mut table, "kind")?.as_deref() {
+            Some("gcc") => CompilerKind::Gcc,
+            Some("llvm") => CompilerKind::Llvm,
+            Some(kind) => return Err(format!("compiler '{}' has unknown kind '{}', expected 'gcc' or 'llvm'", name, kind)),
+            None => return Err(format!("compiler '{}' needs a 'kind' of 'gcc' or 'llvm'", name)),
+        };
+        let path = take_string(&mut table, "path")?.map(|path| directory.join(path));
+
+        if kind == CompilerKind::Llvm && path.is_none() {
+            return Err(format!("compiler '{}' needs a 'path' to its LLVM sysroot", name));
+        }
+
+        let settings = CompilerSettings {
+            flags: take_strings(&mut table, "flags")?.unwrap_or_default(),
+            c_flags: take_strings(&mut table, "cflags")?.unwrap_or_default(),
+            cxx_flags: take_strings(&mut table, "cxxflags")?.unwrap_or_default(),
+            linker_flags: take_strings(&mut table, "ldflags")?.unwrap_or_default(),
+            name, kind, path,
+        };
+
+        no_unknown_keys(&table, "compiler.")?;
+        Ok(settings)
+    }
+}
+
+impl ExpectedFailure {
+    fn from_table(mut table: Table, directory: &Path) -> Result<Self, String> {
+        let tests = take_strings(&mut table, "tests")?.ok_or("every [[expected-failure]] needs a list of 'tests'")?;
+
+        let expected_failure = ExpectedFailure {
+            // Resolved now so that tests match however they are found.
+            tests: tests.iter().map(|test| {
+                let path = directory.join(test);
+                path.canonicalize().map_err(|e| format!("expected failure '{}' cannot be found: {}", test, e))
+            }).collect::<Result<_, _>>()?,
+            compiler: take_string(&mut table, "compiler")?,
+            mcu: take_string(&mut table, "mcu")?,
+            opt_level: take_string(&mut table, "opt-level")?.map(|opt_level| configuration::parse_opt_level(&opt_level)).transpose()?,
+        };
+
+        no_unknown_keys(&table, "expected-failure.")?;
+        Ok(expected_failure)
+    }
+
+    fn matches(&self, test_path: &Path, configuration: &Configuration) -> bool {
+        let setting_matches = |setting: &Option<String>, value: &str| setting.as_ref().map(|s| s == value).unwrap_or(true);
+
+        self.tests.iter().any(|path| path == test_path) &&
+            setting_matches(&self.compiler, &configuration.compiler.name) &&
+            setting_matches(&self.mcu, &configuration.mcu) &&
+            setting_matches(&self.opt_level, &configuration.opt_level)
+    }
+}
+
+fn take_string(table: &mut Table, key: &str) -> Result<Option<String>, String> {
+    match table.remove(key) {
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(value) => Err(format!("'{}' must be a string, not {}", key, value.type_name())),
+        None => Ok(None),
+    }
+}
+
+fn take_strings(table: &mut Table, key: &str) -> Result<Option<Vec<String>>, String> {
+    match table.remove(key) {
+        Some(Value::Array(values)) => values.into_iter().map(|value| match value {
+            Value::String(value) => Ok(value),
+            value => Err(format!("'{}' must only contain strings, not {}", key, value.type_name())),
+        }).collect::<Result<_, _>>().map(Some),
+        Some(value) => Err(format!("'{}' must be an array of strings, not {}", key, value.type_name())),
+        None => Ok(None),
+    }
+}
+
+fn take_tables(table: &mut Table, key: &str) -> Result<Vec<Table>, String> {
+    match table.remove(key) {
+        Some(Value::Array(values)) => values.into_iter().map(|value| match value {
+            Value::Table(table) => Ok(table),
+            value => Err(format!("'{}' must only contain tables, not {}", key, value.type_name())),
+        }).collect(),
+        Some(value) => Err(format!("'{}' must be declared with [[{}]], not as {}", key, key, value.type_name())),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Rejects keys that were not taken out of a table, which are usually typos.
+fn no_unknown_keys(table: &Table, prefix: &str) -> Result<(), String> {
+    match table.keys().next() {
+        Some(key) => Err(format!("unknown setting '{}{}'", prefix, key)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn project(text: &str) -> Result<Project, String> {
+        Project::from_table(toml::parse(text).unwrap(), Path::new("/project"))
+    }
+
+    #[test]
+    fn timeouts_must_be_positive() {
+        assert_eq!(Some(Duration::from_secs(30)), project("timeout = 30").unwrap().timeout);
+        assert_eq!(Some("'timeout' must be a positive number of seconds, not 0".to_owned()), project("timeout = 0").err());
+        assert_eq!(Some("'timeout' must be a positive number of seconds, not -5".to_owned()), project("timeout = -5").err());
+        assert_eq!(Some("'timeout' must be a positive number of seconds, not a string".to_owned()), project("timeout = '30'").err());
+    }
+}
@@ -0,0 +1,264 @@
+//! Showing test results to the user.
+
+mod junit;
+mod tap;
+
+use crate::configuration::Configuration;
+use crate::run::{Outcome, TestResult};
+use std::path::PathBuf;
+
+/// Prints a line of the human readable output.
+macro_rules! terminalln {
+    ($reporter:expr) => { $reporter.write_line(String::new()) };
+    ($reporter:expr, $($arg:tt)*) => { $reporter.write_line(format!($($arg)*)) };
+}
+
+/// A machine readable report that is written once every test has run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Report {
+    /// JUnit XML, for CI servers.
+    JUnit(PathBuf),
+    /// The Test Anything Protocol, written to standard output if no file is given.
+    Tap(Option<PathBuf>),
+}
+
+/// The result of running a test file with a configuration.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub test_path: PathBuf,
+    pub configuration: Configuration,
+    pub result: TestResult,
+}
+
+/// Prints each result as it comes in, and a matrix of every result at the end.
+pub struct Reporter {
+    configurations: Vec<Configuration>,
+    always_show_stderr: bool,
+    reports: Vec<Report>,
+    entries: Vec<Entry>,
+}
+
+impl Reporter {
+    pub fn new(configurations: Vec<Configuration>, always_show_stderr: bool, reports: Vec<Report>) -> Self {
+        Reporter { configurations, always_show_stderr, reports, entries: Vec::new() }
+    }
+
+    pub fn on_test_suite_started(&self, number_of_test_files: usize) {
+        let names = self.configurations.iter().map(|c| &c.name[..]).collect::<Vec<_>>();
+
+        terminalln!(self);
+        terminalln!(self, "Running tests ({} files, {} configurations: {})", number_of_test_files, names.len(), names.join(", "));
+        terminalln!(self);
+    }
+
+    pub fn on_test_finished(&mut self, test_path: PathBuf, configuration: &Configuration, result: TestResult) {
+        terminalln!(self, "{} :: {} ({})", result.outcome.label(), test_path.display(), configuration.name);
+
+        match result.outcome {
+            Outcome::Fail(ref failure) | Outcome::Diverged(ref failure) => {
+                terminalln!(self);
+                terminalln!(self, "test failed: {}", failure.summary);
+                terminalln!(self);
+                terminalln!(self, "{}", failure.details);
+                terminalln!(self);
+            },
+            Outcome::Error(ref message) => {
+                terminalln!(self);
+                terminalln!(self, "{}", message);
+                terminalln!(self);
+            },
+            Outcome::UnexpectedPass => {
+                terminalln!(self);
+                terminalln!(self, "test passed, but it is expected to fail with this configuration");
+                terminalln!(self);
+            },
+            Outcome::Unsupported(ref reason) => terminalln!(self, "  {}", reason),
+            Outcome::Pass | Outcome::ExpectedFailure(..) | Outcome::Skip => (),
+        }
+
+        if self.always_show_stderr || result.outcome.is_erroneous() {
+            for run in result.runs.iter().filter(|run| !run.stderr.trim().is_empty()) {
+                terminalln!(self, "stderr of '{}':", run.command_line);
+                terminalln!(self, "{}", run.stderr.trim_end());
+                terminalln!(self);
+            }
+        }
+
+        self.entries.push(Entry { test_path, configuration: configuration.clone(), result });
+    }
+
+    /// Prints every result in a table with a column per configuration, and
+    /// writes the machine readable reports.
+    ///
+    /// Returns `true` if there were no failures and every report was written.
+    pub fn on_test_suite_finished(&self) -> bool {
+        let mut test_paths: Vec<&PathBuf> = Vec::new();
+        for entry in self.entries.iter() {
+            if !test_paths.contains(&&entry.test_path) {
+                test_paths.push(&entry.test_path);
+            }
+        }
+
+        let name_width = test_paths.iter().map(|p| p.display().to_string().len()).max().unwrap_or(0);
+        let column_widths = self.configurations.iter().map(|c| c.name.len().max("UNSUPPORTED".len())).collect::<Vec<_>>();
+
+        terminalln!(self);
+        terminalln!(self, "finished running tests");
+        terminalln!(self);
+
+        let mut header = format!("  {:width$}", "", width = name_width);
+        for (configuration, width) in self.configurations.iter().zip(column_widths.iter()) {
+            header += &format!("  {:width$}", configuration.name, width = width);
+        }
+        terminalln!(self, "{}", header.trim_end());
+
+        for test_path in test_paths.iter() {
+            let mut row = format!("  {:width$}", test_path.display().to_string(), width = name_width);
+
+            for (configuration, width) in self.configurations.iter().zip(column_widths.iter()) {
+                let label = self.result(test_path, &configuration.name).map(|r| r.outcome.label()).unwrap_or("-");
+                row += &format!("  {:width$}", label, width = width);
+            }
+            terminalln!(self, "{}", row.trim_end());
+        }
+
+        terminalln!(self);
+        for configuration in self.configurations.iter() {
+            let results = self.entries.iter().filter(|e| e.configuration.name == configuration.name).map(|e| &e.result).collect::<Vec<_>>();
+            let count = |label: &str| results.iter().filter(|r| r.outcome.label() == label).count();
+
+            terminalln!(self, "{}: {} passed, {} failed, {} diverged, {} errors, {} expected failures, {} unexpected passes, {} unsupported, {} skipped",
+                        configuration.name, count("PASS"), count("FAIL"), count("DIFF"), count("ERROR"), count("XFAIL"), count("XPASS"), count("UNSUPPORTED"), count("SKIP"));
+        }
+
+        let failed_entries = self.entries.iter().filter(|e| e.result.outcome.is_erroneous()).collect::<Vec<_>>();
+
+        if failed_entries.is_empty() {
+            terminalln!(self);
+            terminalln!(self, "all tests passed");
+        } else {
+            terminalln!(self);
+            terminalln!(self, "Failing tests ({}/{}):", failed_entries.len(), self.entries.len());
+            for entry in failed_entries.iter() {
+                terminalln!(self, "  {} :: {} ({})", entry.result.outcome.label(), entry.test_path.display(), entry.configuration.name);
+            }
+        }
+
+        let mut reports_written = true;
+        for report in self.reports.iter() {
+            if let Err(e) = self.write_report(report) {
+                eprintln!("error: could not write the {} report: {}", report.format_name(), e);
+                reports_written = false;
+            }
+        }
+
+        failed_entries.is_empty() && reports_written
+    }
+
+    fn write_report(&self, report: &Report) -> std::io::Result<()> {
+        match *report {
+            Report::JUnit(ref path) => junit::write(&self.configurations, &self.entries, &mut std::fs::File::create(path)?),
+            Report::Tap(Some(ref path)) => tap::write(&self.entries, &mut std::fs::File::create(path)?),
+            Report::Tap(None) => tap::write(&self.entries, &mut std::io::stdout()),
+        }
+    }
+
+    fn result(&self, test_path: &PathBuf, configuration_name: &str) -> Option<&TestResult> {
+        self.entries.iter().find(|e| &e.test_path == test_path && e.configuration.name == configuration_name).map(|e| &e.result)
+    }
+
+    /// Prints a line of human readable output. It goes to standard error if
+    /// standard output is reserved for a TAP report.
+    fn write_line(&self, line: String) {
+        if self.reports.contains(&Report::Tap(None)) {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+impl Report {
+    fn format_name(&self) -> &'static str {
+        match *self {
+            Report::JUnit(..) => "JUnit",
+            Report::Tap(..) => "TAP",
+        }
+    }
+}
+
+impl std::str::FromStr for Report {
+    type Err = String;
+
+    /// Parses a report such as `junit:results.xml`, `tap` or `tap:results.tap`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (format, path) = match s.find(':') {
+            Some(colon) => (&s[..colon], Some(PathBuf::from(&s[colon + 1..]))),
+            None => (s, None),
+        };
+
+        match (format, path) {
+            ("junit", Some(path)) => Ok(Report::JUnit(path)),
+            ("junit", None) => Err("the JUnit report needs a file, such as 'junit:results.xml'".to_owned()),
+            ("tap", path) => Ok(Report::Tap(path)),
+            _ => Err(format!("unknown report '{}', expected 'junit:<file>', 'tap' or 'tap:<file>'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::{Compiler, CompilerKind};
+    use crate::run::{Failure, RunOutput};
+    use std::time::Duration;
+
+    pub fn configuration(name: &str) -> Configuration {
+        Configuration {
+            name: name.to_owned(),
+            compiler: Compiler {
+                name: name.to_owned(),
+                kind: CompilerKind::Gcc,
+                cc: PathBuf::from("avr-gcc"),
+                cxx: PathBuf::from("avr-g++"),
+                compiler_flags: Vec::new(),
+                c_flags: Vec::new(),
+                cxx_flags: Vec::new(),
+                linker_flags: Vec::new(),
+            },
+            mcu: "atmega328p".to_owned(),
+            opt_level: "O2".to_owned(),
+        }
+    }
+
+    pub fn failure(summary: &str, details: &str) -> Failure {
+        Failure { summary: summary.to_owned(), details: details.to_owned() }
+    }
+
+    pub fn entry(test_path: &str, configuration_name: &str, outcome: Outcome) -> Entry {
+        Entry {
+            test_path: PathBuf::from(test_path),
+            configuration: configuration(configuration_name),
+            result: TestResult {
+                outcome,
+                runs: vec![RunOutput {
+                    command_line: "avr-sim test.elf".to_owned(),
+                    stdout: "OUTPUT_VALUE = 7\n".to_owned(),
+                    stderr: String::new(),
+                    exit_code: Some(0),
+                    timed_out: false,
+                }],
+                duration: Duration::from_millis(1500),
+            },
+        }
+    }
+
+    #[test]
+    fn can_parse_reports() {
+        assert_eq!(Ok(Report::JUnit(PathBuf::from("results.xml"))), "junit:results.xml".parse());
+        assert_eq!(Ok(Report::Tap(None)), "tap".parse());
+        assert_eq!(Ok(Report::Tap(Some(PathBuf::from("results.tap")))), "tap:results.tap".parse());
+        assert!("junit".parse::<Report>().is_err());
+        assert!("html:results.html".parse::<Report>().is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Writing results as JUnit XML, which most CI servers can show.
+//!
+//! Every configuration is a `<testsuite>` and every test file in it is a
+//! `<testcase>`. Expected failures, unsupported tests and tests without `RUN`
+//! lines are marked as skipped.
+
+use super::Entry;
+use crate::configuration::Configuration;
+use crate::run::Outcome;
+use std::io::{self, Write};
+
+pub fn write(configurations: &[Configuration], entries: &[Entry], out: &mut dyn Write) -> io::Result<()> {
+    let count = |entries: &[&Entry], matches: fn(&Outcome) -> bool| entries.iter().filter(|e| matches(&e.result.outcome)).count();
+    let seconds = |entries: &[&Entry]| entries.iter().map(|e| e.result.duration.as_secs_f64()).sum::<f64>();
+
+    let all_entries = entries.iter().collect::<Vec<_>>();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<testsuites name="avr-lit" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+             all_entries.len(), count(&all_entries, is_failure), count(&all_entries, is_error), count(&all_entries, is_skipped), seconds(&all_entries))?;
+
+    for configuration in configurations {
+        let entries = entries.iter().filter(|e| e.configuration.name == configuration.name).collect::<Vec<_>>();
+
+        writeln!(out, r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" skipped="{}" time="{:.3}">"#,
+                 escape(&configuration.name), entries.len(), count(&entries, is_failure), count(&entries, is_error), count(&entries, is_skipped), seconds(&entries))?;
+        writeln!(out, "    <properties>")?;
+        writeln!(out, r#"      <property name="compiler" value="{}"/>"#, escape(&configuration.compiler.name))?;
+        writeln!(out, r#"      <property name="mcu" value="{}"/>"#, escape(&configuration.mcu))?;
+        writeln!(out, r#"      <property name="opt-level" value="{}"/>"#, escape(&configuration.opt_level))?;
+        writeln!(out, "    </properties>")?;
+
+        for entry in entries {
+            write_test_case(entry, out)?;
+        }
+
+        writeln!(out, "  </testsuite>")?;
+    }
+
+    writeln!(out, "</testsuites>")
+}
+
+fn write_test_case(entry: &Entry, out: &mut dyn Write) -> io::Result<()> {
+    let result = &entry.result;
+
+    writeln!(out, r#"    <testcase name="{}" classname="{}" time="{:.3}">"#,
+             escape(&entry.test_path.display().to_string()), escape(&entry.configuration.name), result.duration.as_secs_f64())?;
+
+    match result.outcome {
+        Outcome::Fail(ref failure) | Outcome::Diverged(ref failure) => {
+            writeln!(out, r#"      <failure message="{}" type="{}">{}</failure>"#, escape(&failure.summary), result.outcome.label(), escape(&failure.details))?;
+        },
+        Outcome::UnexpectedPass => {
+            writeln!(out, r#"      <failure message="test passed, but it is expected to fail with this configuration" type="XPASS"/>"#)?;
+        },
+        Outcome::Error(ref message) => {
+            writeln!(out, r#"      <error message="{}">{}</error>"#, escape(message.lines().next().unwrap_or("")), escape(message))?;
+        },
+        Outcome::ExpectedFailure(ref failure) => {
+            writeln!(out, r#"      <skipped message="expected failure: {}"/>"#, escape(&failure.summary))?;
+        },
+        Outcome::Unsupported(ref reason) => writeln!(out, r#"      <skipped message="{}"/>"#, escape(reason))?,
+        Outcome::Skip => writeln!(out, r#"      <skipped message="the test has no RUN lines"/>"#)?,
+        Outcome::Pass => (),
+    }
+
+    if !result.runs.is_empty() {
+        let stdout = result.runs.iter().map(|run| format!("$ {}\n{}", run.command_line, run.stdout)).collect::<Vec<_>>().join("\n");
+        writeln!(out, "      <system-out>{}</system-out>", escape(&stdout))?;
+
+        let stderr = result.runs.iter().filter(|run| !run.stderr.is_empty()).map(|run| format!("$ {}\n{}", run.command_line, run.stderr)).collect::<Vec<_>>().join("\n");
+        if !stderr.is_empty() {
+            writeln!(out, "      <system-err>{}</system-err>", escape(&stderr))?;
+        }
+    }
+
+    writeln!(out, "    </testcase>")
+}
+
+fn is_failure(outcome: &Outcome) -> bool {
+    matches!(*outcome, Outcome::Fail(..) | Outcome::Diverged(..) | Outcome::UnexpectedPass)
+}
+
+fn is_error(outcome: &Outcome) -> bool {
+    matches!(*outcome, Outcome::Error(..))
+}
+
+fn is_skipped(outcome: &Outcome) -> bool {
+    matches!(*outcome, Outcome::ExpectedFailure(..) | Outcome::Unsupported(..) | Outcome::Skip)
+}
+
+/// Escapes text for use in XML, dropping control characters that XML cannot represent.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => (),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::test::{configuration, entry, failure};
+
+    fn junit(configurations: &[Configuration], entries: &[Entry]) -> String {
+        let mut out = Vec::new();
+        write(configurations, entries, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn escapes_markup_characters() {
+        assert_eq!("a &lt;b&gt; &amp; &quot;c&quot; &apos;d&apos;", escape(r#"a <b> & "c" 'd'"#));
+    }
+
+    #[test]
+    fn drops_control_characters_other_than_whitespace() {
+        assert_eq!("[31mred\ttext\r\n", escape("\u{1b}[31mred\u{0}\ttext\u{7}\r\n"));
+        assert_eq!("", escape("\u{1b}\u{1f}"));
+        assert_eq!("über", escape("über"));
+    }
+
+    #[test]
+    fn counts_outcomes_per_configuration() {
+        let entries = [
+            entry("a.c", "gcc", Outcome::Pass),
+            entry("b.c", "gcc", Outcome::Fail(failure("exited with code 1", "details"))),
+            entry("a.c", "llvm", Outcome::Error("could not run".to_owned())),
+            entry("b.c", "llvm", Outcome::Unsupported("REQUIRES".to_owned())),
+        ];
+        let xml = junit(&[configuration("gcc"), configuration("llvm")], &entries);
+
+        assert!(xml.contains(r#"<testsuites name="avr-lit" tests="4" failures="1" errors="1" skipped="1" time="6.000">"#));
+        assert!(xml.contains(r#"<testsuite name="gcc" tests="2" failures="1" errors="0" skipped="0" time="3.000">"#));
+        assert!(xml.contains(r#"<testsuite name="llvm" tests="2" failures="0" errors="1" skipped="1" time="3.000">"#));
+    }
+
+    #[test]
+    fn escapes_failure_details() {
+        let entries = [entry("a<b>.c", "gcc", Outcome::Fail(failure("expected \"x\"", "found <y>\u{1b}[0m & more")))];
+        let xml = junit(&[configuration("gcc")], &entries);
+
+        assert!(xml.contains(r#"<testcase name="a&lt;b&gt;.c" classname="gcc" time="1.500">"#));
+        assert!(xml.contains(r#"<failure message="expected &quot;x&quot;" type="FAIL">found &lt;y&gt;[0m &amp; more</failure>"#));
+        assert!(!xml.contains('\u{1b}'));
+    }
+}
@@ -0,0 +1,135 @@
+//! Writing results in the Test Anything Protocol, version 13.
+//!
+//! Every test file and configuration is a test point. Details are given in a
+//! YAML block below each test point.
+
+use super::Entry;
+use crate::run::Outcome;
+use std::io::{self, Write};
+
+pub fn write(entries: &[Entry], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "TAP version 13")?;
+    writeln!(out, "1..{}", entries.len())?;
+
+    for (index, entry) in entries.iter().enumerate() {
+        let result = &entry.result;
+        let description = format!("{} ({})", entry.test_path.display(), entry.configuration.name);
+
+        // Expected failures use TAP's TODO directive, which does not fail the run.
+        match result.outcome {
+            Outcome::Pass => writeln!(out, "ok {} - {}", index + 1, description)?,
+            Outcome::ExpectedFailure(..) => writeln!(out, "not ok {} - {} # TODO expected failure", index + 1, description)?,
+            Outcome::Unsupported(ref reason) => writeln!(out, "ok {} - {} # SKIP {}", index + 1, description, reason)?,
+            Outcome::Skip => writeln!(out, "ok {} - {} # SKIP the test has no RUN lines", index + 1, description)?,
+            Outcome::Fail(..) | Outcome::Diverged(..) | Outcome::UnexpectedPass | Outcome::Error(..) => {
+                writeln!(out, "not ok {} - {}", index + 1, description)?;
+            },
+        }
+
+        writeln!(out, "  ---")?;
+        writeln!(out, "  outcome: {}", result.outcome.label())?;
+        writeln!(out, "  duration_ms: {:.3}", result.duration.as_secs_f64() * 1000.0)?;
+        writeln!(out, "  compiler: {}", yaml_string(&entry.configuration.compiler.name))?;
+        writeln!(out, "  mcu: {}", yaml_string(&entry.configuration.mcu))?;
+        writeln!(out, "  opt_level: {}", yaml_string(&entry.configuration.opt_level))?;
+
+        match result.outcome {
+            Outcome::Fail(ref failure) | Outcome::Diverged(ref failure) | Outcome::ExpectedFailure(ref failure) => {
+                writeln!(out, "  message: {}", yaml_string(&failure.summary))?;
+                write_block(out, "  ", "details", &failure.details)?;
+            },
+            Outcome::UnexpectedPass => writeln!(out, "  message: {}", yaml_string("test passed, but it is expected to fail with this configuration"))?,
+            Outcome::Error(ref message) => writeln!(out, "  message: {}", yaml_string(message))?,
+            Outcome::Pass | Outcome::Unsupported(..) | Outcome::Skip => (),
+        }
+
+        if !result.runs.is_empty() {
+            writeln!(out, "  runs:")?;
+            for run in result.runs.iter() {
+                writeln!(out, "    - command: {}", yaml_string(&run.command_line))?;
+                match run.exit_code {
+                    Some(code) => writeln!(out, "      exit_code: {}", code)?,
+                    None => writeln!(out, "      exit_code: ~")?,
+                }
+                write_block(out, "      ", "stdout", &run.stdout)?;
+                write_block(out, "      ", "stderr", &run.stderr)?;
+            }
+        }
+
+        writeln!(out, "  ...")?;
+    }
+
+    Ok(())
+}
+
+/// Writes multi-line text as a YAML literal block.
+fn write_block(out: &mut dyn Write, indent: &str, key: &str, text: &str) -> io::Result<()> {
+    if text.is_empty() {
+        return writeln!(out, "{}{}: ''", indent, key);
+    }
+
+    writeln!(out, "{}{}: |-", indent, key)?;
+    for line in text.trim_end_matches('\n').lines() {
+        writeln!(out, "{}  {}", indent, line)?;
+    }
+    Ok(())
+}
+
+/// Quotes a single line of text as a YAML string.
+fn yaml_string(text: &str) -> String {
+    let text = text.lines().collect::<Vec<_>>().join(" ");
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::test::{entry, failure};
+
+    fn tap(entries: &[Entry]) -> String {
+        let mut out = Vec::new();
+        write(entries, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Gets the test point lines, leaving out the YAML blocks.
+    fn test_points(entries: &[Entry]) -> Vec<String> {
+        tap(entries).lines().filter(|line| line.starts_with("ok") || line.starts_with("not ok")).map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn every_outcome_has_a_test_point() {
+        let entries = [
+            entry("pass.c", "gcc", Outcome::Pass),
+            entry("fail.c", "gcc", Outcome::Fail(failure("exited with code 1", ""))),
+            entry("diverged.c", "gcc", Outcome::Diverged(failure("output differs", ""))),
+            entry("xfail.c", "gcc", Outcome::ExpectedFailure(failure("exited with code 1", ""))),
+            entry("xpass.c", "gcc", Outcome::UnexpectedPass),
+            entry("unsupported.c", "gcc", Outcome::Unsupported("needs llvm".to_owned())),
+            entry("error.c", "gcc", Outcome::Error("could not run".to_owned())),
+            entry("skip.c", "gcc", Outcome::Skip),
+        ];
+
+        assert_eq!(vec![
+            "ok 1 - pass.c (gcc)",
+            "not ok 2 - fail.c (gcc)",
+            "not ok 3 - diverged.c (gcc)",
+            "not ok 4 - xfail.c (gcc) # TODO expected failure",
+            "not ok 5 - xpass.c (gcc)",
+            "ok 6 - unsupported.c (gcc) # SKIP needs llvm",
+            "not ok 7 - error.c (gcc)",
+            "ok 8 - skip.c (gcc) # SKIP the test has no RUN lines",
+        ], test_points(&entries));
+        assert!(tap(&entries).starts_with("TAP version 13\n1..8\n"));
+    }
+
+    #[test]
+    fn details_are_written_as_yaml() {
+        let output = tap(&[entry("fail.c", "gcc", Outcome::Fail(failure("it's broken\nreally", "line one\nline two\n")))]);
+
+        assert!(output.contains("  outcome: FAIL\n  duration_ms: 1500.000\n  compiler: 'gcc'\n  mcu: 'atmega328p'\n  opt_level: 'O2'\n"));
+        assert!(output.contains("  message: 'it''s broken really'\n  details: |-\n    line one\n    line two\n"));
+        assert!(output.contains("    - command: 'avr-sim test.elf'\n      exit_code: 0\n      stdout: |-\n        OUTPUT_VALUE = 7\n      stderr: ''\n"));
+        assert!(output.ends_with("  ...\n"));
+    }
+}
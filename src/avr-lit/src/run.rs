@@ -0,0 +1,479 @@
+//! Running a test file and checking its output.
+
+use crate::test_file::{DirectiveKind, Pattern, TestFile};
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{process, thread};
+
+/// The shell that `RUN` lines are executed with.
+const SHELL: &str = "bash";
+
+/// The result of running a test file with a single configuration.
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    pub outcome: Outcome,
+    /// The output of every `RUN` line that was executed.
+    pub runs: Vec<RunOutput>,
+    /// How long it took to run the test, including the compiler.
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    /// A `RUN` line failed or the output did not match a `CHECK`.
+    Fail(Failure),
+    /// The output differs from the output of the reference compiler.
+    Diverged(Failure),
+    /// The test failed, but it is marked with `XFAIL`.
+    ExpectedFailure(Failure),
+    /// The test passed, but it is marked with `XFAIL`.
+    UnexpectedPass,
+    /// An `UNSUPPORTED` or `REQUIRES` directive excludes the configuration.
+    Unsupported(String),
+    /// The test could not be run at all.
+    Error(String),
+    /// The test has no `RUN` lines.
+    Skip,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// A one line description, such as "command exited with code 1".
+    pub summary: String,
+    /// Everything needed to understand the failure, such as the unmatched output.
+    pub details: String,
+}
+
+/// The output of a single `RUN` line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunOutput {
+    /// The command after constants were substituted.
+    pub command_line: String,
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` if the process was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Whether the command was killed for running longer than the timeout.
+    pub timed_out: bool,
+}
+
+/// Settings shared by every test that is run.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Values for the `@name` constants in `RUN` lines.
+    pub constants: HashMap<String, String>,
+    /// Directories to search for programs before the `PATH`.
+    pub executable_search_paths: Vec<PathBuf>,
+    pub cleanup_temporary_files: bool,
+    /// The number of output lines to show around a failed check, or `None` for all of them.
+    pub context_lines: Option<usize>,
+    /// The longest a single `RUN` line may take.
+    pub timeout: Option<Duration>,
+    /// The features of the configuration, which `XFAIL`, `UNSUPPORTED` and `REQUIRES` directives test for.
+    pub features: Vec<String>,
+    /// Whether the test is expected to fail even if no `XFAIL` directive matches.
+    pub expected_failure: bool,
+}
+
+impl Outcome {
+    /// Checks if the outcome should fail the test run.
+    pub fn is_erroneous(&self) -> bool {
+        match *self {
+            Outcome::Fail(..) | Outcome::Diverged(..) | Outcome::UnexpectedPass | Outcome::Error(..) => true,
+            Outcome::Pass | Outcome::ExpectedFailure(..) | Outcome::Unsupported(..) | Outcome::Skip => false,
+        }
+    }
+
+    /// Gets a short label such as `PASS`.
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Outcome::Pass => "PASS",
+            Outcome::Fail(..) => "FAIL",
+            Outcome::Diverged(..) => "DIFF",
+            Outcome::ExpectedFailure(..) => "XFAIL",
+            Outcome::UnexpectedPass => "XPASS",
+            Outcome::Unsupported(..) => "UNSUPPORTED",
+            Outcome::Error(..) => "ERROR",
+            Outcome::Skip => "SKIP",
+        }
+    }
+}
+
+/// Runs every `RUN` line of a test, checking the output of each against the `CHECK`s.
+pub fn test(test_file: &TestFile, settings: &Settings) -> TestResult {
+    let started = Instant::now();
+
+    if let Some(reason) = test_file.unsupported_reason(&settings.features) {
+        return TestResult { outcome: Outcome::Unsupported(reason), runs: Vec::new(), duration: started.elapsed() };
+    }
+
+    // Every test gets a directory of its own for temporary files, so that
+    // tests running at the same time cannot overwrite each other's files.
+    let temporary_directory = match tempfile::Builder::new().prefix("avr-lit-").tempdir() {
+        Ok(directory) => directory,
+        Err(e) => {
+            let outcome = Outcome::Error(format!("could not create a temporary directory: {}", e));
+            return TestResult { outcome, runs: Vec::new(), duration: started.elapsed() };
+        },
+    };
+
+    let mut runs = Vec::new();
+    let mut outcome = if test_file.run_lines().next().is_some() { Outcome::Pass } else { Outcome::Skip };
+
+    for run_line in test_file.run_lines() {
+        let mut variables = settings.constants.clone();
+        variables.insert("file".to_owned(), test_file.path.display().to_string());
+
+        let command_line = match substitute_constants(run_line, &mut variables, temporary_directory.path()) {
+            Ok(command_line) => command_line,
+            Err(message) => {
+                outcome = Outcome::Error(message);
+                break;
+            },
+        };
+
+        let run = match execute(&command_line, settings) {
+            Ok(run) => run,
+            Err(message) => {
+                outcome = Outcome::Error(message);
+                break;
+            },
+        };
+
+        let run_outcome = if run.exit_code == Some(0) {
+            check_output(test_file, &run.stdout, variables, settings)
+        } else {
+            let exit_status = match (run.timed_out, run.exit_code) {
+                (true, _) => format!("timed out after {} seconds", settings.timeout.unwrap_or_default().as_secs()),
+                (false, Some(code)) => format!("exited with code {}", code),
+                (false, None) => "was killed by a signal".to_owned(),
+            };
+            Outcome::Fail(Failure {
+                summary: format!("command {}", exit_status),
+                details: format!("command '{}' {}", run.command_line, exit_status),
+            })
+        };
+        runs.push(run);
+
+        if run_outcome != Outcome::Pass {
+            outcome = run_outcome;
+            break;
+        }
+    }
+
+    if !settings.cleanup_temporary_files {
+        // Keep the files, along with files the test derived from them such as
+        // `@tempfile.vcd`. The directory is only deleted when it is dropped.
+        std::mem::forget(temporary_directory);
+    }
+
+    let outcome = if settings.expected_failure || test_file.is_expected_failure(&settings.features) {
+        match outcome {
+            Outcome::Fail(failure) => Outcome::ExpectedFailure(failure),
+            Outcome::Pass => Outcome::UnexpectedPass,
+            outcome => outcome,
+        }
+    } else {
+        outcome
+    };
+
+    TestResult { outcome, runs, duration: started.elapsed() }
+}
+
+/// Replaces every `@name` in a command line with the value of the constant.
+///
+/// Names containing `tempfile` are given a fresh path in the temporary
+/// directory of the test the first time they are used.
+fn substitute_constants(command_line: &str, variables: &mut HashMap<String, String>, temporary_directory: &Path) -> Result<String, String> {
+    let constant_regex = Regex::new("@([_a-zA-Z]+)").unwrap();
+    let mut error = None;
+
+    let command_line = constant_regex.replace_all(command_line, |captures: &regex::Captures| {
+        let name = &captures[1];
+
+        if !variables.contains_key(name) && name.contains("tempfile") {
+            match tempfile::Builder::new().prefix(name).tempfile_in(temporary_directory).and_then(|f| f.into_temp_path().keep().map_err(|e| e.error)) {
+                Ok(path) => { variables.insert(name.to_owned(), path.display().to_string()); },
+                Err(e) => error = Some(format!("could not create a temporary file: {}", e)),
+            }
+        }
+
+        match variables.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                error = Some(format!("no constant named '@{}' exists", name));
+                String::new()
+            },
+        }
+    }).into_owned();
+
+    match error {
+        Some(message) => Err(message),
+        None => Ok(command_line),
+    }
+}
+
+fn execute(command_line: &str, settings: &Settings) -> Result<RunOutput, String> {
+    let mut command = process::Command::new(SHELL);
+    command.args(["-c", command_line]);
+    command.stdin(process::Stdio::null());
+
+    if !settings.executable_search_paths.is_empty() {
+        let current_path = std::env::var_os("PATH").unwrap_or_default();
+        let paths = settings.executable_search_paths.iter().cloned().chain(std::env::split_paths(&current_path));
+        command.env("PATH", std::env::join_paths(paths).map_err(|e| e.to_string())?);
+    }
+
+    command.stdout(process::Stdio::piped());
+    command.stderr(process::Stdio::piped());
+    // Put the shell and everything it starts in their own process group, so
+    // that all of them can be killed if the command times out.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("shell '{}' does not exist", SHELL),
+        _ => e.to_string(),
+    })?;
+
+    // Read the output on other threads so that a full pipe cannot block the command.
+    let stdout = read_in_background(child.stdout.take().unwrap());
+    let stderr = read_in_background(child.stderr.take().unwrap());
+
+    let started = Instant::now();
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+
+        if settings.timeout.map(|timeout| started.elapsed() > timeout).unwrap_or(false) {
+            kill(&mut child);
+            timed_out = true;
+            break child.wait().map_err(|e| e.to_string())?;
+        }
+
+        thread::sleep(Duration::from_millis(5));
+    };
+
+    Ok(RunOutput {
+        command_line: command_line.to_owned(),
+        stdout: String::from_utf8_lossy(&stdout.join().unwrap_or_default()).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr.join().unwrap_or_default()).into_owned(),
+        exit_code: status.code(),
+        timed_out,
+    })
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        pipe.read_to_end(&mut buffer).ok();
+        buffer
+    })
+}
+
+/// Kills a command along with every process it started.
+fn kill(child: &mut process::Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+
+    child.kill().ok();
+}
+
+/// Checks the output of a `RUN` line against every `CHECK` in order.
+fn check_output(test_file: &TestFile, output: &str, mut variables: HashMap<String, String>, settings: &Settings) -> Outcome {
+    let mut position = 0;
+
+    for directive in test_file.directives.iter() {
+        let (pattern, require_next_line) = match directive.kind {
+            DirectiveKind::Check(ref pattern) => (pattern, false),
+            DirectiveKind::CheckNext(ref pattern) => (pattern, true),
+            DirectiveKind::Run(..) | DirectiveKind::XFail(..) | DirectiveKind::Unsupported(..) |
+                DirectiveKind::Requires(..) | DirectiveKind::DiffIgnore(..) => continue,
+        };
+
+        // Leading whitespace never needs to be matched.
+        let unchecked = &output[position..];
+        position += unchecked.len() - unchecked.trim_start().len();
+        let unchecked = &output[position..];
+
+        let regex = match pattern.to_regex(|name| variables.get(name).cloned()) {
+            Ok(regex) => regex,
+            Err(message) => return Outcome::Error(format!("line {}: {}", directive.line, message)),
+        };
+
+        let captures = match regex.captures(unchecked) {
+            Some(captures) => captures,
+            None => return check_failure(pattern, None, output, position, settings),
+        };
+        let matched = captures.get(0).unwrap();
+
+        if require_next_line {
+            if let Some(end_of_line) = unchecked.find('\n') {
+                if matched.start() >= end_of_line {
+                    let hint = format!("found a match for '{}', but it does not appear on the next line, as required by the CHECK-NEXT directive", pattern);
+                    return check_failure(pattern, Some(hint), output, position, settings);
+                }
+            }
+        }
+
+        for name in regex.capture_names().flatten() {
+            if let Some(value) = captures.name(name) {
+                variables.insert(name.to_owned(), value.as_str().to_owned());
+            }
+        }
+
+        // No other checks run against the rest of the matched line.
+        let after_match = position + matched.end();
+        position = match output[after_match..].find('\n') {
+            Some(end_of_line) => after_match + end_of_line + 1,
+            None => output.len(),
+        };
+    }
+
+    Outcome::Pass
+}
+
+fn check_failure(pattern: &Pattern, hint: Option<String>, output: &str, position: usize, settings: &Settings) -> Outcome {
+    let (checked, remaining) = output.split_at(position);
+    let mut details = format!("expected text '{}' but that was not found\n\n", pattern);
+
+    details += &format_output("successfully checked output", checked, 1, Keep::Last, settings);
+    details += "\n\n";
+    details += &format_output("remaining unchecked output", remaining, checked.lines().count() + 1, Keep::First, settings);
+
+    if let Some(hint) = hint {
+        details += &format!("\n\nhint: {}", hint);
+    }
+
+    Outcome::Fail(Failure { summary: "test checked for text that did not exist in the output".to_owned(), details })
+}
+
+/// Which lines of long output to show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Keep {
+    First,
+    Last,
+}
+
+/// Formats program output with line numbers, truncated to the number of context lines.
+fn format_output(label: &str, output: &str, first_line_number: usize, keep: Keep, settings: &Settings) -> String {
+    let lines = output.lines().enumerate().map(|(i, line)| format!("    {:>4}| {}", first_line_number + i, line)).collect::<Vec<_>>();
+
+    let lines = match (settings.context_lines, keep) {
+        (Some(count), Keep::First) if lines.len() > count => lines[..count].to_vec(),
+        (Some(count), Keep::Last) if lines.len() > count => lines[lines.len() - count..].to_vec(),
+        _ => lines,
+    };
+
+    format!("<{}>:\n\n{}\n</{}>", label, lines.join("\n"), label)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_file::Directive;
+
+    fn test_file(checks: &[(&str, bool)]) -> TestFile {
+        let directives = checks.iter().enumerate().map(|(index, &(pattern, next_line))| Directive {
+            line: index + 1,
+            kind: if next_line { DirectiveKind::CheckNext(pattern.parse().unwrap()) } else { DirectiveKind::Check(pattern.parse().unwrap()) },
+        }).collect();
+
+        TestFile { path: PathBuf::from("test.c"), relative_path: PathBuf::from("test.c"), directives }
+    }
+
+    fn settings() -> Settings {
+        Settings {
+            constants: HashMap::new(),
+            executable_search_paths: Vec::new(),
+            cleanup_temporary_files: true,
+            context_lines: None,
+            timeout: None,
+            features: Vec::new(),
+            expected_failure: false,
+        }
+    }
+
+    fn check(checks: &[(&str, bool)], output: &str) -> Outcome {
+        check_output(&test_file(checks), output, HashMap::new(), &settings())
+    }
+
+    #[test]
+    fn checks_match_in_order() {
+        let output = "a = 1\nb = 2\nc = 3\n";
+
+        assert_eq!(Outcome::Pass, check(&[("a = 1", false), ("c = 3", false)], output));
+        assert!(matches!(check(&[("c = 3", false), ("a = 1", false)], output), Outcome::Fail(..)));
+    }
+
+    #[test]
+    fn checks_do_not_match_the_rest_of_a_matched_line() {
+        assert!(matches!(check(&[("a", false), ("1", false)], "a = 1\n"), Outcome::Fail(..)));
+    }
+
+    #[test]
+    fn check_next_must_match_the_next_line() {
+        let output = "start\nmiddle\nend\n";
+
+        assert_eq!(Outcome::Pass, check(&[("start", false), ("middle", true)], output));
+
+        match check(&[("start", false), ("end", true)], output) {
+            Outcome::Fail(failure) => {
+                assert!(failure.details.starts_with("expected text 'end' but that was not found"));
+                assert!(failure.details.contains("it does not appear on the next line, as required by the CHECK-NEXT directive"));
+            },
+            outcome => panic!("expected a failure but got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn captured_variables_are_used_by_later_checks() {
+        let checks = [("pc = [[pc:0x\\w+]]", false), ("stopped at $$pc", false)];
+
+        assert_eq!(Outcome::Pass, check(&checks, "pc = 0x2a\nstopped at 0x2a\n"));
+        assert!(matches!(check(&checks, "pc = 0x2a\nstopped at 0x2b\n"), Outcome::Fail(..)));
+    }
+
+    #[test]
+    fn unknown_variables_are_errors() {
+        assert_eq!(Outcome::Error("line 1: no variable named 'pc' has been captured".to_owned()), check(&[("at $$pc", false)], "at 0x2a\n"));
+    }
+
+    #[test]
+    fn constants_are_substituted() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut variables = HashMap::new();
+        variables.insert("cc".to_owned(), "avr-gcc".to_owned());
+
+        assert_eq!(Ok("avr-gcc -c foo.c".to_owned()), substitute_constants("@cc -c foo.c", &mut variables, directory.path()));
+    }
+
+    #[test]
+    fn tempfiles_are_created_once_and_keep_their_suffix() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut variables = HashMap::new();
+
+        let command_line = substitute_constants("cc -o @tempfile.elf && run @tempfile.elf @other_tempfile", &mut variables, directory.path()).unwrap();
+        let (tempfile, other_tempfile) = (&variables["tempfile"], &variables["other_tempfile"]);
+
+        assert_eq!(format!("cc -o {}.elf && run {}.elf {}", tempfile, tempfile, other_tempfile), command_line);
+        assert_ne!(tempfile, other_tempfile);
+        assert!(Path::new(tempfile).starts_with(directory.path()));
+        assert!(Path::new(tempfile).exists());
+    }
+
+    #[test]
+    fn unknown_constants_are_errors() {
+        let directory = tempfile::tempdir().unwrap();
+
+        assert_eq!(Err("no constant named '@nope' exists".to_owned()), substitute_constants("echo @nope", &mut HashMap::new(), directory.path()));
+    }
+}
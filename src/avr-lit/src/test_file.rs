@@ -0,0 +1,344 @@
+//! Finding test files and parsing the directives inside them.
+
+use crate::expression::Expression;
+use regex::Regex;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A test file and the directives written in its comments.
+#[derive(Clone, Debug)]
+pub struct TestFile {
+    /// The on-disk path to the test file.
+    pub path: PathBuf,
+    /// The path shown in reports, relative to the directory it was found in.
+    pub relative_path: PathBuf,
+    pub directives: Vec<Directive>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Directive {
+    /// The line of the test file that the directive is written on.
+    pub line: usize,
+    pub kind: DirectiveKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum DirectiveKind {
+    /// Run a shell command, checking its output against every `CHECK`.
+    Run(String),
+    /// Verify that the output contains text matching a pattern.
+    Check(Pattern),
+    /// Verify that the very next output line matches a pattern.
+    CheckNext(Pattern),
+    /// Mark the test as supposed to fail in configurations matching any of
+    /// the expressions, or in every configuration if there are none.
+    XFail(Vec<Expression>),
+    /// Skip the test in configurations matching any of the expressions.
+    Unsupported(Vec<Expression>),
+    /// Skip the test in configurations that do not match every expression.
+    Requires(Vec<Expression>),
+    /// Leave output lines matching a regex out of differential comparisons.
+    DiffIgnore(Regex),
+}
+
+/// Text to look for in the output of a test.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    components: Vec<PatternComponent>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PatternComponent {
+    Text(String),
+    /// `$$name`, the value captured by an earlier named regex.
+    Variable(String),
+    /// `[[regex]]`
+    Regex(String),
+    /// `[[name:regex]]`, which captures the match into a variable.
+    NamedRegex { name: String, regex: String },
+}
+
+impl TestFile {
+    /// Reads and parses a test file.
+    pub fn load(path: PathBuf, relative_path: PathBuf) -> Result<Self, String> {
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+        let directives = parse_directives(&text).map_err(|e| format!("{}:{}", relative_path.display(), e))?;
+
+        Ok(TestFile { path, relative_path, directives })
+    }
+
+    /// Gets the shell command of every `RUN` directive.
+    pub fn run_lines(&self) -> impl Iterator<Item=&str> {
+        self.directives.iter().filter_map(|d| match d.kind {
+            DirectiveKind::Run(ref command) => Some(&command[..]),
+            _ => None,
+        })
+    }
+
+    /// Gets the regexes of every `DIFF-IGNORE` directive.
+    pub fn diff_ignore_patterns(&self) -> Vec<Regex> {
+        self.directives.iter().filter_map(|d| match d.kind {
+            DirectiveKind::DiffIgnore(ref regex) => Some(regex.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    /// Checks if an `XFAIL` directive matches a configuration with the given features.
+    pub fn is_expected_failure(&self, features: &[String]) -> bool {
+        self.directives.iter().any(|d| match d.kind {
+            DirectiveKind::XFail(ref expressions) => expressions.is_empty() || expressions.iter().any(|e| e.evaluate(features)),
+            _ => false,
+        })
+    }
+
+    /// Gets the reason that the test cannot run in a configuration with the
+    /// given features, if an `UNSUPPORTED` or `REQUIRES` directive says so.
+    pub fn unsupported_reason(&self, features: &[String]) -> Option<String> {
+        self.directives.iter().find_map(|d| match d.kind {
+            DirectiveKind::Unsupported(ref expressions) if expressions.iter().any(|e| e.evaluate(features)) => {
+                Some(format!("the UNSUPPORTED directive on line {} matches this configuration", d.line))
+            },
+            DirectiveKind::Requires(ref expressions) if !expressions.iter().all(|e| e.evaluate(features)) => {
+                Some(format!("this configuration lacks a feature listed by the REQUIRES directive on line {}", d.line))
+            },
+            _ => None,
+        })
+    }
+}
+
+/// Recursively finds the test files in each of the given paths.
+pub fn find(test_paths: &[PathBuf], extensions: &[String]) -> Result<Vec<TestFile>, String> {
+    let mut test_files = Vec::new();
+
+    for test_path in test_paths {
+        if test_path.is_dir() {
+            let mut paths = Vec::new();
+            for entry in walkdir::WalkDir::new(test_path) {
+                let entry = entry.map_err(|e| format!("could not search '{}': {}", test_path.display(), e))?;
+
+                if entry.file_type().is_file() && has_extension(entry.path(), extensions) {
+                    paths.push(entry.into_path());
+                }
+            }
+            paths.sort();
+
+            for path in paths {
+                let relative_path = path.strip_prefix(test_path).unwrap().to_owned();
+                test_files.push(TestFile::load(path, relative_path)?);
+            }
+        } else {
+            let relative_path = test_path.file_name().map(PathBuf::from).unwrap_or_else(|| test_path.clone());
+            test_files.push(TestFile::load(test_path.clone(), relative_path)?);
+        }
+    }
+
+    Ok(test_files)
+}
+
+fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => extensions.iter().any(|e| e.trim_start_matches('.') == extension),
+        None => false,
+    }
+}
+
+/// Parses the directives in comments such as `// CHECK: foo`.
+fn parse_directives(text: &str) -> Result<Vec<Directive>, String> {
+    let directive_regex = Regex::new(r"//\s*([A-Z][A-Z-]*):(.*)").unwrap();
+    let mut directives = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let captures = match directive_regex.captures(line) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let (name, rest) = (&captures[1], captures[2].trim());
+
+        let kind = match name {
+            "RUN" => DirectiveKind::Run(rest.split_whitespace().collect::<Vec<_>>().join(" ")),
+            "CHECK" => DirectiveKind::Check(rest.parse()?),
+            "CHECK-NEXT" => DirectiveKind::CheckNext(rest.parse()?),
+            "XFAIL" => DirectiveKind::XFail(parse_expressions(rest, index)?),
+            "UNSUPPORTED" => DirectiveKind::Unsupported(parse_expressions(rest, index)?),
+            "REQUIRES" => DirectiveKind::Requires(parse_expressions(rest, index)?),
+            "DIFF-IGNORE" => DirectiveKind::DiffIgnore(Regex::new(rest).map_err(|e| format!("{}: invalid regex '{}': {}", index + 1, rest, e))?),
+            _ => return Err(format!("{}: directive '{}' not known", index + 1, name)),
+        };
+
+        directives.push(Directive { line: index + 1, kind });
+    }
+
+    Ok(directives)
+}
+
+fn parse_expressions(text: &str, line_index: usize) -> Result<Vec<Expression>, String> {
+    Expression::parse_list(text).map_err(|e| format!("{}: {}", line_index + 1, e))
+}
+
+impl Pattern {
+    /// Builds a regex that matches the pattern, substituting the values of variables.
+    pub fn to_regex(&self, lookup_variable: impl Fn(&str) -> Option<String>) -> Result<Regex, String> {
+        let parts = self.components.iter().map(|component| Ok(match *component {
+            PatternComponent::Text(ref text) => regex::escape(text),
+            PatternComponent::Variable(ref name) => {
+                lookup_variable(name).ok_or_else(|| format!("no variable named '{}' has been captured", name))?
+            },
+            PatternComponent::Regex(ref regex) => regex.clone(),
+            PatternComponent::NamedRegex { ref name, ref regex } => format!("(?P<{}>{})", name, regex),
+        })).collect::<Result<Vec<_>, String>>()?;
+
+        Regex::new(&parts.join("")).map_err(|e| format!("invalid pattern '{}': {}", self, e))
+    }
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut components = Vec::new();
+        let mut text = String::new();
+        let mut rest = s;
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("$$") {
+                let name = after.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect::<String>();
+                if name.is_empty() {
+                    return Err(format!("expected a variable name after '$$' in '{}'", s));
+                }
+
+                components.push(PatternComponent::Text(std::mem::take(&mut text)));
+                components.push(PatternComponent::Variable(name.clone()));
+                rest = &after[name.len()..];
+            } else if let Some(after) = rest.strip_prefix("[[") {
+                let end = regex_end(after).ok_or_else(|| format!("unterminated '[[' in '{}'", s))?;
+                let regex = &after[..end];
+
+                components.push(PatternComponent::Text(std::mem::take(&mut text)));
+                components.push(match regex.find(':') {
+                    Some(colon) if is_identifier(&regex[..colon]) => PatternComponent::NamedRegex {
+                        name: regex[..colon].to_owned(),
+                        regex: regex[colon + 1..].to_owned(),
+                    },
+                    _ => PatternComponent::Regex(regex.to_owned()),
+                });
+                rest = &after[end + 2..];
+            } else {
+                let c = rest.chars().next().unwrap();
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+
+        components.push(PatternComponent::Text(text));
+        components.retain(|c| *c != PatternComponent::Text(String::new()));
+
+        Ok(Pattern { components })
+    }
+}
+
+/// Finds the `]]` that ends a regex, skipping over character classes such as `[0-9]`.
+fn regex_end(s: &str) -> Option<usize> {
+    let mut bracket_level = 0;
+
+    for (index, c) in s.char_indices() {
+        match c {
+            ']' if bracket_level == 0 && s[index..].starts_with("]]") => return Some(index),
+            '[' => bracket_level += 1,
+            ']' => bracket_level -= 1,
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for component in self.components.iter() {
+            match *component {
+                PatternComponent::Text(ref text) => write!(fmt, "{}", text)?,
+                PatternComponent::Variable(ref name) => write!(fmt, "$${}", name)?,
+                PatternComponent::Regex(ref regex) => write!(fmt, "[[{}]]", regex)?,
+                PatternComponent::NamedRegex { ref name, ref regex } => write!(fmt, "[[{}:{}]]", name, regex)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern(s: &str) -> Pattern {
+        s.parse().unwrap()
+    }
+
+    fn no_variables(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn can_parse_patterns() {
+        assert_eq!(Pattern { components: vec![
+            PatternComponent::Text("value = ".to_owned()),
+            PatternComponent::Regex(r"\d+".to_owned()),
+            PatternComponent::Text(" at ".to_owned()),
+            PatternComponent::NamedRegex { name: "pc".to_owned(), regex: "0x[0-9a-f]+".to_owned() },
+            PatternComponent::Text(", again at ".to_owned()),
+            PatternComponent::Variable("pc".to_owned()),
+        ] }, pattern(r"value = [[\d+]] at [[pc:0x[0-9a-f]+]], again at $$pc"));
+    }
+
+    #[test]
+    fn character_classes_do_not_end_a_regex() {
+        assert_eq!(Pattern { components: vec![
+            PatternComponent::Regex("[a-f]+".to_owned()),
+            PatternComponent::Text("]".to_owned()),
+        ] }, pattern("[[[a-f]+]]]"));
+    }
+
+    #[test]
+    fn colons_only_name_a_regex_after_an_identifier() {
+        assert_eq!(Pattern { components: vec![PatternComponent::Regex("(?:µs|ms)".to_owned())] }, pattern("[[(?:µs|ms)]]"));
+        assert_eq!(Pattern { components: vec![PatternComponent::Regex("1st:x".to_owned())] }, pattern("[[1st:x]]"));
+    }
+
+    #[test]
+    fn malformed_patterns_are_errors() {
+        assert_eq!(Err("unterminated '[[' in 'value = [[\\d+'".to_owned()), r"value = [[\d+".parse::<Pattern>());
+        assert_eq!(Err("expected a variable name after '$$' in 'value = $$'".to_owned()), "value = $$".parse::<Pattern>());
+    }
+
+    #[test]
+    fn text_is_matched_literally() {
+        let regex = pattern("f(x) = [1.5]").to_regex(no_variables).unwrap();
+
+        assert!(regex.is_match("f(x) = [1.5]"));
+        assert!(!regex.is_match("f(x) = [105]"));
+    }
+
+    #[test]
+    fn regexes_and_variables_are_substituted() {
+        let regex = pattern("[[pc:0x\\w+]] then $$previous").to_regex(|name| match name {
+            "previous" => Some("0x10".to_owned()),
+            _ => None,
+        }).unwrap();
+
+        let captures = regex.captures("0x2a then 0x10").unwrap();
+        assert_eq!("0x2a", &captures["pc"]);
+        assert!(!regex.is_match("0x2a then 0x11"));
+    }
+
+    #[test]
+    fn unknown_variables_and_invalid_regexes_are_errors() {
+        assert_eq!(Err("no variable named 'pc' has been captured".to_owned()), pattern("at $$pc").to_regex(no_variables).map(|r| r.to_string()));
+        assert!(pattern("[[(]]").to_regex(no_variables).unwrap_err().starts_with("invalid pattern '[[(]]'"));
+    }
+}
@@ -0,0 +1,411 @@
+//! A parser for the subset of TOML that `avr-lit.toml` files use.
+//!
+//! Supports tables, arrays of tables, dotted keys, basic and literal strings,
+//! integers, booleans, arrays and inline tables. Dates, floats and
+//! multi-line strings are not supported.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    /// Describes the type of the value for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::String(..) => "a string",
+            Value::Integer(..) => "an integer",
+            Value::Boolean(..) => "a boolean",
+            Value::Array(..) => "an array",
+            Value::Table(..) => "a table",
+        }
+    }
+}
+
+/// Parses a TOML document into its root table.
+pub fn parse(text: &str) -> Result<Table, String> {
+    let mut parser = Parser { text, position: 0 };
+    parser.document().map_err(|message| format!("line {}: {}", parser.line(), message))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        // The path to the table that key/value pairs are added to.
+        let mut current_path: Vec<String> = Vec::new();
+        // The paths of the `[table]` headers seen so far, which may not be repeated.
+        let mut defined_tables: BTreeSet<Vec<String>> = BTreeSet::new();
+
+        loop {
+            self.skip_whitespace_and_newlines();
+            if self.rest().is_empty() {
+                return Ok(root);
+            }
+
+            if self.eat("[[") {
+                let path = self.key_path()?;
+                self.expect("]]")?;
+                array_of_tables_at(&mut root, &path)?.push(Value::Table(Table::new()));
+                // The tables below the previous element were in a different table.
+                defined_tables.retain(|defined| !defined.starts_with(&path));
+                current_path = path;
+            } else if self.eat("[") {
+                let path = self.key_path()?;
+                self.expect("]")?;
+
+                let (key, parents) = path.split_last().unwrap();
+                if let Some(Value::Array(..)) = table_at(&mut root, parents)?.get(key) {
+                    return Err(format!("'{}' is an array of tables, not a table", path.join(".")));
+                }
+                if !defined_tables.insert(path.clone()) {
+                    return Err(format!("table '{}' is defined twice", path.join(".")));
+                }
+
+                table_at(&mut root, &path)?;
+                current_path = path;
+            } else {
+                let key_path = self.key_path()?;
+                self.skip_whitespace();
+                self.expect("=")?;
+                let value = self.value()?;
+
+                let (key, parents) = key_path.split_last().unwrap();
+                let table = table_at(table_at(&mut root, &current_path)?, parents)?;
+                if table.contains_key(key) {
+                    return Err(format!("key '{}' is defined twice", key_path.join(".")));
+                }
+                table.insert(key.clone(), value);
+            }
+
+            self.end_of_line()?;
+        }
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>, String> {
+        let mut path = vec![self.key()?];
+        self.skip_whitespace();
+        while self.eat(".") {
+            path.push(self.key()?);
+            self.skip_whitespace();
+        }
+        Ok(path)
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+
+        if self.rest().starts_with('"') || self.rest().starts_with('\'') {
+            return self.string();
+        }
+
+        let length = self.rest().find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-')).unwrap_or(self.rest().len());
+        if length == 0 {
+            return Err(format!("expected a key but found '{}'", self.current_line()));
+        }
+
+        let key = self.rest()[..length].to_owned();
+        self.position += length;
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let rest = self.rest();
+
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            Ok(Value::String(self.string()?))
+        } else if self.eat("[") {
+            self.array()
+        } else if self.eat("{") {
+            self.inline_table()
+        } else if self.eat("true") {
+            Ok(Value::Boolean(true))
+        } else if self.eat("false") {
+            Ok(Value::Boolean(false))
+        } else {
+            let length = rest.find(|c: char| !(c.is_ascii_digit() || c == '_' || c == '+' || c == '-')).unwrap_or(rest.len());
+            let digits = rest[..length].replace('_', "");
+
+            match digits.parse() {
+                Ok(integer) => {
+                    self.position += length;
+                    Ok(Value::Integer(integer))
+                },
+                Err(..) => Err(format!("expected a value but found '{}'", rest.lines().next().unwrap_or("").trim())),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.rest().chars().next().unwrap();
+        self.position += 1;
+
+        let mut string = String::new();
+        let mut chars = self.rest().char_indices();
+
+        while let Some((index, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.position += index + 1;
+                    return Ok(string);
+                },
+                '\n' => break,
+                // Literal strings do not have escapes.
+                '\\' if quote == '"' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c) => return Err(format!("unknown escape sequence '\\{}'", c)),
+                        None => break,
+                    };
+                    string.push(escaped);
+                },
+                c => string.push(c),
+            }
+        }
+
+        Err("unterminated string".to_owned())
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut values = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_newlines();
+            if self.eat("]") {
+                return Ok(Value::Array(values));
+            }
+
+            values.push(self.value()?);
+
+            self.skip_whitespace_and_newlines();
+            if !self.eat(",") {
+                self.skip_whitespace_and_newlines();
+                self.expect("]")?;
+                return Ok(Value::Array(values));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        let mut table = Table::new();
+
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Value::Table(table));
+        }
+
+        loop {
+            let key_path = self.key_path()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            let value = self.value()?;
+
+            let (key, parents) = key_path.split_last().unwrap();
+            let parent = table_at(&mut table, parents)?;
+            if parent.contains_key(key) {
+                return Err(format!("key '{}' is defined twice", key_path.join(".")));
+            }
+            parent.insert(key.clone(), value);
+
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Value::Table(table));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    /// Skips to the next line, only allowing a comment on the way.
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.rest().starts_with('#') {
+            self.skip_comment();
+        }
+
+        if self.rest().is_empty() || self.eat("\n") || self.eat("\r\n") {
+            Ok(())
+        } else {
+            Err(format!("expected the end of the line but found '{}'", self.current_line()))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    }
+
+    fn skip_whitespace_and_newlines(&mut self) {
+        loop {
+            let rest = self.rest();
+            self.position += rest.len() - rest.trim_start().len();
+
+            if self.rest().starts_with('#') {
+                self.skip_comment();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        self.position += self.rest().find('\n').unwrap_or(self.rest().len());
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' but found '{}'", token, self.current_line()))
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn current_line(&self) -> &'a str {
+        self.rest().lines().next().unwrap_or("").trim()
+    }
+
+    fn line(&self) -> usize {
+        self.text[..self.position].matches('\n').count() + 1
+    }
+}
+
+/// Gets the table at a path, creating tables that do not exist. Arrays of
+/// tables resolve to their last table.
+fn table_at<'t>(mut table: &'t mut Table, path: &[String]) -> Result<&'t mut Table, String> {
+    for key in path {
+        let value = table.entry(key.clone()).or_insert_with(|| Value::Table(Table::new()));
+
+        table = match value {
+            Value::Table(table) => table,
+            Value::Array(values) => match values.last_mut() {
+                Some(Value::Table(table)) => table,
+                _ => return Err(format!("'{}' is not a table", key)),
+            },
+            _ => return Err(format!("'{}' is not a table", key)),
+        };
+    }
+
+    Ok(table)
+}
+
+fn array_of_tables_at<'t>(table: &'t mut Table, path: &[String]) -> Result<&'t mut Vec<Value>, String> {
+    let (key, parents) = path.split_last().unwrap();
+    let parent = table_at(table, parents)?;
+
+    match parent.entry(key.clone()).or_insert_with(|| Value::Array(Vec::new())) {
+        Value::Array(values) if values.iter().all(|v| matches!(v, Value::Table(..))) => Ok(values),
+        _ => Err(format!("'{}' is not an array of tables", path.join("."))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    fn table(entries: &[(&str, Value)]) -> Value {
+        Value::Table(entries.iter().map(|(key, value)| (key.to_string(), value.clone())).collect())
+    }
+
+    fn parse_value(text: &str) -> Value {
+        parse(&format!("value = {}", text)).unwrap().remove("value").unwrap()
+    }
+
+    #[test]
+    fn can_parse_strings_and_escapes() {
+        assert_eq!(string("a \"quoted\" \\ word\n\tindented\r"), parse_value(r#""a \"quoted\" \\ word\n\tindented\r""#));
+        assert_eq!(string(r"C:\no\escapes"), parse_value(r"'C:\no\escapes'"));
+        assert_eq!(Err("line 1: unknown escape sequence '\\q'".to_owned()), parse(r#"value = "\q""#));
+        assert_eq!(Err("line 1: unterminated string".to_owned()), parse("value = \"open\nnext = 1"));
+    }
+
+    #[test]
+    fn can_parse_integers_and_booleans() {
+        assert_eq!(Value::Integer(1_000), parse_value("1_000"));
+        assert_eq!(Value::Integer(-5), parse_value("-5"));
+        assert_eq!(Value::Boolean(true), parse_value("true"));
+    }
+
+    #[test]
+    fn can_parse_multi_line_arrays() {
+        let text = "value = [\n    \"-O0\", # no optimization\n\n    \"-O2\",\n]\n";
+
+        assert_eq!(Value::Array(vec![string("-O0"), string("-O2")]), parse(text).unwrap()["value"]);
+        assert_eq!(Value::Array(vec![]), parse_value("[ ]"));
+    }
+
+    #[test]
+    fn can_parse_inline_tables() {
+        assert_eq!(table(&[("name", string("gcc")), ("flags", table(&[("c", string("-std=c99"))]))]),
+                   parse_value(r#"{ name = "gcc", flags.c = "-std=c99" }"#));
+        assert_eq!(table(&[]), parse_value("{}"));
+        assert_eq!(Err("line 1: key 'a' is defined twice".to_owned()), parse("value = { a = 1, a = 2 }"));
+    }
+
+    #[test]
+    fn can_parse_tables_and_dotted_keys() {
+        let root = parse("top = 1\n[a.b]\nc = 2\n[a]\nd.e = 3\n").unwrap();
+
+        assert_eq!(Value::Integer(1), root["top"]);
+        assert_eq!(table(&[("b", table(&[("c", Value::Integer(2))])), ("d", table(&[("e", Value::Integer(3))]))]), root["a"]);
+    }
+
+    #[test]
+    fn can_parse_arrays_of_tables() {
+        let root = parse("[[compiler]]\nname = \"a\"\n[compiler.flags]\nc = 1\n[[compiler]]\nname = \"b\"\n[compiler.flags]\nc = 2\n").unwrap();
+
+        assert_eq!(Value::Array(vec![
+            table(&[("name", string("a")), ("flags", table(&[("c", Value::Integer(1))]))]),
+            table(&[("name", string("b")), ("flags", table(&[("c", Value::Integer(2))]))]),
+        ]), root["compiler"]);
+    }
+
+    #[test]
+    fn rejects_duplicate_keys_and_tables() {
+        assert_eq!(Err("line 2: key 'a' is defined twice".to_owned()), parse("a = 1\na = 2"));
+        assert_eq!(Err("line 3: key 'b.c' is defined twice".to_owned()), parse("[a]\nb.c = 1\nb.c = 2"));
+        assert_eq!(Err("line 3: table 'a' is defined twice".to_owned()), parse("[a]\nb = 1\n[a]\nc = 2"));
+        assert_eq!(Err("line 4: table 'a.b' is defined twice".to_owned()), parse("[[a]]\n[a.b]\n[a.c]\n[a.b]"));
+        assert_eq!(Err("line 2: 'a' is an array of tables, not a table".to_owned()), parse("[[a]]\n[a]"));
+        assert_eq!(Err("line 2: 'a' is not an array of tables".to_owned()), parse("[a]\n[[a]]"));
+    }
+
+    #[test]
+    fn errors_report_the_line_they_are_on() {
+        assert_eq!(Err("line 3: expected a value but found 'nope'".to_owned()), parse("# comment\na = 1\nb = nope\n"));
+        assert_eq!(Err("line 2: expected the end of the line but found '2'".to_owned()), parse("a = 1\nb = 1 2\n"));
+        assert_eq!(Err("line 4: expected ']' but found 'b = 2'".to_owned()), parse("a = [\n  1,\n  2\nb = 2\n"));
+    }
+}
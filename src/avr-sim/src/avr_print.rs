@@ -1,7 +1,7 @@
 //! Routines for allowing the AVR to print to the console.
 
 use crate::{
-    MemorySpace, WatchableSymbol,
+    Error, MemorySpace, Result, WatchableSymbol,
     read_current_memory_address, read_current_memory_address_mut,
 };
 
@@ -21,11 +21,11 @@ pub struct Config {
 impl Config {
     /// Creates a new AVR printing config, if it is possible.
     pub fn new(watchable_symbols: &[WatchableSymbol])
-        -> Result<Self, String> {
+        -> Result<Self> {
         let lookup_symbol = |symbol_name: &str| {
             match watchable_symbols.iter().find(|s| s.name == symbol_name) {
                 Some(s) => Ok(s.clone()),
-                None => Err(format!("the AVR executable does not contain the libavrlit special symbol '{}'", symbol_name)),
+                None => Err(Error::MissingSymbol(format!("the AVR executable does not contain the libavrlit special symbol '{}'", symbol_name))),
             }
         };
 
@@ -35,11 +35,11 @@ impl Config {
         Ok(Config { symbol_for_send_buffer, symbol_for_send_buffer_flags })
     }
 
-    pub fn consume_character(&self, avr: &simavr::Avr) -> Result<Option<char>, String> {
+    pub fn consume_character(&self, avr: &simavr::Avr) -> Result<Option<char>> {
         self.consume_byte(avr).map(|o| o.map(|b| b as char))
     }
 
-    pub fn consume_byte(&self, avr: &simavr::Avr) -> Result<Option<u8>, String> {
+    pub fn consume_byte(&self, avr: &simavr::Avr) -> Result<Option<u8>> {
         read_current_memory_address(MemorySpace::Data, self.symbol_for_send_buffer_flags.address, avr)?;
 
         let current_flags = self.get_current_flags(avr)?;
@@ -58,30 +58,30 @@ impl Config {
         }
     }
 
-    fn get_current_flags(&self, avr: &simavr::Avr) -> Result<WriteBufferFlags, String> {
+    fn get_current_flags(&self, avr: &simavr::Avr) -> Result<WriteBufferFlags> {
         read_current_memory_address(MemorySpace::Data, self.symbol_for_send_buffer_flags.address, avr)?
             .get(0)
-            .ok_or_else(|| "the debug write buffer flag has no allocated space".to_owned())
-            .and_then(|&b| WriteBufferFlags::from_bits(b).ok_or("the debug write buffer flag variable is corrupted".to_string()))
+            .ok_or_else(|| Error::AddressOutOfRange("the debug write buffer flag has no allocated space".to_owned()))
+            .and_then(|&b| WriteBufferFlags::from_bits(b).ok_or_else(|| Error::SimulationFault("the debug write buffer flag variable is corrupted".to_owned())))
     }
 
-    fn set_current_flags(&self, flags: WriteBufferFlags, avr: &simavr::Avr) -> Result<(), String> {
+    fn set_current_flags(&self, flags: WriteBufferFlags, avr: &simavr::Avr) -> Result<()> {
         let flag_addr: &mut u8 = read_current_memory_address_mut(MemorySpace::Data, self.symbol_for_send_buffer_flags.address, avr)?
-            .get_mut(0).unwrap();
+            .get_mut(0).ok_or_else(|| Error::AddressOutOfRange("the debug write buffer flag has no allocated space".to_owned()))?;
         *flag_addr = flags.bits();
         Ok(())
     }
 
-    fn get_current_write_buffer_value(&self, avr: &simavr::Avr) -> Result<u8, String> {
+    fn get_current_write_buffer_value(&self, avr: &simavr::Avr) -> Result<u8> {
         read_current_memory_address(MemorySpace::Data, self.symbol_for_send_buffer.address, avr)?
             .get(0)
             .cloned()
-            .ok_or_else(|| "the debug write buffer has no allocated space".to_owned())
+            .ok_or_else(|| Error::AddressOutOfRange("the debug write buffer has no allocated space".to_owned()))
     }
 
-    fn set_current_write_buffer_value(&self, new_value: u8, avr: &simavr::Avr) -> Result<(), String> {
+    fn set_current_write_buffer_value(&self, new_value: u8, avr: &simavr::Avr) -> Result<()> {
         let buffer_addr: &mut u8 = read_current_memory_address_mut(MemorySpace::Data, self.symbol_for_send_buffer.address, avr)?
-            .get_mut(0).unwrap();
+            .get_mut(0).ok_or_else(|| Error::AddressOutOfRange("the debug write buffer has no allocated space".to_owned()))?;
         *buffer_addr = new_value;
         Ok(())
     }
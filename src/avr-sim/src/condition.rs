@@ -0,0 +1,264 @@
+//! Conditions that compare watchables against constants or each other.
+//!
+//! Conditions are used to stop the simulation early, or to assert that
+//! the simulated program upholds some invariant.
+
+use crate::debug_info::DebugInfo;
+use crate::{Error, Result, Watch, WatchState, WatchableSymbol, parse_watch};
+use std::fmt;
+
+/// A comparison such as `OUTPUT_VALUE=u8 == 4`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub operator: ComparisonOperator,
+    pub rhs: Operand,
+}
+
+/// One side of a condition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Watch(Watch),
+    Constant(i128),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+/// The operators in the order they must be searched for.
+///
+/// Two character operators come first so that `<=` is not mistaken for `<`.
+const OPERATORS: &[(&str, ComparisonOperator)] = &[
+    ("==", ComparisonOperator::Equal),
+    ("!=", ComparisonOperator::NotEqual),
+    ("<=", ComparisonOperator::LessThanOrEqual),
+    (">=", ComparisonOperator::GreaterThanOrEqual),
+    ("<", ComparisonOperator::LessThan),
+    (">", ComparisonOperator::GreaterThan),
+];
+
+impl Condition {
+    /// Checks whether the condition currently holds.
+    ///
+    /// Returns `None` if an operand is a local variable that cannot be read right now.
+    pub fn evaluate(&self, avr: &simavr::Avr, watchable_symbols: &[WatchableSymbol], debug_info: &DebugInfo) -> Result<Option<bool>> {
+        let lhs = self.lhs.current_value(avr, watchable_symbols, debug_info)?;
+        let rhs = self.rhs.current_value(avr, watchable_symbols, debug_info)?;
+
+        Ok(match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => Some(self.operator.compare(lhs, rhs)),
+            _ => None,
+        })
+    }
+
+    /// Describes the current values of all watched operands, for use in diagnostics.
+    pub fn describe_operands(&self, avr: &simavr::Avr, watchable_symbols: &[WatchableSymbol], debug_info: &DebugInfo) -> String {
+        let descriptions = [&self.lhs, &self.rhs].iter().filter_map(|operand| match operand {
+            Operand::Watch(watch) => {
+                let value = match watch.current_value(avr, watchable_symbols, debug_info) {
+                    Ok(value) => value.to_string(),
+                    Err(e) => format!("<{}>", e),
+                };
+                Some(format!("{} = {}", watch.location(), value))
+            },
+            Operand::Constant(..) => None,
+        }).collect::<Vec<_>>();
+
+        descriptions.join(", ")
+    }
+
+    /// Gets all watches referred to by the condition.
+    pub fn watches(&self) -> impl Iterator<Item=&Watch> {
+        [&self.lhs, &self.rhs].to_vec().into_iter().filter_map(|operand| match operand {
+            Operand::Watch(watch) => Some(watch),
+            Operand::Constant(..) => None,
+        })
+    }
+}
+
+impl Operand {
+    fn current_value(&self, avr: &simavr::Avr, watchable_symbols: &[WatchableSymbol], debug_info: &DebugInfo) -> Result<Option<i128>> {
+        match *self {
+            Operand::Watch(ref watch) => {
+                let state = watch.current_value(avr, watchable_symbols, debug_info)?;
+                if state.is_available() { integer_value(&state).map(Some) } else { Ok(None) }
+            },
+            Operand::Constant(value) => Ok(Some(value)),
+        }
+    }
+}
+
+impl ComparisonOperator {
+    fn compare(&self, lhs: i128, rhs: i128) -> bool {
+        match *self {
+            ComparisonOperator::Equal => lhs == rhs,
+            ComparisonOperator::NotEqual => lhs != rhs,
+            ComparisonOperator::LessThan => lhs < rhs,
+            ComparisonOperator::LessThanOrEqual => lhs <= rhs,
+            ComparisonOperator::GreaterThan => lhs > rhs,
+            ComparisonOperator::GreaterThanOrEqual => lhs >= rhs,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        OPERATORS.iter().find(|(_, op)| op == self).map(|(s, _)| *s).unwrap()
+    }
+}
+
+/// Interprets a watched value as an integer so that it can be compared.
+fn integer_value(state: &WatchState) -> Result<i128> {
+    use std::convert::TryFrom;
+
+    match *state {
+        WatchState::Char(c) => Ok(c as i128),
+        WatchState::U8(i) => Ok(i.into()),
+        WatchState::U16(i) => Ok(i.into()),
+        WatchState::U32(i) => Ok(i.into()),
+        WatchState::U64(i) => Ok(i.into()),
+        WatchState::U128(i) => i128::try_from(i).map_err(|_| Error::CommandLine(format!("the value {} is too large to be compared", i))),
+        WatchState::I8(i) => Ok(i.into()),
+        WatchState::I16(i) => Ok(i.into()),
+        WatchState::I32(i) => Ok(i.into()),
+        WatchState::I64(i) => Ok(i.into()),
+        WatchState::I128(i) => Ok(i),
+        WatchState::HighLowBit(b) => Ok(b as i128),
+        WatchState::IoRegisterStatus(r) => Ok(r.into()),
+        WatchState::Array { .. } => Err(Error::CommandLine("arrays cannot be compared".to_owned())),
+        WatchState::OutOfScope | WatchState::OptimizedOut => Err(Error::CommandLine(format!("{} cannot be compared", state))),
+    }
+}
+
+/// Parses a condition in the form `<OPERAND> <OPERATOR> <OPERAND>`.
+pub fn parse_condition(s: &str) -> Result<Condition> {
+    let s = s.trim();
+
+    let (index, operator_str, operator) = OPERATORS.iter().filter_map(|&(operator_str, operator)| {
+        // The '>' in the '->' of a watch expression is not an operator.
+        s.match_indices(operator_str).map(|(index, _)| index)
+            .find(|&index| !(operator_str.starts_with('>') && s[..index].ends_with('-')))
+            .map(|index| (index, operator_str, operator))
+    }).next().ok_or_else(|| Error::CommandLine(format!("expected condition to contain a comparison operator (one of {}): {}",
+                                                      OPERATORS.iter().map(|(s, _)| *s).collect::<Vec<_>>().join(", "), s)))?;
+
+    let (lhs, rhs) = (&s[..index], &s[index + operator_str.len()..]);
+
+    Ok(Condition {
+        lhs: parse_operand(lhs)?,
+        operator,
+        rhs: parse_operand(rhs)?,
+    })
+}
+
+fn parse_operand(s: &str) -> Result<Operand> {
+    let s = s.trim();
+
+    if s.is_empty() {
+        return Err(Error::CommandLine("expected an operand on both sides of the comparison operator".to_owned()));
+    }
+
+    if let Some(constant) = parse_constant(s) {
+        return Ok(Operand::Constant(constant));
+    }
+
+    let mut watches = parse_watch(s)?;
+
+    match watches.len() {
+        1 => Ok(Operand::Watch(watches.remove(0))),
+        _ => Err(Error::CommandLine(format!("the watchable '{}' refers to more than one value and so cannot be compared (try 'io-port', 'io-pin' or 'io-ddr' instead)", s))),
+    }
+}
+
+fn parse_constant(s: &str) -> Option<i128> {
+    let (is_negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+
+    let magnitude = match digits {
+        "HIGH" | "high" | "true" => Some(1),
+        "LOW" | "low" | "false" => Some(0),
+        _ if digits.starts_with("0x") || digits.starts_with("0X") => i128::from_str_radix(&digits[2..], 16).ok(),
+        _ if digits.starts_with("0b") || digits.starts_with("0B") => i128::from_str_radix(&digits[2..], 2).ok(),
+        _ if digits.len() == 3 && digits.starts_with('\'') && digits.ends_with('\'') => digits.chars().nth(1).map(|c| c as i128),
+        _ if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) => digits.parse().ok(),
+        _ => None,
+    }?;
+
+    Some(if is_negative { -magnitude } else { magnitude })
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {} {}", self.lhs, self.operator.symbol(), self.rhs)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Watch(ref watch) => write!(fmt, "{}", watch.location()),
+            Operand::Constant(value) => write!(fmt, "{}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DataType;
+
+    #[test]
+    fn can_parse_symbol_against_constant() {
+        assert_eq!(Ok(Condition {
+            lhs: Operand::Watch(Watch::Symbol { name: "OUTPUT_VALUE".to_owned(), data_type: DataType::U8 }),
+            operator: ComparisonOperator::Equal,
+            rhs: Operand::Constant(4),
+        }), parse_condition("OUTPUT_VALUE=u8 == 4"));
+
+        assert_eq!(Ok(Condition {
+            lhs: Operand::Watch(Watch::Symbol { name: "COUNTER".to_owned(), data_type: DataType::I16 }),
+            operator: ComparisonOperator::LessThanOrEqual,
+            rhs: Operand::Constant(-0x10),
+        }), parse_condition("COUNTER=i16<=-0x10"));
+    }
+
+    #[test]
+    fn can_parse_io_pin_against_level() {
+        assert_eq!(Ok(Condition {
+            lhs: Operand::Watch(Watch::IoPort { port_letter: 'B', port_index: Some(3) }),
+            operator: ComparisonOperator::NotEqual,
+            rhs: Operand::Constant(1),
+        }), parse_condition("io-port=B3 != HIGH"));
+    }
+
+    #[test]
+    fn can_parse_watch_against_watch() {
+        assert_eq!(Ok(Condition {
+            lhs: Operand::Watch(Watch::Symbol { name: "A".to_owned(), data_type: DataType::U16 }),
+            operator: ComparisonOperator::GreaterThan,
+            rhs: Operand::Watch(Watch::Symbol { name: "B".to_owned(), data_type: DataType::U16 }),
+        }), parse_condition("A=u16 > B=u16"));
+    }
+
+    #[test]
+    fn can_parse_pointer_member_against_constant() {
+        let condition = parse_condition("node->value > 3").unwrap();
+
+        assert_eq!(ComparisonOperator::GreaterThan, condition.operator);
+        assert_eq!("node->value", condition.lhs.to_string());
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        assert!(parse_condition("OUTPUT_VALUE=u8").is_err());
+        assert!(parse_condition("== 4").is_err());
+        assert!(parse_condition("io=B3 == HIGH").is_err());
+    }
+}
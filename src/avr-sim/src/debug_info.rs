@@ -0,0 +1,478 @@
+//! Maps program addresses back to functions and source lines.
+//!
+//! Function and object extents come from the ELF symbol table. Source lines,
+//! declared types and local variables come from DWARF if the executable was
+//! compiled with `-g`.
+
+use crate::locals::{self, LocalVariable};
+use crate::types::{TypeId, Types, UnitTypes};
+use crate::Error;
+use object::read::{Object, ObjectSection};
+use std::{borrow, collections::BTreeMap, fmt, ops::Range, path::PathBuf};
+
+/// ELF files for AVR place the data space at this virtual address.
+pub const DATA_SPACE_ELF_OFFSET: u64 = 0x800000;
+/// ELF files for AVR place the EEPROM at this virtual address.
+pub const EEPROM_ELF_OFFSET: u64 = 0x810000;
+
+#[derive(Clone, Debug, Default)]
+pub struct DebugInfo {
+    functions: Vec<SizedSymbol>,
+    objects: Vec<SizedSymbol>,
+    lines: Vec<(Range<u64>, SourceLocation)>,
+    /// Global variables and functions, by symbol name.
+    declarations: BTreeMap<String, Declaration>,
+    locals: Vec<LocalVariable>,
+    /// The types of every global and local variable.
+    types: Types,
+    /// The raw '.debug_frame' section, used to find the call frame address.
+    debug_frame: Vec<u8>,
+    /// Executable sections, as program space address ranges.
+    code_sections: Vec<Section>,
+    /// Read-only sections that are mapped into the data space.
+    read_only_data_sections: Vec<Section>,
+}
+
+/// A loaded ELF section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// The addresses relative to the start of the memory space.
+    pub addresses: Range<u32>,
+}
+
+/// A symbol that occupies a known range of addresses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizedSymbol {
+    pub name: String,
+    /// The address relative to the start of the memory space.
+    pub address: u32,
+    pub size: u32,
+}
+
+/// What DWARF says about a global variable or function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    /// The type as it would be written in C.
+    pub type_name: String,
+    /// Where the variable or function is declared.
+    pub location: Option<SourceLocation>,
+    /// The type of a variable, for following pointers and members.
+    pub type_id: Option<TypeId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub line: u64,
+}
+
+impl DebugInfo {
+    /// Reads the symbol table and line information from an ELF file.
+    pub fn parse(elf_data: &[u8]) -> Result<Self, Error> {
+        let object = object::read::File::parse(elf_data).map_err(|e| Error::Elf(e.to_string()))?;
+
+        let mut functions = Vec::new();
+        let mut objects = Vec::new();
+
+        for (_, symbol) in object.symbols() {
+            let name = match symbol.name() {
+                Some(name) if !name.trim().is_empty() && symbol.size() > 0 => name.trim().to_owned(),
+                _ => continue,
+            };
+
+            match symbol.kind() {
+                object::SymbolKind::Text => functions.push(SizedSymbol {
+                    name, address: symbol.address() as u32, size: symbol.size() as u32,
+                }),
+                object::SymbolKind::Data if symbol.address() >= DATA_SPACE_ELF_OFFSET => objects.push(SizedSymbol {
+                    name, address: (symbol.address() - DATA_SPACE_ELF_OFFSET) as u32, size: symbol.size() as u32,
+                }),
+                _ => (),
+            }
+        }
+
+        let mut code_sections = Vec::new();
+        let mut read_only_data_sections = Vec::new();
+
+        for section in object.sections() {
+            let (start, end) = (section.address(), section.address() + section.size());
+            let name = section.name().unwrap_or("").to_owned();
+
+            match section.kind() {
+                object::SectionKind::Text if start < DATA_SPACE_ELF_OFFSET => code_sections.push(Section {
+                    name, addresses: start as u32..end as u32,
+                }),
+                object::SectionKind::ReadOnlyData | object::SectionKind::ReadOnlyString if start >= DATA_SPACE_ELF_OFFSET => read_only_data_sections.push(Section {
+                    name, addresses: (start - DATA_SPACE_ELF_OFFSET) as u32..(end - DATA_SPACE_ELF_OFFSET) as u32,
+                }),
+                _ => (),
+            }
+        }
+
+        functions.sort_by_key(|s| s.address);
+        objects.sort_by_key(|s| s.address);
+
+        let mut dwarf_info = DwarfInfo::default();
+        parse_dwarf(&object, &mut dwarf_info).map_err(|e| Error::Elf(format!("could not read DWARF debug information: {}", e)))?;
+
+        let DwarfInfo { mut lines, declarations, locals, types } = dwarf_info;
+        lines.sort_by_key(|(range, _)| range.start);
+
+        let debug_frame = object.section_by_name(".debug_frame").and_then(|s| s.uncompressed_data().ok()).map(|d| d.into_owned()).unwrap_or_default();
+
+        Ok(DebugInfo { functions, objects, lines, declarations, locals, types, debug_frame, code_sections, read_only_data_sections })
+    }
+
+    /// Gets the function whose code contains the given program address.
+    pub fn function_at(&self, pc: u32) -> Option<&SizedSymbol> {
+        self.functions.iter().find(|f| f.contains(pc))
+    }
+
+    /// Gets the data object that contains the given data space address.
+    pub fn object_at(&self, address: u32) -> Option<&SizedSymbol> {
+        self.objects.iter().find(|o| o.contains(address))
+    }
+
+    /// Gets the executable section containing the given program address.
+    pub fn code_section_at(&self, pc: u32) -> Option<&Section> {
+        self.code_sections.iter().find(|s| s.addresses.contains(&pc))
+    }
+
+    /// Gets the read-only section mapped at the given data space address.
+    pub fn read_only_data_section_at(&self, address: u32) -> Option<&Section> {
+        self.read_only_data_sections.iter().find(|s| s.addresses.contains(&address))
+    }
+
+    /// Gets the source line that the given program address was compiled from.
+    pub fn line_at(&self, pc: u32) -> Option<&SourceLocation> {
+        let pc = pc as u64;
+        self.lines.iter().find(|(range, _)| range.contains(&pc)).map(|(_, location)| location)
+    }
+
+    /// Gets the declaration of a global variable or function by its symbol name.
+    pub fn declaration(&self, symbol_name: &str) -> Option<&Declaration> {
+        self.declarations.get(symbol_name)
+    }
+
+    /// Finds a local variable or parameter of a function, by either its plain or mangled name.
+    ///
+    /// If there is more than one variable with the name, the one in the outermost scope is used.
+    pub fn local_in_function(&self, function: &str, name: &str) -> Option<&LocalVariable> {
+        locals::outermost_in_function(&self.locals, function, name)
+    }
+
+    /// Finds the local variable or parameter with the given name that is visible from a source line.
+    ///
+    /// The path only needs to match the end of the path recorded in the debug information.
+    pub fn local_at_line(&self, path: &str, line: u64, name: &str) -> Option<&LocalVariable> {
+        let pcs = self.lines.iter()
+            .filter(|(_, location)| location.line == line && location.path.ends_with(path))
+            .map(|(range, _)| range.start as u32)
+            .collect::<Vec<_>>();
+
+        locals::innermost_at(&self.locals, name, &pcs)
+    }
+
+    /// Gets the types referred to by declarations and locals.
+    pub fn types(&self) -> &Types {
+        &self.types
+    }
+
+    pub(crate) fn debug_frame(&self) -> &[u8] {
+        &self.debug_frame
+    }
+
+    /// Describes a program address for use in diagnostics.
+    pub fn describe_pc(&self, pc: u32) -> String {
+        let mut description = format!("PC 0x{:04x}", pc);
+
+        if let Some(function) = self.function_at(pc) {
+            description += &format!(" in '{}'", function.name);
+        }
+        if let Some(location) = self.line_at(pc) {
+            description += &format!(" at {}", location);
+        }
+
+        description
+    }
+
+    /// Describes a data space address for use in diagnostics.
+    pub fn describe_data_address(&self, address: u32) -> String {
+        match self.object_at(address) {
+            Some(object) if object.address == address => format!("0x{:04x} ('{}')", address, object.name),
+            Some(object) => format!("0x{:04x} ('{}'+{})", address, object.name, address - object.address),
+            None => format!("0x{:04x}", address),
+        }
+    }
+}
+
+impl SizedSymbol {
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.address && address < self.address + self.size
+    }
+}
+
+pub(crate) type Reader<'a> = gimli::EndianSlice<'a, gimli::RunTimeEndian>;
+
+#[derive(Default)]
+struct DwarfInfo {
+    lines: Vec<(Range<u64>, SourceLocation)>,
+    declarations: BTreeMap<String, Declaration>,
+    locals: Vec<LocalVariable>,
+    types: Types,
+}
+
+fn parse_dwarf(object: &object::read::File, info: &mut DwarfInfo) -> Result<(), gimli::Error> {
+    let endian = if object.is_little_endian() { gimli::RunTimeEndian::Little } else { gimli::RunTimeEndian::Big };
+
+    let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
+        Ok(object.section_by_name(id.name()).and_then(|s| s.uncompressed_data().ok()).unwrap_or(borrow::Cow::Borrowed(&[])))
+    };
+    let load_supplementary_section = |_| Ok(borrow::Cow::Borrowed(&[][..]));
+
+    let dwarf_sections = gimli::Dwarf::load(&load_section, &load_supplementary_section)?;
+    let dwarf = dwarf_sections.borrow(|section| gimli::EndianSlice::new(section, endian));
+
+    let mut units = dwarf.units();
+
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+
+        parse_line_program(&dwarf, &unit, &mut info.lines)?;
+        let mut types = UnitTypes::new(&mut info.types);
+
+        parse_declarations(&dwarf, &unit, &mut types, &mut info.declarations)?;
+        locals::parse(&dwarf, &unit, &mut types, &mut info.locals)?;
+    }
+
+    Ok(())
+}
+
+fn parse_line_program(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    lines: &mut Vec<(Range<u64>, SourceLocation)>,
+) -> Result<(), gimli::Error> {
+    let program = match unit.line_program.clone() {
+        Some(program) => program,
+        None => return Ok(()),
+    };
+
+    let mut rows = program.rows();
+    let mut previous_row: Option<(u64, SourceLocation)> = None;
+
+    while let Some((header, row)) = rows.next_row()? {
+        if let Some((start, location)) = previous_row.take() {
+            if row.address() > start {
+                lines.push((start..row.address(), location));
+            }
+        }
+
+        if row.end_sequence() {
+            continue;
+        }
+
+        let path = match row.file(header) {
+            Some(file) => file_path(dwarf, unit, header, file)?,
+            None => PathBuf::new(),
+        };
+
+        previous_row = Some((row.address(), SourceLocation { path, line: row.line().unwrap_or(0) }));
+    }
+
+    Ok(())
+}
+
+fn file_path(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    header: &gimli::LineProgramHeader<Reader>,
+    file: &gimli::FileEntry<Reader>,
+) -> Result<PathBuf, gimli::Error> {
+    let mut path = PathBuf::new();
+
+    if let Some(directory) = file.directory(header) {
+        path.push(dwarf.attr_string(unit, directory)?.to_string_lossy().as_ref());
+    }
+    path.push(dwarf.attr_string(unit, file.path_name())?.to_string_lossy().as_ref());
+
+    Ok(path)
+}
+
+/// Reads the global variables and functions declared at the top level of a compilation unit.
+fn parse_declarations(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    types: &mut UnitTypes,
+    declarations: &mut BTreeMap<String, Declaration>,
+) -> Result<(), gimli::Error> {
+    let mut entries = unit.entries();
+    let mut depth = 0;
+
+    while let Some((depth_delta, entry)) = entries.next_dfs()? {
+        depth += depth_delta;
+
+        let is_global = depth == 1 && (entry.tag() == gimli::DW_TAG_variable || entry.tag() == gimli::DW_TAG_subprogram);
+        if !is_global || entry.attr_value(gimli::DW_AT_declaration)?.is_some() {
+            continue;
+        }
+
+        let attribute = |name| attribute(unit, entry, name);
+
+        // Symbol names are mangled in C++.
+        let name = match attribute(gimli::DW_AT_linkage_name)?.or(attribute(gimli::DW_AT_MIPS_linkage_name)?).or(attribute(gimli::DW_AT_name)?) {
+            Some(name) => dwarf.attr_string(unit, name)?.to_string_lossy().into_owned(),
+            None => continue,
+        };
+
+        let type_offset = match attribute(gimli::DW_AT_type)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+        let return_or_variable_type = match type_offset {
+            Some(offset) => type_name(dwarf, unit, offset)?,
+            None => "void".to_owned(),
+        };
+        let (type_name, type_id) = if entry.tag() == gimli::DW_TAG_subprogram {
+            (format!("{} ({})", return_or_variable_type, parameter_type_names(dwarf, unit, entry.offset())?.join(", ")), None)
+        } else {
+            let type_id = match type_offset {
+                Some(offset) => Some(types.resolve(dwarf, unit, offset)?),
+                None => None,
+            };
+            (return_or_variable_type, type_id)
+        };
+
+        let location = declaration_location(dwarf, unit, entry)?;
+
+        declarations.insert(name, Declaration { type_name, location, type_id });
+    }
+
+    Ok(())
+}
+
+/// Gets an attribute of an entry, falling back to the entry it was specified by or is an instance of.
+///
+/// C++ definitions outside of their class or namespace refer back to their
+/// declaration, and out of line copies of inline functions refer back to the
+/// abstract function.
+pub(crate) fn attribute<'a>(
+    unit: &gimli::Unit<Reader<'a>>,
+    entry: &gimli::DebuggingInformationEntry<Reader<'a>>,
+    name: gimli::DwAt,
+) -> Result<Option<gimli::AttributeValue<Reader<'a>>>, gimli::Error> {
+    if let Some(value) = entry.attr_value(name)? {
+        return Ok(Some(value));
+    }
+
+    for &origin in [gimli::DW_AT_specification, gimli::DW_AT_abstract_origin].iter() {
+        if let Some(gimli::AttributeValue::UnitRef(offset)) = entry.attr_value(origin)? {
+            return attribute(unit, &unit.entry(offset)?, name);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Gets the source line that an entry was declared on.
+pub(crate) fn declaration_location(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+) -> Result<Option<SourceLocation>, gimli::Error> {
+    let (file, line, program) = match (attribute(unit, entry, gimli::DW_AT_decl_file)?, attribute(unit, entry, gimli::DW_AT_decl_line)?, unit.line_program.as_ref()) {
+        (Some(gimli::AttributeValue::FileIndex(index)), Some(line), Some(program)) => match program.header().file(index) {
+            Some(file) => (file, line, program),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(SourceLocation {
+        path: file_path(dwarf, unit, program.header(), file)?,
+        line: line.udata_value().unwrap_or(0),
+    }))
+}
+
+/// Formats a DWARF type as it would be written in C.
+fn type_name(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    offset: gimli::UnitOffset,
+) -> Result<String, gimli::Error> {
+    let entry = unit.entry(offset)?;
+
+    let name = match entry.attr_value(gimli::DW_AT_name)? {
+        Some(name) => Some(dwarf.attr_string(unit, name)?.to_string_lossy().into_owned()),
+        None => None,
+    };
+    let inner_type_name = || match entry.attr_value(gimli::DW_AT_type)? {
+        Some(gimli::AttributeValue::UnitRef(offset)) => type_name(dwarf, unit, offset),
+        _ => Ok("void".to_owned()),
+    };
+    let tagged_name = |keyword: &str| format!("{} {}", keyword, name.clone().unwrap_or_else(|| "<anonymous>".to_owned()));
+
+    Ok(match entry.tag() {
+        gimli::DW_TAG_structure_type => tagged_name("struct"),
+        gimli::DW_TAG_class_type => tagged_name("class"),
+        gimli::DW_TAG_union_type => tagged_name("union"),
+        gimli::DW_TAG_enumeration_type => tagged_name("enum"),
+        gimli::DW_TAG_pointer_type => format!("{} *", inner_type_name()?),
+        gimli::DW_TAG_reference_type => format!("{} &", inner_type_name()?),
+        gimli::DW_TAG_const_type => format!("const {}", inner_type_name()?),
+        gimli::DW_TAG_volatile_type => format!("volatile {}", inner_type_name()?),
+        gimli::DW_TAG_subroutine_type => format!("{} ({})", inner_type_name()?, parameter_type_names(dwarf, unit, offset)?.join(", ")),
+        gimli::DW_TAG_array_type => {
+            let mut dimensions = String::new();
+            let mut tree = unit.entries_tree(Some(offset))?;
+            let mut children = tree.root()?.children();
+
+            while let Some(child) = children.next()? {
+                if child.entry().tag() != gimli::DW_TAG_subrange_type {
+                    continue;
+                }
+
+                let count = match (child.entry().attr_value(gimli::DW_AT_count)?, child.entry().attr_value(gimli::DW_AT_upper_bound)?) {
+                    (Some(count), _) => count.udata_value(),
+                    (None, Some(upper_bound)) => upper_bound.udata_value().map(|b| b + 1),
+                    (None, None) => None,
+                };
+                dimensions += &count.map(|c| format!("[{}]", c)).unwrap_or_else(|| "[]".to_owned());
+            }
+
+            format!("{}{}", inner_type_name()?, dimensions)
+        },
+        _ => name.unwrap_or_else(|| "<unknown>".to_owned()),
+    })
+}
+
+/// Gets the types of the parameters of a function or function type.
+fn parameter_type_names(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    offset: gimli::UnitOffset,
+) -> Result<Vec<String>, gimli::Error> {
+    let mut names = Vec::new();
+    let mut tree = unit.entries_tree(Some(offset))?;
+    let mut children = tree.root()?.children();
+
+    while let Some(child) = children.next()? {
+        match child.entry().tag() {
+            gimli::DW_TAG_formal_parameter => match child.entry().attr_value(gimli::DW_AT_type)? {
+                Some(gimli::AttributeValue::UnitRef(offset)) => names.push(type_name(dwarf, unit, offset)?),
+                _ => names.push("<unknown>".to_owned()),
+            },
+            gimli::DW_TAG_unspecified_parameters => names.push("...".to_owned()),
+            _ => (),
+        }
+    }
+
+    Ok(names)
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}", self.path.display(), self.line)
+    }
+}
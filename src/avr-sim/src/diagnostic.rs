@@ -0,0 +1,34 @@
+//! Problems found in the simulated program by the optional checkers.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    Warning,
+    Error,
+}
+
+impl Diagnostic {
+    pub fn warning(message: String) -> Self {
+        Diagnostic { kind: DiagnosticKind::Warning, message }
+    }
+
+    pub fn error(message: String) -> Self {
+        Diagnostic { kind: DiagnosticKind::Error, message }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            DiagnosticKind::Warning => write!(fmt, "warning: {}", self.message),
+            DiagnosticKind::Error => write!(fmt, "error: {}", self.message),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Errors that can occur while setting up or running a simulation.
+
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The process exit code used when the simulation ran but a test failed,
+/// for example because an assertion did not hold.
+pub const TEST_FAILURE_EXIT_CODE: i32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The command line, or a watch or condition on it, is malformed.
+    CommandLine(String),
+    /// The executable could not be read or is not a valid AVR ELF file.
+    Elf(String),
+    /// A symbol that was asked for does not exist in the executable.
+    MissingSymbol(String),
+    /// An address lies outside of the memory space it refers to.
+    AddressOutOfRange(String),
+    /// simavr could not answer an ioctl, usually because the MCU lacks the peripheral.
+    Ioctl(String),
+    /// The simulated CPU crashed, or the simulation state could not be used.
+    SimulationFault(String),
+}
+
+impl Error {
+    /// Creates an error for a symbol that is not in the executable.
+    pub fn missing_symbol(name: &str) -> Self {
+        Error::MissingSymbol(format!("the symbol '{}' does not exist in the ELF file, or the ELF file contains no debug information", name))
+    }
+
+    /// Gets the process exit code that `avr-sim` exits with when it encounters this error.
+    ///
+    /// These are distinct from [`TEST_FAILURE_EXIT_CODE`] so that test runners can
+    /// tell a broken test setup apart from a failing test.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::CommandLine(..) => 2,
+            Error::Elf(..) => 3,
+            Error::MissingSymbol(..) => 4,
+            Error::AddressOutOfRange(..) => 5,
+            Error::Ioctl(..) => 6,
+            Error::SimulationFault(..) => 7,
+        }
+    }
+
+    /// Prefixes the error message with what was being done when the error occurred.
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        let prefix = |message: String| format!("{}: {}", context, message);
+
+        match self {
+            Error::CommandLine(m) => Error::CommandLine(prefix(m)),
+            Error::Elf(m) => Error::Elf(prefix(m)),
+            Error::MissingSymbol(m) => Error::MissingSymbol(prefix(m)),
+            Error::AddressOutOfRange(m) => Error::AddressOutOfRange(prefix(m)),
+            Error::Ioctl(m) => Error::Ioctl(prefix(m)),
+            Error::SimulationFault(m) => Error::SimulationFault(prefix(m)),
+        }
+    }
+
+    fn message(&self) -> &str {
+        match *self {
+            Error::CommandLine(ref m) |
+                Error::Elf(ref m) |
+                Error::MissingSymbol(ref m) |
+                Error::AddressOutOfRange(ref m) |
+                Error::Ioctl(ref m) |
+                Error::SimulationFault(ref m) => m,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        self.message().fmt(fmt)
+    }
+}
+
+impl std::error::Error for Error { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn context_keeps_the_kind_of_error() {
+        let error = Error::Ioctl("no such port".to_owned()).context("could not read 'PINB'");
+
+        assert_eq!(Error::Ioctl("could not read 'PINB': no such port".to_owned()), error);
+        assert_eq!(6, error.exit_code());
+    }
+}
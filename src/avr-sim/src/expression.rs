@@ -0,0 +1,552 @@
+//! Watch expressions that follow pointers and members, such as `*ptr`,
+//! `buffer[3]`, `point.x`, `node->next` and `(u16)SYM`.
+//!
+//! Types come from DWARF where it is available. Casts give a type to
+//! values that have none, and override the one that DWARF gives.
+
+use crate::locals::LocalValue;
+use crate::types::{Type, TypeId};
+use crate::debug_info::DebugInfo;
+use crate::{DataType, Error, MemorySpace, Pointer, Result, WatchState, WatchableSymbol};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum Expression {
+    /// A global variable, by its symbol name.
+    Symbol(String),
+    /// A local variable or parameter, as in `callStuff::d`.
+    Local { function: String, name: String },
+    /// `*EXPR`
+    Dereference(Box<Expression>),
+    /// `EXPR[INDEX]`
+    Index(Box<Expression>, u32),
+    /// `EXPR.MEMBER`
+    Member(Box<Expression>, String),
+    /// `EXPR->MEMBER`
+    PointerMember(Box<Expression>, String),
+    /// `(TYPE)EXPR` or `(TYPE *)EXPR`
+    Cast(CastType, Box<Expression>),
+}
+
+/// The type in a cast.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub struct CastType {
+    pub data_type: DataType,
+    pub is_pointer: bool,
+}
+
+/// Where the value of an expression is, once every pointer has been followed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Place {
+    Memory { space: MemorySpace, address: u32 },
+    /// The value of a local that lives in registers, or is a constant.
+    Bytes(Vec<u8>),
+    /// A local that cannot be read at the moment.
+    Unavailable(WatchState),
+}
+
+/// The type of an expression, as far as it is known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ExpressionType {
+    Dwarf(TypeId),
+    Scalar(DataType),
+    PointerTo(DataType),
+    Unknown,
+}
+
+/// A place and the type of the value in it.
+struct Value {
+    place: Place,
+    ty: ExpressionType,
+}
+
+/// The state needed to evaluate an expression.
+struct Context<'a> {
+    avr: &'a simavr::Avr,
+    watchable_symbols: &'a [WatchableSymbol],
+    debug_info: &'a DebugInfo,
+}
+
+impl Expression {
+    /// Finds where the value of the expression currently is, and the type to read it as.
+    ///
+    /// The type is `None` if neither DWARF nor a cast says what it is.
+    pub(crate) fn locate(&self,
+        avr: &simavr::Avr,
+        watchable_symbols: &[WatchableSymbol],
+        debug_info: &DebugInfo,
+    ) -> Result<(Place, Option<DataType>)> {
+        let context = Context { avr, watchable_symbols, debug_info };
+        let value = self.evaluate(&context)?;
+
+        let data_type = match value.ty {
+            ExpressionType::Dwarf(id) => debug_info.types().data_type(id),
+            ExpressionType::Scalar(ref data_type) => Some(data_type.clone()),
+            ExpressionType::PointerTo(..) => Some(DataType::U16),
+            ExpressionType::Unknown => None,
+        };
+
+        Ok((value.place, data_type))
+    }
+
+    /// Checks that every symbol and local in the expression exists.
+    pub(crate) fn check_resolved(&self, watchable_symbols: &[WatchableSymbol], debug_info: &DebugInfo) -> Result<()> {
+        match *self {
+            Expression::Symbol(ref name) => match watchable_symbols.iter().any(|s| s.name == *name) {
+                true => Ok(()),
+                false => Err(Error::missing_symbol(name)),
+            },
+            Expression::Local { ref function, ref name } => find_local(function, name, debug_info).map(|_| ()),
+            Expression::Dereference(ref inner) | Expression::Index(ref inner, _) |
+                Expression::Member(ref inner, _) | Expression::PointerMember(ref inner, _) |
+                Expression::Cast(_, ref inner) => inner.check_resolved(watchable_symbols, debug_info),
+        }
+    }
+
+    fn evaluate(&self, context: &Context) -> Result<Value> {
+        let types = context.debug_info.types();
+
+        match *self {
+            Expression::Symbol(ref name) => {
+                let symbol = context.watchable_symbols.iter().find(|s| s.name == *name).ok_or_else(|| Error::missing_symbol(name))?;
+                let ty = match context.debug_info.declaration(name).and_then(|d| d.type_id) {
+                    Some(id) => ExpressionType::Dwarf(id),
+                    None => ExpressionType::Unknown,
+                };
+
+                Ok(Value { place: Place::Memory { space: symbol.memory_space, address: symbol.address.address }, ty })
+            },
+            Expression::Local { ref function, ref name } => {
+                let local = find_local(function, name, context.debug_info)?;
+                let size = local.type_id.and_then(|id| types.size_of(id)).unwrap_or(2);
+
+                let place = match local.read(size, context.avr, context.debug_info)? {
+                    LocalValue::OutOfScope => Place::Unavailable(WatchState::OutOfScope),
+                    LocalValue::OptimizedOut => Place::Unavailable(WatchState::OptimizedOut),
+                    LocalValue::Memory(address) => Place::Memory { space: MemorySpace::Data, address },
+                    LocalValue::Bytes(bytes) => Place::Bytes(bytes),
+                };
+                let ty = match local.type_id {
+                    Some(id) => ExpressionType::Dwarf(id),
+                    None => ExpressionType::Unknown,
+                };
+
+                Ok(Value { place, ty })
+            },
+            Expression::Dereference(ref inner) => {
+                let pointer = inner.evaluate(context)?;
+
+                let pointee = match pointer.ty {
+                    ExpressionType::Dwarf(id) => match *types.get(id) {
+                        Type::Pointer { pointee: Some(pointee) } => ExpressionType::Dwarf(pointee),
+                        Type::Pointer { pointee: None } => ExpressionType::Unknown,
+                        // An array decays to a pointer to its first element.
+                        Type::Array { element, .. } => return Ok(Value { place: pointer.place, ty: ExpressionType::Dwarf(element) }),
+                        _ => return Err(not_a_pointer(inner)),
+                    },
+                    ExpressionType::PointerTo(ref data_type) => ExpressionType::Scalar(data_type.clone()),
+                    // Pointers without debug info are still pointers, but what they point at needs a cast.
+                    ExpressionType::Unknown => ExpressionType::Unknown,
+                    ExpressionType::Scalar(..) => return Err(not_a_pointer(inner)),
+                };
+
+                Ok(Value { place: read_pointer(&pointer.place, context)?, ty: pointee })
+            },
+            Expression::Index(ref inner, index) => {
+                let array = inner.evaluate(context)?;
+
+                let (base, element, element_size) = match array.ty {
+                    ExpressionType::Dwarf(id) => match *types.get(id) {
+                        Type::Array { element, .. } => (array.place, ExpressionType::Dwarf(element), types.size_of(element)),
+                        Type::Pointer { pointee: Some(pointee) } => (read_pointer(&array.place, context)?, ExpressionType::Dwarf(pointee), types.size_of(pointee)),
+                        _ => return Err(not_indexable(inner)),
+                    },
+                    ExpressionType::PointerTo(ref data_type) => (read_pointer(&array.place, context)?, ExpressionType::Scalar(data_type.clone()), Some(data_type.minimum_size())),
+                    ExpressionType::Scalar(..) | ExpressionType::Unknown => return Err(not_indexable(inner)),
+                };
+
+                let element_size = element_size.ok_or_else(|| Error::CommandLine(format!("the elements of '{}' have no known size", inner)))?;
+                let byte_offset = index.checked_mul(element_size).ok_or_else(|| past_end_of_memory(self))?;
+                Ok(Value { place: offset(base, byte_offset, self)?, ty: element })
+            },
+            Expression::Member(ref inner, ref member_name) => {
+                let value = inner.evaluate(context)?;
+                member(value, inner, member_name, context)
+            },
+            Expression::PointerMember(ref inner, ref member_name) => {
+                let value = Expression::Dereference(inner.clone()).evaluate(context)?;
+                member(value, inner, member_name, context)
+            },
+            Expression::Cast(ref cast_type, ref inner) => {
+                let value = inner.evaluate(context)?;
+                let ty = if cast_type.is_pointer {
+                    ExpressionType::PointerTo(cast_type.data_type.clone())
+                } else {
+                    ExpressionType::Scalar(cast_type.data_type.clone())
+                };
+
+                Ok(Value { place: value.place, ty })
+            },
+        }
+    }
+}
+
+fn find_local<'d>(function: &str, name: &str, debug_info: &'d DebugInfo) -> Result<&'d crate::locals::LocalVariable> {
+    debug_info.local_in_function(function, name).ok_or_else(|| {
+        Error::MissingSymbol(format!("there is no local variable '{}' in the function '{}', or the ELF file contains no debug information", name, function))
+    })
+}
+
+fn member(value: Value, inner: &Expression, member_name: &str, context: &Context) -> Result<Value> {
+    let members = match value.ty {
+        ExpressionType::Dwarf(id) => match *context.debug_info.types().get(id) {
+            Type::Struct { ref members, .. } => members,
+            _ => return Err(Error::CommandLine(format!("'{}' is not a struct, class or union", inner))),
+        },
+        _ => return Err(Error::CommandLine(format!("'{}' has no debug information, so its members are not known", inner))),
+    };
+
+    match members.iter().find(|m| m.name == member_name) {
+        Some(member) => Ok(Value { place: offset(value.place, member.offset, inner)?, ty: ExpressionType::Dwarf(member.type_id) }),
+        None => Err(Error::CommandLine(format!("'{}' has no member named '{}'", inner, member_name))),
+    }
+}
+
+/// Reads a 16-bit pointer and gets the place in the data space that it points to.
+fn read_pointer(place: &Place, context: &Context) -> Result<Place> {
+    let bytes = match *place {
+        Place::Memory { space, address } => crate::read_current_memory_address(space, Pointer { address, natural_radix: 16 }, context.avr)?,
+        Place::Bytes(ref bytes) => &bytes[..],
+        Place::Unavailable(ref state) => return Ok(Place::Unavailable(state.clone())),
+    };
+
+    match bytes {
+        [low, high, ..] => Ok(Place::Memory { space: MemorySpace::Data, address: u16::from_le_bytes([*low, *high]) as u32 }),
+        _ => Err(Error::AddressOutOfRange("a pointer runs past the end of memory".to_owned())),
+    }
+}
+
+/// Gets the place `offset` bytes into `place`, which is the value of `expression`.
+fn offset(place: Place, offset: u32, expression: &Expression) -> Result<Place> {
+    Ok(match place {
+        Place::Memory { space, address } => Place::Memory {
+            space,
+            address: address.checked_add(offset).ok_or_else(|| past_end_of_memory(expression))?,
+        },
+        Place::Bytes(bytes) => Place::Bytes(bytes.get(offset as usize..).unwrap_or(&[]).to_vec()),
+        Place::Unavailable(state) => Place::Unavailable(state),
+    })
+}
+
+fn past_end_of_memory(expression: &Expression) -> Error {
+    Error::AddressOutOfRange(format!("'{}' runs past the end of memory", expression))
+}
+
+fn not_a_pointer(expression: &Expression) -> Error {
+    Error::CommandLine(format!("'{}' is not a pointer", expression))
+}
+
+fn not_indexable(expression: &Expression) -> Error {
+    Error::CommandLine(format!("'{}' is not an array or pointer with a known element type, try casting it as in '(u8 *){}'", expression, expression))
+}
+
+/// Checks whether a watchable (without its `=TYPE` suffix) should be parsed as an expression.
+pub(crate) fn is_expression(location: &str) -> bool {
+    // 'file.c:42:name' names a local by its source line.
+    let is_line_local = location.replace("::", "").contains(':');
+
+    location.contains(['*', '[', '(']) || location.contains("->") ||
+        (location.contains('.') && !is_line_local && !is_dotted_symbol_name(location))
+}
+
+/// Checks for a symbol name that GCC gives to function-static variables and
+/// clones, such as `counter.1` or `helper.constprop.0`. Members never start
+/// with a digit, so these cannot be member accesses.
+fn is_dotted_symbol_name(location: &str) -> bool {
+    let mut parts = location.split('.');
+    let is_word = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    matches!(parts.next(), Some(first) if is_word(first) && !first.starts_with(|c: char| c.is_ascii_digit())) &&
+        parts.clone().all(is_word) && parts.any(|part| part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parses an expression such as `point->coordinates[2]`.
+pub fn parse_expression(s: &str) -> Result<Expression> {
+    let tokens = tokenize(s)?;
+    let mut parser = Parser { tokens: &tokens, position: 0, source: s };
+
+    let expression = parser.unary()?;
+    match parser.peek() {
+        None => Ok(expression),
+        Some(token) => Err(parser.error(&format!("unexpected '{}'", token))),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Identifier(String),
+    Integer(u32),
+    Star,
+    Dot,
+    Arrow,
+    OpenBracket,
+    CloseBracket,
+    OpenParen,
+    CloseParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' => { chars.next(); },
+            '*' => { chars.next(); tokens.push(Token::Star); },
+            '.' => { chars.next(); tokens.push(Token::Dot); },
+            '[' => { chars.next(); tokens.push(Token::OpenBracket); },
+            ']' => { chars.next(); tokens.push(Token::CloseBracket); },
+            '(' => { chars.next(); tokens.push(Token::OpenParen); },
+            ')' => { chars.next(); tokens.push(Token::CloseParen); },
+            '-' => {
+                chars.next();
+                match chars.next() {
+                    Some('>') => tokens.push(Token::Arrow),
+                    _ => return Err(Error::CommandLine(format!("expected '->' in expression: {}", s))),
+                }
+            },
+            '0'..='9' => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() { break; }
+                    digits.push(c);
+                    chars.next();
+                }
+
+                let pointer: Pointer = digits.parse()?;
+                tokens.push(Token::Integer(pointer.address));
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                // Identifiers may be qualified by a function, as in 'callStuff::d'.
+                let mut identifier = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                        identifier.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Identifier(identifier));
+            },
+            c => return Err(Error::CommandLine(format!("unexpected character '{}' in expression: {}", c, s))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'t> {
+    tokens: &'t [Token],
+    position: usize,
+    source: &'t str,
+}
+
+impl<'t> Parser<'t> {
+    fn peek(&self) -> Option<&'t Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&'t Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn next(&mut self) -> Option<&'t Token> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if *token == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::CommandLine(format!("{} in expression: {}", message, self.source))
+    }
+
+    /// UNARY := '*' UNARY | '(' TYPE ['*'] ')' UNARY | POSTFIX
+    fn unary(&mut self) -> Result<Expression> {
+        match (self.peek(), self.peek_at(1), self.peek_at(2)) {
+            (Some(Token::Star), _, _) => {
+                self.next();
+                Ok(Expression::Dereference(Box::new(self.unary()?)))
+            },
+            (Some(Token::OpenParen), Some(Token::Identifier(name)), Some(Token::CloseParen)) |
+                (Some(Token::OpenParen), Some(Token::Identifier(name)), Some(Token::Star)) if name.parse::<DataType>().is_ok() => {
+                self.position += 2;
+                let is_pointer = self.peek() == Some(&Token::Star);
+                if is_pointer {
+                    self.next();
+                }
+                self.expect(Token::CloseParen)?;
+
+                let cast_type = CastType { data_type: name.parse()?, is_pointer };
+                Ok(Expression::Cast(cast_type, Box::new(self.unary()?)))
+            },
+            _ => self.postfix(),
+        }
+    }
+
+    /// POSTFIX := PRIMARY ('[' INTEGER ']' | '.' IDENTIFIER | '->' IDENTIFIER)*
+    fn postfix(&mut self) -> Result<Expression> {
+        let mut expression = self.primary()?;
+
+        loop {
+            expression = match self.peek() {
+                Some(Token::OpenBracket) => {
+                    self.next();
+                    let index = match self.next() {
+                        Some(Token::Integer(index)) => *index,
+                        _ => return Err(self.error("expected an integer index")),
+                    };
+                    self.expect(Token::CloseBracket)?;
+                    Expression::Index(Box::new(expression), index)
+                },
+                Some(Token::Dot) => {
+                    self.next();
+                    Expression::Member(Box::new(expression), self.member_name()?)
+                },
+                Some(Token::Arrow) => {
+                    self.next();
+                    Expression::PointerMember(Box::new(expression), self.member_name()?)
+                },
+                _ => return Ok(expression),
+            };
+        }
+    }
+
+    /// PRIMARY := IDENTIFIER | FUNCTION '::' IDENTIFIER | '(' UNARY ')'
+    fn primary(&mut self) -> Result<Expression> {
+        match self.next() {
+            Some(Token::Identifier(identifier)) => match identifier.rfind("::") {
+                Some(index) => Ok(Expression::Local {
+                    function: identifier[..index].to_owned(),
+                    name: identifier[index + 2..].to_owned(),
+                }),
+                None => Ok(Expression::Symbol(identifier.to_owned())),
+            },
+            Some(Token::OpenParen) => {
+                let expression = self.unary()?;
+                self.expect(Token::CloseParen)?;
+                Ok(expression)
+            },
+            _ => Err(self.error("expected a symbol name")),
+        }
+    }
+
+    fn member_name(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Identifier(name)) if !name.contains(':') => Ok(name.to_owned()),
+            _ => Err(self.error("expected a member name")),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Identifier(ref name) => write!(fmt, "{}", name),
+            Token::Integer(i) => write!(fmt, "{}", i),
+            Token::Star => write!(fmt, "*"),
+            Token::Dot => write!(fmt, "."),
+            Token::Arrow => write!(fmt, "->"),
+            Token::OpenBracket => write!(fmt, "["),
+            Token::CloseBracket => write!(fmt, "]"),
+            Token::OpenParen => write!(fmt, "("),
+            Token::CloseParen => write!(fmt, ")"),
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expression::Symbol(ref name) => write!(fmt, "{}", name),
+            Expression::Local { ref function, ref name } => write!(fmt, "{}::{}", function, name),
+            Expression::Dereference(ref inner) => write!(fmt, "*{}", inner),
+            Expression::Index(ref inner, index) => write!(fmt, "{}[{}]", inner, index),
+            Expression::Member(ref inner, ref member) => write!(fmt, "{}.{}", inner, member),
+            Expression::PointerMember(ref inner, ref member) => write!(fmt, "{}->{}", inner, member),
+            Expression::Cast(ref cast_type, ref inner) => write!(fmt, "({}{}){}", cast_type.data_type, if cast_type.is_pointer { " *" } else { "" }, inner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn symbol(name: &str) -> Box<Expression> {
+        Box::new(Expression::Symbol(name.to_owned()))
+    }
+
+    #[test]
+    fn can_parse_pointer_expressions() {
+        assert_eq!(Ok(Expression::Dereference(symbol("ptr"))), parse_expression("*ptr"));
+        assert_eq!(Ok(Expression::Index(symbol("ptr"), 3)), parse_expression("ptr[3]"));
+        assert_eq!(Ok(Expression::Member(symbol("s"), "field".to_owned())), parse_expression("s.field"));
+        assert_eq!(Ok(Expression::PointerMember(symbol("s"), "field".to_owned())), parse_expression("s->field"));
+        assert_eq!(Ok(Expression::Cast(CastType { data_type: DataType::U16, is_pointer: false }, symbol("SYM"))), parse_expression("(u16)SYM"));
+    }
+
+    #[test]
+    fn postfix_operators_bind_tighter_than_dereference() {
+        let expression = parse_expression("*(u8 *)list->items[0x2]").unwrap();
+
+        assert_eq!(Expression::Dereference(Box::new(Expression::Cast(
+            CastType { data_type: DataType::U8, is_pointer: true },
+            Box::new(Expression::Index(Box::new(Expression::PointerMember(symbol("list"), "items".to_owned())), 2)),
+        ))), expression);
+        assert_eq!("*(u8 *)list->items[2]", expression.to_string());
+    }
+
+    #[test]
+    fn can_parse_locals_in_expressions() {
+        assert_eq!(Ok(Expression::Dereference(Box::new(Expression::Local {
+            function: "callStuff".to_owned(),
+            name: "p".to_owned(),
+        }))), parse_expression("*callStuff::p"));
+    }
+
+    #[test]
+    fn offsets_past_the_end_of_memory_are_errors() {
+        let expression = Expression::Index(symbol("buf"), 4_000_000_000);
+        let place = Place::Memory { space: MemorySpace::Data, address: 0x100 };
+
+        assert!(matches!(offset(place.clone(), 0x10, &expression), Ok(Place::Memory { address: 0x110, .. })));
+        match offset(place, u32::MAX, &expression) {
+            Err(Error::AddressOutOfRange(message)) => assert_eq!("'buf[4000000000]' runs past the end of memory", message),
+            result => panic!("expected an out of range address but got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn line_locals_are_not_expressions() {
+        assert!(!is_expression("file.c:42:i"));
+        assert!(is_expression("point.x"));
+        assert!(is_expression("callStuff::p->x"));
+    }
+
+    #[test]
+    fn dotted_symbol_names_are_not_expressions() {
+        assert!(!is_expression("counter.1"));
+        assert!(!is_expression("helper.constprop.0"));
+        assert!(is_expression("point.x"));
+        assert!(is_expression("points.1x"));
+        assert!(is_expression("counter.1->x"));
+    }
+}
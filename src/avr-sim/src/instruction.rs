@@ -0,0 +1,255 @@
+//! Decodes the data memory accesses made by AVR instructions.
+//!
+//! simavr does not expose hooks for SRAM accesses, so the checkers that need
+//! to see them decode the instruction at the program counter before it is
+//! executed and work out which addresses it will touch.
+
+/// The data space address of the low byte of the stack pointer.
+pub const STACK_POINTER_LOW: u16 = 0x5d;
+/// The data space address of the high byte of the stack pointer.
+pub const STACK_POINTER_HIGH: u16 = 0x5e;
+
+/// IO space addresses are offset by the register file in the data space.
+const IO_SPACE_DATA_OFFSET: u16 = 0x20;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Load,
+    Store,
+}
+
+/// A single byte access to the data space.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub address: u16,
+}
+
+/// The data memory effects of a decoded instruction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Effects {
+    /// Every byte accessed, loads listed before stores.
+    pub accesses: Vec<MemoryAccess>,
+    /// How much the instruction moves the stack pointer by, ignoring explicit writes to SPL/SPH.
+    pub stack_pointer_delta: i32,
+    /// The pointer register used to address memory, if the instruction is an indirect load or store.
+    pub pointer_register: Option<PointerRegister>,
+    /// The size of the instruction in bytes.
+    pub size: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PointerRegister {
+    X,
+    Y,
+    Z,
+}
+
+/// The register state needed to calculate effective addresses.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers<'a> {
+    /// The 32 general purpose registers.
+    pub general: &'a [u8],
+    pub stack_pointer: u16,
+    /// The number of bytes in a return address, either two or three.
+    pub return_address_size: u8,
+}
+
+impl Effects {
+    pub fn loads(&self) -> impl Iterator<Item=u16> + '_ {
+        self.accesses.iter().filter(|a| a.kind == AccessKind::Load).map(|a| a.address)
+    }
+
+    pub fn stores(&self) -> impl Iterator<Item=u16> + '_ {
+        self.accesses.iter().filter(|a| a.kind == AccessKind::Store).map(|a| a.address)
+    }
+
+    /// Checks whether the instruction explicitly writes to the stack pointer.
+    pub fn writes_stack_pointer(&self) -> bool {
+        self.stores().any(|a| a == STACK_POINTER_LOW || a == STACK_POINTER_HIGH)
+    }
+
+    fn load(mut self, address: u16) -> Self {
+        self.accesses.push(MemoryAccess { kind: AccessKind::Load, address });
+        self
+    }
+
+    fn store(mut self, address: u16) -> Self {
+        self.accesses.push(MemoryAccess { kind: AccessKind::Store, address });
+        self
+    }
+}
+
+/// Decodes the memory effects of the instruction made up of `opcode` and, for 32-bit
+/// instructions, `next_word`.
+pub fn decode(opcode: u16, next_word: u16, registers: Registers) -> Effects {
+    let effects = Effects { size: if is_two_words(opcode) { 4 } else { 2 }, ..Effects::default() };
+    let register_pair = |low: usize| u16::from_le_bytes([registers.general[low], registers.general[low + 1]]);
+    let (x, y, z) = (register_pair(26), register_pair(28), register_pair(30));
+    let sp = registers.stack_pointer;
+    let return_address_size = registers.return_address_size as u16;
+
+    match opcode {
+        // LDS Rd, k
+        _ if opcode & 0xfe0f == 0x9000 => effects.load(next_word),
+        // STS k, Rr
+        _ if opcode & 0xfe0f == 0x9200 => effects.store(next_word),
+        // LD Rd, {Z+, -Z, Y+, -Y, X, X+, -X}
+        _ if opcode & 0xfe00 == 0x9000 => match pointer_operand(opcode & 0xf, x, y, z) {
+            Some((pointer_register, address)) => Effects { pointer_register: Some(pointer_register), ..effects.load(address) },
+            None if opcode & 0xf == 0xf => Effects { stack_pointer_delta: 1, ..effects.load(sp.wrapping_add(1)) }, // POP
+            None => effects,
+        },
+        // ST {Z+, -Z, Y+, -Y, X, X+, -X}, Rr
+        _ if opcode & 0xfe00 == 0x9200 => match pointer_operand(opcode & 0xf, x, y, z) {
+            Some((pointer_register, address)) => Effects { pointer_register: Some(pointer_register), ..effects.store(address) },
+            None if opcode & 0xf == 0xf => Effects { stack_pointer_delta: -1, ..effects.store(sp) }, // PUSH
+            // XCH, LAS, LAC, LAT
+            None if opcode & 0xc == 0x4 => effects.load(z).store(z),
+            None => effects,
+        },
+        // LDD/STD Rd, {Y+q, Z+q}
+        _ if opcode & 0xd000 == 0x8000 => {
+            let displacement = ((opcode >> 8) & 0x20) | ((opcode >> 7) & 0x18) | (opcode & 0x7);
+            let (pointer_register, base) = if opcode & 0x8 != 0 { (PointerRegister::Y, y) } else { (PointerRegister::Z, z) };
+            let address = base.wrapping_add(displacement);
+
+            let effects = if opcode & 0x200 != 0 { effects.store(address) } else { effects.load(address) };
+            Effects { pointer_register: Some(pointer_register), ..effects }
+        },
+        // IN Rd, A
+        _ if opcode & 0xf800 == 0xb000 => effects.load(io_address((opcode >> 5) & 0x30 | (opcode & 0xf))),
+        // OUT A, Rr
+        _ if opcode & 0xf800 == 0xb800 => effects.store(io_address((opcode >> 5) & 0x30 | (opcode & 0xf))),
+        // CBI, SBI
+        _ if opcode & 0xfd00 == 0x9800 => {
+            let address = io_address((opcode >> 3) & 0x1f);
+            effects.load(address).store(address)
+        },
+        // SBIC, SBIS
+        _ if opcode & 0xfd00 == 0x9900 => effects.load(io_address((opcode >> 3) & 0x1f)),
+        // RCALL, CALL, ICALL, EICALL
+        _ if opcode & 0xf000 == 0xd000 || opcode & 0xfe0e == 0x940e || opcode == 0x9509 || opcode == 0x9519 => {
+            let effects = (0..return_address_size).fold(effects, |effects, i| effects.store(sp.wrapping_sub(i)));
+            Effects { stack_pointer_delta: -(return_address_size as i32), ..effects }
+        },
+        // RET, RETI
+        0x9508 | 0x9518 => {
+            let effects = (1..=return_address_size).fold(effects, |effects, i| effects.load(sp.wrapping_add(i)));
+            Effects { stack_pointer_delta: return_address_size as i32, ..effects }
+        },
+        _ => effects,
+    }
+}
+
+/// Checks whether the opcode is the first word of a two word instruction.
+pub fn is_two_words(opcode: u16) -> bool {
+    // LDS, STS, JMP, CALL
+    opcode & 0xfc0f == 0x9000 || opcode & 0xfe0c == 0x940c
+}
+
+/// Gets the address accessed by an indirect load or store with the given addressing mode.
+fn pointer_operand(mode: u16, x: u16, y: u16, z: u16) -> Option<(PointerRegister, u16)> {
+    match mode {
+        0x1 => Some((PointerRegister::Z, z)),
+        0x2 => Some((PointerRegister::Z, z.wrapping_sub(1))),
+        0x9 => Some((PointerRegister::Y, y)),
+        0xa => Some((PointerRegister::Y, y.wrapping_sub(1))),
+        0xc | 0xd => Some((PointerRegister::X, x)),
+        0xe => Some((PointerRegister::X, x.wrapping_sub(1))),
+        _ => None,
+    }
+}
+
+fn io_address(io_register: u16) -> u16 {
+    io_register + IO_SPACE_DATA_OFFSET
+}
+
+/// Decodes the instruction the AVR is about to execute.
+pub fn decode_next(avr: &simavr::Avr) -> Effects {
+    let raw = avr.raw();
+    let flash = unsafe { std::slice::from_raw_parts(raw.flash, raw.flashend as usize + 1) };
+    let data = unsafe { std::slice::from_raw_parts(raw.data, raw.ramend as usize + 1) };
+
+    let word_at = |address: usize| flash.get(address..address + 2).map(|w| u16::from_le_bytes([w[0], w[1]])).unwrap_or(0xffff);
+    let opcode = word_at(raw.pc as usize);
+    let next_word = if is_two_words(opcode) { word_at(raw.pc as usize + 2) } else { 0 };
+
+    decode(opcode, next_word, Registers {
+        general: &data[0..32],
+        stack_pointer: u16::from_le_bytes([data[STACK_POINTER_LOW as usize], data[STACK_POINTER_HIGH as usize]]),
+        return_address_size: raw.address_size,
+    })
+}
+
+/// Checks whether the opcode is a `SPM` instruction, which writes to program memory.
+pub fn is_store_program_memory(opcode: u16) -> bool {
+    opcode == 0x95e8 || opcode == 0x95f8
+}
+
+/// Reads the instruction word at the program counter.
+pub fn opcode_at_pc(avr: &simavr::Avr) -> u16 {
+    let raw = avr.raw();
+    let flash = unsafe { std::slice::from_raw_parts(raw.flash, raw.flashend as usize + 1) };
+
+    flash.get(raw.pc as usize..raw.pc as usize + 2).map(|w| u16::from_le_bytes([w[0], w[1]])).unwrap_or(0xffff)
+}
+
+/// Reads the Z pointer register.
+pub fn z_register(avr: &simavr::Avr) -> u16 {
+    let raw = avr.raw();
+    unsafe { u16::from_le_bytes([*raw.data.add(30), *raw.data.add(31)]) }
+}
+
+/// Reads the current stack pointer.
+pub fn stack_pointer(avr: &simavr::Avr) -> u16 {
+    let raw = avr.raw();
+    unsafe { u16::from_le_bytes([*raw.data.add(STACK_POINTER_LOW as usize), *raw.data.add(STACK_POINTER_HIGH as usize)]) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode_with_pointers(opcode: u16, next_word: u16) -> Effects {
+        let mut general = [0u8; 32];
+        general[26..32].copy_from_slice(&[0x00, 0x01, 0x10, 0x01, 0x20, 0x01]); // X=0x100, Y=0x110, Z=0x120
+        decode(opcode, next_word, Registers { general: &general, stack_pointer: 0x8ff, return_address_size: 2 })
+    }
+
+    #[test]
+    fn can_decode_indirect_loads_and_stores() {
+        // ld r24, X+
+        assert_eq!(vec![0x100], decode_with_pointers(0x918d, 0).loads().collect::<Vec<_>>());
+        // st -Z, r24
+        assert_eq!(vec![0x11f], decode_with_pointers(0x9382, 0).stores().collect::<Vec<_>>());
+        // ldd r24, Y+5
+        assert_eq!(vec![0x115], decode_with_pointers(0x818d, 0).loads().collect::<Vec<_>>());
+        // std Z+63, r24
+        assert_eq!(vec![0x15f], decode_with_pointers(0xaf87, 0).stores().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn can_decode_direct_and_io_accesses() {
+        // lds r24, 0x0123
+        assert!(is_two_words(0x9180));
+        assert_eq!(vec![0x123], decode_with_pointers(0x9180, 0x0123).loads().collect::<Vec<_>>());
+        // out 0x3d, r28
+        assert!(decode_with_pointers(0xbfcd, 0).writes_stack_pointer());
+        // sbi 0x05, 3
+        assert_eq!(vec![0x25], decode_with_pointers(0x9a2b, 0).stores().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn can_decode_stack_accesses() {
+        // push r28
+        let push = decode_with_pointers(0x93cf, 0);
+        assert_eq!((vec![0x8ff], -1), (push.stores().collect::<Vec<_>>(), push.stack_pointer_delta));
+        // rcall .+0
+        let rcall = decode_with_pointers(0xd000, 0);
+        assert_eq!((vec![0x8ff, 0x8fe], -2), (rcall.stores().collect::<Vec<_>>(), rcall.stack_pointer_delta));
+        // ret
+        let ret = decode_with_pointers(0x9508, 0);
+        assert_eq!((vec![0x900, 0x901], 2), (ret.loads().collect::<Vec<_>>(), ret.stack_pointer_delta));
+    }
+}
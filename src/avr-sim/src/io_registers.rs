@@ -0,0 +1,136 @@
+//! The IO registers that exist on each supported MCU.
+
+/// An IO register, as its data space address and its name in the datasheet.
+pub type IoRegister = (u16, &'static str);
+
+/// The status register and stack pointer, which are at the same addresses on every classic AVR.
+pub const CORE_REGISTERS: &[IoRegister] = &[(0x5d, "SPL"), (0x5e, "SPH"), (0x5f, "SREG")];
+
+/// The IO registers of the ATmega48/88/168/328 family.
+const ATMEGA328_IO_REGISTERS: &[IoRegister] = &[
+    (0x23, "PINB"), (0x24, "DDRB"), (0x25, "PORTB"),
+    (0x26, "PINC"), (0x27, "DDRC"), (0x28, "PORTC"),
+    (0x29, "PIND"), (0x2a, "DDRD"), (0x2b, "PORTD"),
+    (0x35, "TIFR0"), (0x36, "TIFR1"), (0x37, "TIFR2"), (0x3b, "PCIFR"), (0x3c, "EIFR"), (0x3d, "EIMSK"), (0x3e, "GPIOR0"),
+    (0x3f, "EECR"), (0x40, "EEDR"), (0x41, "EEARL"), (0x42, "EEARH"),
+    (0x43, "GTCCR"), (0x44, "TCCR0A"), (0x45, "TCCR0B"), (0x46, "TCNT0"), (0x47, "OCR0A"), (0x48, "OCR0B"),
+    (0x4a, "GPIOR1"), (0x4b, "GPIOR2"), (0x4c, "SPCR"), (0x4d, "SPSR"), (0x4e, "SPDR"), (0x50, "ACSR"),
+    (0x53, "SMCR"), (0x54, "MCUSR"), (0x55, "MCUCR"), (0x57, "SPMCSR"), (0x5d, "SPL"), (0x5e, "SPH"), (0x5f, "SREG"),
+    (0x60, "WDTCSR"), (0x61, "CLKPR"), (0x64, "PRR"), (0x66, "OSCCAL"),
+    (0x68, "PCICR"), (0x69, "EICRA"), (0x6b, "PCMSK0"), (0x6c, "PCMSK1"), (0x6d, "PCMSK2"),
+    (0x6e, "TIMSK0"), (0x6f, "TIMSK1"), (0x70, "TIMSK2"),
+    (0x78, "ADCL"), (0x79, "ADCH"), (0x7a, "ADCSRA"), (0x7b, "ADCSRB"), (0x7c, "ADMUX"), (0x7e, "DIDR0"), (0x7f, "DIDR1"),
+    (0x80, "TCCR1A"), (0x81, "TCCR1B"), (0x82, "TCCR1C"), (0x84, "TCNT1L"), (0x85, "TCNT1H"),
+    (0x86, "ICR1L"), (0x87, "ICR1H"), (0x88, "OCR1AL"), (0x89, "OCR1AH"), (0x8a, "OCR1BL"), (0x8b, "OCR1BH"),
+    (0xb0, "TCCR2A"), (0xb1, "TCCR2B"), (0xb2, "TCNT2"), (0xb3, "OCR2A"), (0xb4, "OCR2B"), (0xb6, "ASSR"),
+    (0xb8, "TWBR"), (0xb9, "TWSR"), (0xba, "TWAR"), (0xbb, "TWDR"), (0xbc, "TWCR"), (0xbd, "TWAMR"),
+    (0xc0, "UCSR0A"), (0xc1, "UCSR0B"), (0xc2, "UCSR0C"), (0xc4, "UBRR0L"), (0xc5, "UBRR0H"), (0xc6, "UDR0"),
+];
+
+/// The IO registers of the ATmega640/1280/2560 family.
+const ATMEGA2560_IO_REGISTERS: &[IoRegister] = &[
+    (0x20, "PINA"), (0x21, "DDRA"), (0x22, "PORTA"), (0x23, "PINB"), (0x24, "DDRB"), (0x25, "PORTB"),
+    (0x26, "PINC"), (0x27, "DDRC"), (0x28, "PORTC"), (0x29, "PIND"), (0x2a, "DDRD"), (0x2b, "PORTD"),
+    (0x2c, "PINE"), (0x2d, "DDRE"), (0x2e, "PORTE"), (0x2f, "PINF"), (0x30, "DDRF"), (0x31, "PORTF"),
+    (0x32, "PING"), (0x33, "DDRG"), (0x34, "PORTG"),
+    (0x35, "TIFR0"), (0x36, "TIFR1"), (0x37, "TIFR2"), (0x38, "TIFR3"), (0x39, "TIFR4"), (0x3a, "TIFR5"),
+    (0x3b, "PCIFR"), (0x3c, "EIFR"), (0x3d, "EIMSK"), (0x3e, "GPIOR0"),
+    (0x3f, "EECR"), (0x40, "EEDR"), (0x41, "EEARL"), (0x42, "EEARH"),
+    (0x43, "GTCCR"), (0x44, "TCCR0A"), (0x45, "TCCR0B"), (0x46, "TCNT0"), (0x47, "OCR0A"), (0x48, "OCR0B"),
+    (0x4a, "GPIOR1"), (0x4b, "GPIOR2"), (0x4c, "SPCR"), (0x4d, "SPSR"), (0x4e, "SPDR"), (0x50, "ACSR"), (0x51, "OCDR"),
+    (0x53, "SMCR"), (0x54, "MCUSR"), (0x55, "MCUCR"), (0x57, "SPMCSR"), (0x5b, "RAMPZ"), (0x5c, "EIND"),
+    (0x5d, "SPL"), (0x5e, "SPH"), (0x5f, "SREG"),
+    (0x60, "WDTCSR"), (0x61, "CLKPR"), (0x64, "PRR0"), (0x65, "PRR1"), (0x66, "OSCCAL"),
+    (0x68, "PCICR"), (0x69, "EICRA"), (0x6a, "EICRB"), (0x6b, "PCMSK0"), (0x6c, "PCMSK1"), (0x6d, "PCMSK2"),
+    (0x6e, "TIMSK0"), (0x6f, "TIMSK1"), (0x70, "TIMSK2"), (0x71, "TIMSK3"), (0x72, "TIMSK4"), (0x73, "TIMSK5"),
+    (0x74, "XMCRA"), (0x75, "XMCRB"),
+    (0x78, "ADCL"), (0x79, "ADCH"), (0x7a, "ADCSRA"), (0x7b, "ADCSRB"), (0x7c, "ADMUX"),
+    (0x7d, "DIDR2"), (0x7e, "DIDR0"), (0x7f, "DIDR1"),
+    (0x80, "TCCR1A"), (0x81, "TCCR1B"), (0x82, "TCCR1C"), (0x84, "TCNT1L"), (0x85, "TCNT1H"),
+    (0x86, "ICR1L"), (0x87, "ICR1H"), (0x88, "OCR1AL"), (0x89, "OCR1AH"), (0x8a, "OCR1BL"), (0x8b, "OCR1BH"),
+    (0x8c, "OCR1CL"), (0x8d, "OCR1CH"),
+    (0x90, "TCCR3A"), (0x91, "TCCR3B"), (0x92, "TCCR3C"), (0x94, "TCNT3L"), (0x95, "TCNT3H"),
+    (0x96, "ICR3L"), (0x97, "ICR3H"), (0x98, "OCR3AL"), (0x99, "OCR3AH"), (0x9a, "OCR3BL"), (0x9b, "OCR3BH"),
+    (0x9c, "OCR3CL"), (0x9d, "OCR3CH"),
+    (0xa0, "TCCR4A"), (0xa1, "TCCR4B"), (0xa2, "TCCR4C"), (0xa4, "TCNT4L"), (0xa5, "TCNT4H"),
+    (0xa6, "ICR4L"), (0xa7, "ICR4H"), (0xa8, "OCR4AL"), (0xa9, "OCR4AH"), (0xaa, "OCR4BL"), (0xab, "OCR4BH"),
+    (0xac, "OCR4CL"), (0xad, "OCR4CH"),
+    (0xb0, "TCCR2A"), (0xb1, "TCCR2B"), (0xb2, "TCNT2"), (0xb3, "OCR2A"), (0xb4, "OCR2B"), (0xb6, "ASSR"),
+    (0xb8, "TWBR"), (0xb9, "TWSR"), (0xba, "TWAR"), (0xbb, "TWDR"), (0xbc, "TWCR"), (0xbd, "TWAMR"),
+    (0xc0, "UCSR0A"), (0xc1, "UCSR0B"), (0xc2, "UCSR0C"), (0xc4, "UBRR0L"), (0xc5, "UBRR0H"), (0xc6, "UDR0"),
+    (0xc8, "UCSR1A"), (0xc9, "UCSR1B"), (0xca, "UCSR1C"), (0xcc, "UBRR1L"), (0xcd, "UBRR1H"), (0xce, "UDR1"),
+    (0xd0, "UCSR2A"), (0xd1, "UCSR2B"), (0xd2, "UCSR2C"), (0xd4, "UBRR2L"), (0xd5, "UBRR2H"), (0xd6, "UDR2"),
+    (0x100, "PINH"), (0x101, "DDRH"), (0x102, "PORTH"), (0x103, "PINJ"), (0x104, "DDRJ"), (0x105, "PORTJ"),
+    (0x106, "PINK"), (0x107, "DDRK"), (0x108, "PORTK"), (0x109, "PINL"), (0x10a, "DDRL"), (0x10b, "PORTL"),
+    (0x120, "TCCR5A"), (0x121, "TCCR5B"), (0x122, "TCCR5C"), (0x124, "TCNT5L"), (0x125, "TCNT5H"),
+    (0x126, "ICR5L"), (0x127, "ICR5H"), (0x128, "OCR5AL"), (0x129, "OCR5AH"), (0x12a, "OCR5BL"), (0x12b, "OCR5BH"),
+    (0x12c, "OCR5CL"), (0x12d, "OCR5CH"),
+    (0x130, "UCSR3A"), (0x131, "UCSR3B"), (0x132, "UCSR3C"), (0x134, "UBRR3L"), (0x135, "UBRR3H"), (0x136, "UDR3"),
+];
+
+/// The IO registers of the ATtiny25/45/85 family.
+const ATTINY85_IO_REGISTERS: &[IoRegister] = &[
+    (0x23, "ADCSRB"), (0x24, "ADCL"), (0x25, "ADCH"), (0x26, "ADCSRA"), (0x27, "ADMUX"), (0x28, "ACSR"),
+    (0x2d, "USICR"), (0x2e, "USISR"), (0x2f, "USIDR"), (0x30, "USIBR"),
+    (0x31, "GPIOR0"), (0x32, "GPIOR1"), (0x33, "GPIOR2"), (0x34, "DIDR0"), (0x35, "PCMSK"),
+    (0x36, "PINB"), (0x37, "DDRB"), (0x38, "PORTB"),
+    (0x3c, "EECR"), (0x3d, "EEDR"), (0x3e, "EEARL"), (0x3f, "EEARH"),
+    (0x40, "PRR"), (0x41, "WDTCR"), (0x42, "DWDR"), (0x43, "DTPS1"), (0x44, "DT1B"), (0x45, "DT1A"),
+    (0x46, "CLKPR"), (0x47, "PLLCSR"), (0x48, "OCR0B"), (0x49, "OCR0A"), (0x4a, "TCCR0A"),
+    (0x4b, "OCR1B"), (0x4c, "GTCCR"), (0x4d, "OCR1C"), (0x4e, "OCR1A"), (0x4f, "TCNT1"), (0x50, "TCCR1"),
+    (0x51, "OSCCAL"), (0x52, "TCNT0"), (0x53, "TCCR0B"), (0x54, "MCUSR"), (0x55, "MCUCR"), (0x57, "SPMCSR"),
+    (0x58, "TIFR"), (0x59, "TIMSK"), (0x5a, "GIFR"), (0x5b, "GIMSK"), (0x5d, "SPL"), (0x5e, "SPH"), (0x5f, "SREG"),
+];
+
+/// Gets every IO register on the given MCU, if known.
+pub fn for_mcu(mcu_name: &str) -> Option<&'static [IoRegister]> {
+    match mcu_name.to_lowercase().as_str() {
+        "atmega48" | "atmega48a" | "atmega48p" | "atmega48pa" |
+            "atmega88" | "atmega88a" | "atmega88p" | "atmega88pa" |
+            "atmega168" | "atmega168a" | "atmega168p" | "atmega168pa" |
+            "atmega328" | "atmega328p" => Some(ATMEGA328_IO_REGISTERS),
+        "atmega640" | "atmega1280" | "atmega2560" => Some(ATMEGA2560_IO_REGISTERS),
+        "attiny25" | "attiny45" | "attiny85" => Some(ATTINY85_IO_REGISTERS),
+        _ => None,
+    }
+}
+
+/// Checks if a register holds nothing but its own value, so that writing
+/// the value back is all it takes to restore it.
+///
+/// This is true of the status register, the stack pointer, GPIO and general
+/// purpose IO registers, and registers that only configure the core.
+pub fn is_plain_storage(name: &str) -> bool {
+    ["PIN", "DDR", "PORT", "GPIOR", "PRR"].iter().any(|prefix| name.starts_with(prefix)) ||
+        ["SPL", "SPH", "SREG", "RAMPZ", "EIND", "EEDR", "EEARL", "EEARH", "SMCR", "MCUSR", "MCUCR", "CLKPR", "OSCCAL"].contains(&name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tables_are_sorted_by_address() {
+        for mcu in &["atmega328p", "atmega2560", "attiny85"] {
+            let table = for_mcu(mcu).unwrap();
+            assert!(table.windows(2).all(|pair| pair[0].0 < pair[1].0), "{} is not sorted", mcu);
+        }
+    }
+
+    #[test]
+    fn every_table_has_the_core_registers() {
+        for mcu in &["atmega328p", "atmega2560", "attiny85"] {
+            let table = for_mcu(mcu).unwrap();
+            assert!(CORE_REGISTERS.iter().all(|register| table.contains(register)), "{} is missing a core register", mcu);
+        }
+    }
+
+    #[test]
+    fn peripheral_registers_are_not_plain_storage() {
+        assert!(is_plain_storage("PORTB"));
+        assert!(is_plain_storage("SREG"));
+        assert!(!is_plain_storage("TCCR0B"));
+        assert!(!is_plain_storage("UCSR0B"));
+        assert!(!is_plain_storage("PCMSK0"));
+    }
+}
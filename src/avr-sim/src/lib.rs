@@ -0,0 +1,52 @@
+//! A simulator for running compiled AVR test programs and observing their behaviour.
+//!
+//! The `avr-sim` command line tool is a thin frontend over this library. Test
+//! harnesses can use the [`Simulator`] directly.
+//!
+//! ```no_run
+//! use avr_sim::{Simulator, RunOutcome};
+//!
+//! let mut simulator = Simulator::from_elf_file("atmega328", "test.elf".as_ref()).unwrap();
+//! let watch = avr_sim::parse_watch("OUTPUT_VALUE=u8").unwrap().remove(0);
+//!
+//! assert_eq!(RunOutcome::Finished, simulator.run_to_completion().unwrap());
+//! println!("{}", simulator.read_watch(&watch).unwrap());
+//! ```
+
+/// Re-exported so that users of the library do not need to depend on simavr themselves.
+pub extern crate simavr_sim as simavr;
+#[macro_use] extern crate bitflags;
+
+pub mod condition;
+pub mod debug_info;
+pub mod diagnostic;
+pub mod error;
+pub mod expression;
+pub mod locals;
+pub mod ram_fill;
+pub mod snapshot;
+pub mod stimulus;
+pub mod types;
+pub mod uart;
+pub mod vcd;
+
+mod avr_print;
+mod instruction;
+mod io_registers;
+mod sanitizer;
+mod simulator;
+mod uninit;
+mod watch;
+
+pub use self::diagnostic::Diagnostic;
+pub use self::error::{Error, Result};
+pub use self::simulator::{RunOutcome, SimulatedTime, Simulator, Step};
+pub use self::watch::{
+    DataType, LocalScope, MemorySpace, Pointer, Watch, WatchState, WatchableSymbol,
+    parse_watch, parse_watchable_symbols_from_elf,
+};
+pub(crate) use self::watch::{read_current_memory_address, read_current_memory_address_mut};
+
+const fn avr_ioctl_def(a: char, b: char, c: char, d: char) -> u32 {
+    ((a as u32) << 24) | ((b as u32) << 16) | ((c as u32) << 8)| ((d as u32))
+}
@@ -0,0 +1,468 @@
+//! Local variables and function parameters described by DWARF.
+//!
+//! Locals do not live at a fixed address. DWARF describes where each one is
+//! with a location expression, which may depend on the program counter, the
+//! registers, the frame base of the function or the call frame address.
+//! These are evaluated against the current state of the simulated CPU.
+
+use crate::debug_info::{self, DebugInfo, Reader, SourceLocation, DATA_SPACE_ELF_OFFSET};
+use crate::types::{TypeId, UnitTypes};
+use crate::{DataType, Error, Result};
+use std::ops::Range;
+
+/// The DWARF register number of the stack pointer on AVR.
+const STACK_POINTER_REGISTER: u16 = 32;
+/// The data space addresses of the SPL and SPH IO registers.
+const STACK_POINTER_ADDRESS: usize = 0x5d;
+
+#[derive(Clone, Debug)]
+pub struct LocalVariable {
+    pub name: String,
+    /// The name of the function that the variable belongs to.
+    pub function: String,
+    /// The mangled name of the function, if it has one.
+    pub function_linkage_name: Option<String>,
+    pub declared_at: Option<SourceLocation>,
+    pub type_id: Option<TypeId>,
+    /// The type of the variable, if it can be watched without an explicit type.
+    pub data_type: Option<DataType>,
+    /// The program addresses where the variable is in scope.
+    scope: Vec<Range<u64>>,
+    location: Location,
+    /// The frame base of the function, which locations may be relative to.
+    frame_base: Location,
+    encoding: gimli::Encoding,
+}
+
+/// The value of a local variable at the current program counter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LocalValue {
+    /// The program counter is not inside the variable's scope.
+    OutOfScope,
+    /// The compiler did not keep the variable around at this point.
+    OptimizedOut,
+    /// The variable lives in data memory at this address.
+    Memory(u32),
+    /// The variable lives in registers, or is a constant.
+    Bytes(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+enum Location {
+    /// There is no location, so the variable was optimized away entirely.
+    None,
+    /// The variable was folded into a constant.
+    Constant(Vec<u8>),
+    Expression(Vec<u8>),
+    /// A location that depends on the program address.
+    List(Vec<(Range<u64>, Vec<u8>)>),
+}
+
+/// The function or lexical block that variables are being read from.
+#[derive(Clone)]
+struct Scope {
+    function: String,
+    function_linkage_name: Option<String>,
+    frame_base: Location,
+    addresses: Vec<Range<u64>>,
+}
+
+impl LocalVariable {
+    /// Checks if the variable is in scope at the given program address.
+    pub fn is_in_scope(&self, pc: u32) -> bool {
+        self.scope.iter().any(|range| range.contains(&(pc as u64)))
+    }
+
+    /// Gets the total number of program addresses where the variable is in scope.
+    fn scope_size(&self) -> u64 {
+        self.scope.iter().map(|range| range.end - range.start).sum()
+    }
+
+    /// Reads the variable, assuming that it is `size` bytes.
+    pub fn read(&self, size: u32, avr: &simavr::Avr, debug_info: &DebugInfo) -> Result<LocalValue> {
+        let pc = avr.raw().pc;
+
+        if !self.is_in_scope(pc) {
+            return Ok(LocalValue::OutOfScope);
+        }
+
+        let expression = match self.location.at(pc) {
+            LocationAt::None => return Ok(LocalValue::OptimizedOut),
+            LocationAt::Constant(bytes) => {
+                // Constants are stored in as few bytes as possible.
+                let mut bytes = bytes.to_owned();
+                bytes.resize(bytes.len().max(size as usize), 0);
+                return Ok(LocalValue::Bytes(bytes));
+            },
+            LocationAt::Expression(expression) => expression,
+        };
+
+        let pieces = self.evaluate(expression, avr, debug_info)?;
+
+        // The common case of a variable that lives entirely in memory.
+        if let [gimli::Piece { size_in_bits: None, location: gimli::Location::Address { address }, .. }] = pieces[..] {
+            return Ok(LocalValue::Memory(data_address(address)));
+        }
+
+        let mut bytes = Vec::new();
+
+        for piece in pieces.iter() {
+            let piece_size = piece.size_in_bits.map(|bits| bits.div_ceil(8) as usize).unwrap_or(size as usize);
+
+            match piece.location {
+                gimli::Location::Empty => return Ok(LocalValue::OptimizedOut),
+                gimli::Location::Register { register } => {
+                    bytes.extend((0..piece_size).map(|i| register_byte(avr, register.0, i)));
+                },
+                gimli::Location::Address { address } => {
+                    let memory = crate::read_current_memory_address(crate::MemorySpace::Data, crate::Pointer { address: data_address(address), natural_radix: 16 }, avr)?;
+                    bytes.extend(memory.get(0..piece_size).ok_or_else(|| Error::AddressOutOfRange(format!("'{}' runs past the end of data memory", self.name)))?);
+                },
+                gimli::Location::Value { value } => {
+                    let value = value.to_u64(!0).map_err(|e| dwarf_error(&self.name, e))?;
+                    bytes.extend(value.to_le_bytes().iter().cycle().take(piece_size));
+                },
+                gimli::Location::Bytes { value } => bytes.extend(value.slice()),
+                gimli::Location::ImplicitPointer { .. } => {
+                    return Err(Error::SimulationFault(format!("'{}' is an implicit pointer, which is not supported", self.name)));
+                },
+            }
+        }
+
+        Ok(LocalValue::Bytes(bytes))
+    }
+
+    fn evaluate<'e>(&self, expression: &'e [u8], avr: &simavr::Avr, debug_info: &DebugInfo)
+        -> Result<Vec<gimli::Piece<Reader<'e>>>> {
+        let mut evaluation = gimli::Evaluation::new(gimli::EndianSlice::new(expression, gimli::RunTimeEndian::Little), self.encoding);
+        let mut result = evaluation.evaluate().map_err(|e| dwarf_error(&self.name, e))?;
+
+        loop {
+            let resumed = match result {
+                gimli::EvaluationResult::Complete => break,
+                gimli::EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let mut value = 0u64;
+                    for i in (0..size as usize).rev() {
+                        let byte = avr_data(avr).get(data_address(address) as usize + i).cloned().unwrap_or(0);
+                        value = (value << 8) | byte as u64;
+                    }
+                    evaluation.resume_with_memory(gimli::Value::Generic(value))
+                },
+                gimli::EvaluationResult::RequiresRegister { register, .. } => {
+                    evaluation.resume_with_register(gimli::Value::Generic(register_pair(avr, register.0)))
+                },
+                gimli::EvaluationResult::RequiresFrameBase => {
+                    let frame_base = self.frame_base(avr, debug_info)?;
+                    evaluation.resume_with_frame_base(frame_base)
+                },
+                gimli::EvaluationResult::RequiresCallFrameCfa => {
+                    let cfa = match call_frame_address(avr, debug_info).map_err(|e| dwarf_error(&self.name, e))? {
+                        Some(cfa) => cfa,
+                        None => return Err(Error::SimulationFault(format!("the call frame address of '{}' is an expression, which is not supported", self.function))),
+                    };
+                    evaluation.resume_with_call_frame_cfa(cfa)
+                },
+                gimli::EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address)
+                },
+                ref requirement => {
+                    return Err(Error::SimulationFault(format!("the location of '{}' needs {:?}, which is not supported", self.name, requirement)));
+                },
+            };
+
+            result = resumed.map_err(|e| dwarf_error(&self.name, e))?;
+        }
+
+        Ok(evaluation.result())
+    }
+
+    fn frame_base(&self, avr: &simavr::Avr, debug_info: &DebugInfo) -> Result<u64> {
+        let expression = match self.frame_base.at(avr.raw().pc) {
+            LocationAt::Expression(expression) => expression,
+            _ => return Err(Error::SimulationFault(format!("the frame base of '{}' is not known", self.function))),
+        };
+
+        match self.evaluate(expression, avr, debug_info)?[..] {
+            [gimli::Piece { location: gimli::Location::Address { address }, .. }] => Ok(address),
+            [gimli::Piece { location: gimli::Location::Register { register }, .. }] => Ok(register_pair(avr, register.0)),
+            _ => Err(Error::SimulationFault(format!("the frame base of '{}' is not an address", self.function))),
+        }
+    }
+}
+
+enum LocationAt<'a> {
+    None,
+    Constant(&'a [u8]),
+    Expression(&'a [u8]),
+}
+
+impl Location {
+    fn at(&self, pc: u32) -> LocationAt<'_> {
+        match *self {
+            Location::None => LocationAt::None,
+            Location::Constant(ref bytes) => LocationAt::Constant(bytes),
+            Location::Expression(ref expression) => LocationAt::Expression(expression),
+            Location::List(ref entries) => {
+                match entries.iter().find(|(range, _)| range.contains(&(pc as u64))) {
+                    Some((_, expression)) => LocationAt::Expression(expression),
+                    None => LocationAt::None,
+                }
+            },
+        }
+    }
+}
+
+/// Reads the locals of every function in a compilation unit.
+pub(crate) fn parse(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    types: &mut UnitTypes,
+    locals: &mut Vec<LocalVariable>,
+) -> std::result::Result<(), gimli::Error> {
+    let mut tree = unit.entries_tree(None)?;
+    parse_children(dwarf, unit, tree.root()?, None, types, locals)
+}
+
+fn parse_children(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    node: gimli::EntriesTreeNode<Reader>,
+    scope: Option<&Scope>,
+    types: &mut UnitTypes,
+    locals: &mut Vec<LocalVariable>,
+) -> std::result::Result<(), gimli::Error> {
+    let mut children = node.children();
+
+    while let Some(child) = children.next()? {
+        let entry = child.entry().clone();
+
+        match (entry.tag(), scope) {
+            (gimli::DW_TAG_subprogram, None) => {
+                let addresses = ranges(dwarf, unit, &entry)?;
+                // Skip declarations and inline functions that were never emitted out of line.
+                if addresses.is_empty() {
+                    continue;
+                }
+
+                let function = Scope {
+                    function: string_attribute(dwarf, unit, &entry, gimli::DW_AT_name)?.unwrap_or_default(),
+                    function_linkage_name: match string_attribute(dwarf, unit, &entry, gimli::DW_AT_linkage_name)? {
+                        Some(name) => Some(name),
+                        None => string_attribute(dwarf, unit, &entry, gimli::DW_AT_MIPS_linkage_name)?,
+                    },
+                    frame_base: parse_location(dwarf, unit, entry.attr_value(gimli::DW_AT_frame_base)?)?,
+                    addresses,
+                };
+                parse_children(dwarf, unit, child, Some(&function), types, locals)?;
+            },
+            (gimli::DW_TAG_namespace, None) => parse_children(dwarf, unit, child, None, types, locals)?,
+            (gimli::DW_TAG_lexical_block, Some(scope)) => {
+                let addresses = ranges(dwarf, unit, &entry)?;
+                let block = Scope {
+                    addresses: if addresses.is_empty() { scope.addresses.clone() } else { addresses },
+                    ..scope.clone()
+                };
+                parse_children(dwarf, unit, child, Some(&block), types, locals)?;
+            },
+            (gimli::DW_TAG_variable, Some(scope)) | (gimli::DW_TAG_formal_parameter, Some(scope)) => {
+                let name = match string_attribute(dwarf, unit, &entry, gimli::DW_AT_name)? {
+                    Some(name) => name,
+                    None => continue, // compiler generated
+                };
+
+                let location = match entry.attr_value(gimli::DW_AT_const_value)? {
+                    Some(value) => constant_location(value),
+                    None => parse_location(dwarf, unit, entry.attr_value(gimli::DW_AT_location)?)?,
+                };
+                let type_id = match debug_info::attribute(unit, &entry, gimli::DW_AT_type)? {
+                    Some(gimli::AttributeValue::UnitRef(offset)) => Some(types.resolve(dwarf, unit, offset)?),
+                    _ => None,
+                };
+
+                locals.push(LocalVariable {
+                    name,
+                    function: scope.function.clone(),
+                    function_linkage_name: scope.function_linkage_name.clone(),
+                    declared_at: debug_info::declaration_location(dwarf, unit, &entry)?,
+                    type_id,
+                    data_type: type_id.and_then(|id| types.data_type(id)),
+                    scope: scope.addresses.clone(),
+                    location,
+                    frame_base: scope.frame_base.clone(),
+                    encoding: unit.encoding(),
+                });
+            },
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_location(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    value: Option<gimli::AttributeValue<Reader>>,
+) -> std::result::Result<Location, gimli::Error> {
+    let value = match value {
+        Some(gimli::AttributeValue::Exprloc(expression)) => return Ok(Location::Expression(expression.0.slice().to_owned())),
+        Some(value) => value,
+        None => return Ok(Location::None),
+    };
+
+    match dwarf.attr_locations(unit, value)? {
+        Some(mut entries) => {
+            let mut list = Vec::new();
+            while let Some(entry) = entries.next()? {
+                list.push((entry.range.begin..entry.range.end, entry.data.0.slice().to_owned()));
+            }
+            Ok(Location::List(list))
+        },
+        None => Ok(Location::None),
+    }
+}
+
+fn constant_location(value: gimli::AttributeValue<Reader>) -> Location {
+    match value {
+        gimli::AttributeValue::Block(bytes) => Location::Constant(bytes.slice().to_owned()),
+        gimli::AttributeValue::Data1(value) => Location::Constant(vec![value]),
+        gimli::AttributeValue::Data2(value) => Location::Constant(value.to_le_bytes().to_vec()),
+        gimli::AttributeValue::Data4(value) => Location::Constant(value.to_le_bytes().to_vec()),
+        gimli::AttributeValue::Data8(value) => Location::Constant(value.to_le_bytes().to_vec()),
+        gimli::AttributeValue::Sdata(value) => Location::Constant(value.to_le_bytes().to_vec()),
+        gimli::AttributeValue::Udata(value) => Location::Constant(value.to_le_bytes().to_vec()),
+        _ => Location::None,
+    }
+}
+
+fn ranges(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+) -> std::result::Result<Vec<Range<u64>>, gimli::Error> {
+    let mut ranges = Vec::new();
+    let mut iter = dwarf.die_ranges(unit, entry)?;
+
+    while let Some(range) = iter.next()? {
+        ranges.push(range.begin..range.end);
+    }
+
+    Ok(ranges)
+}
+
+fn string_attribute(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    name: gimli::DwAt,
+) -> std::result::Result<Option<String>, gimli::Error> {
+    match debug_info::attribute(unit, entry, name)? {
+        Some(value) => Ok(Some(dwarf.attr_string(unit, value)?.to_string_lossy().into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// Finds the local with the given name and the largest scope in a function,
+/// which is given by either its plain or mangled name.
+pub fn outermost_in_function<'a>(locals: &'a [LocalVariable], function: &str, name: &str) -> Option<&'a LocalVariable> {
+    locals.iter()
+        .filter(|local| {
+            local.name == name && (local.function == function || local.function_linkage_name.as_ref().map(|n| n == function).unwrap_or(false))
+        })
+        .max_by_key(|local| local.scope_size())
+}
+
+/// Finds the innermost local with the given name that is in scope at any of the program addresses.
+pub fn innermost_at<'a>(locals: &'a [LocalVariable], name: &str, pcs: &[u32]) -> Option<&'a LocalVariable> {
+    locals.iter()
+        .filter(|local| local.name == name && pcs.iter().any(|&pc| local.is_in_scope(pc)))
+        .min_by_key(|local| local.scope_size())
+}
+
+/// Computes the canonical frame address of the current function from the call frame information.
+fn call_frame_address(avr: &simavr::Avr, debug_info: &DebugInfo) -> std::result::Result<Option<u64>, gimli::Error> {
+    use gimli::UnwindSection;
+
+    let mut debug_frame = gimli::DebugFrame::new(debug_info.debug_frame(), gimli::LittleEndian);
+    debug_frame.set_address_size(2);
+
+    let bases = gimli::BaseAddresses::default();
+    let mut context = gimli::UninitializedUnwindContext::new();
+    let row = debug_frame.unwind_info_for_address(&bases, &mut context, avr.raw().pc as u64, gimli::DebugFrame::cie_from_offset)?;
+
+    match *row.cfa() {
+        gimli::CfaRule::RegisterAndOffset { register, offset } => Ok(Some((register_pair(avr, register.0) as i64 + offset) as u64)),
+        gimli::CfaRule::Expression(..) => Ok(None),
+    }
+}
+
+fn avr_data(avr: &simavr::Avr) -> &[u8] {
+    let raw = avr.raw();
+    unsafe { std::slice::from_raw_parts(raw.data, raw.ramend as usize + 1) }
+}
+
+/// Reads a byte of a multi-byte value that starts in the given register.
+fn register_byte(avr: &simavr::Avr, register: u16, index: usize) -> u8 {
+    let address = if register == STACK_POINTER_REGISTER { STACK_POINTER_ADDRESS } else { register as usize };
+    avr_data(avr).get(address + index).cloned().unwrap_or(0)
+}
+
+/// Reads a pointer from a register and the one after it, as in `DW_OP_breg28` for the Y register.
+fn register_pair(avr: &simavr::Avr, register: u16) -> u64 {
+    register_byte(avr, register, 0) as u64 | (register_byte(avr, register, 1) as u64) << 8
+}
+
+/// Converts an address from DWARF into a data space address.
+fn data_address(address: u64) -> u32 {
+    if address >= DATA_SPACE_ELF_OFFSET { (address - DATA_SPACE_ELF_OFFSET) as u32 } else { address as u32 }
+}
+
+fn dwarf_error(name: &str, error: gimli::Error) -> Error {
+    Error::SimulationFault(format!("could not evaluate the location of '{}': {}", name, error))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn local(name: &str, function: &str, scope: Range<u64>) -> LocalVariable {
+        LocalVariable {
+            name: name.to_owned(),
+            function: function.to_owned(),
+            function_linkage_name: Some(format!("_Z{}{}v", function.len(), function)),
+            declared_at: None,
+            type_id: None,
+            data_type: None,
+            scope: vec![scope],
+            location: Location::None,
+            frame_base: Location::None,
+            encoding: gimli::Encoding { format: gimli::Format::Dwarf32, version: 4, address_size: 2 },
+        }
+    }
+
+    /// 'i' is declared at the top of 'loop' and shadowed inside a block.
+    fn shadowed_locals() -> Vec<LocalVariable> {
+        vec![
+            local("i", "loop", 0x120..0x140),
+            local("i", "loop", 0x100..0x180),
+            local("i", "other", 0x200..0x400),
+        ]
+    }
+
+    #[test]
+    fn outermost_local_in_function_wins_when_shadowed() {
+        let locals = shadowed_locals();
+
+        assert_eq!(Some(&(0x100..0x180)), outermost_in_function(&locals, "loop", "i").map(|local| &local.scope[0]));
+        assert_eq!(Some(&(0x100..0x180)), outermost_in_function(&locals, "_Z4loopv", "i").map(|local| &local.scope[0]));
+        assert!(outermost_in_function(&locals, "loop", "j").is_none());
+    }
+
+    #[test]
+    fn innermost_local_at_address_wins_when_shadowed() {
+        let locals = shadowed_locals();
+
+        assert_eq!(Some(&(0x120..0x140)), innermost_at(&locals, "i", &[0x130]).map(|local| &local.scope[0]));
+        assert_eq!(Some(&(0x100..0x180)), innermost_at(&locals, "i", &[0x150]).map(|local| &local.scope[0]));
+    }
+}
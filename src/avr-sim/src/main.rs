@@ -1,80 +1,82 @@
-extern crate simavr_sim as simavr;
-#[macro_use] extern crate bitflags;
+//! The `avr-sim` command line tool.
 
-mod avr_print;
+#[macro_use] mod output;
+mod symbol_list;
+
+use avr_sim::condition::{self, Condition};
+use avr_sim::debug_info::DebugInfo;
+use avr_sim::ram_fill::RamFill;
+use avr_sim::snapshot::{self, Snapshot};
+use avr_sim::stimulus::Stimulus;
+use avr_sim::vcd::VcdRecorder;
+use avr_sim::{Error, MemorySpace, Simulator, Watch, WatchState, parse_watch};
+use avr_sim::simavr;
 
-use byteorder::ByteOrder as _;
 use clap::{App, Arg};
-use std::fmt::Debug;
 use std::io::prelude::*;
 use std::io::{self, stderr};
-use std::{env, process, collections::BTreeMap};
+use std::{env, process, collections::BTreeMap, sync::mpsc, thread};
 
 const DEFAULT_GDB_PORT: u16 = 1234;
 
-type ByteOrder = byteorder::LittleEndian;
+/// The MCU that is simulated if none is given on the command line.
+const DEFAULT_MCU_NAME: &str = "atmega328";
 
-fn open_firmware(executable_path: Option<&std::path::Path>) -> Result<(simavr::Firmware, Vec<u8>), io::Error> {
+fn read_executable(executable_path: Option<&std::path::Path>) -> Result<Vec<u8>, io::Error> {
     // See if a path was specified on the command line.
     match executable_path {
-        Some(path) => {
-            Ok((simavr::Firmware::read_elf_via_disk(path).unwrap(), std::fs::read(path).unwrap()))
-        }
+        Some(path) => std::fs::read(path),
         // Assume standard input.
         None => {
             writeln!(stderr(), "note: no firmware path specified, reading from stdin").unwrap();
             let mut buffer = Vec::new();
             io::stdin().read_to_end(&mut buffer)?;
-            Ok((simavr::Firmware::read_elf(&buffer).unwrap(), buffer))
+            Ok(buffer)
         },
     }
 }
 
+/// Reads standard input on another thread so that the simulation does not
+/// wait for input that the program may never ask for.
+fn read_stdin_in_background() -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut buffer = [0; 256];
+        loop {
+            match io::stdin().read(&mut buffer) {
+                Ok(0) | Err(..) => break,
+                Ok(count) => if sender.send(buffer[..count].to_vec()).is_err() { break },
+            }
+        }
+    });
+
+    receiver
+}
+
 #[derive(Clone, Debug)]
 pub struct CommandLine {
     executable_path: Option<std::path::PathBuf>,
+    mcu_name: String,
     print_before: Vec<Watch>,
     print_on_change: Vec<Watch>,
     print_after: Vec<Watch>,
+    stop_when: Vec<Condition>,
+    assert_always: Vec<Condition>,
+    assert_after: Vec<Condition>,
+    save_snapshot: Option<(String, std::path::PathBuf)>,
+    load_snapshot: Option<std::path::PathBuf>,
+    check_uninit: bool,
+    sanitize: bool,
+    ram_fill: Option<RamFill>,
+    stimulus: Option<Stimulus>,
+    vcd_path: Option<std::path::PathBuf>,
+    frequency: Option<u32>,
+    uart: Option<u8>,
+    uart_input: Option<std::path::PathBuf>,
+    uart_log: Option<std::path::PathBuf>,
     gdb_server_port: Option<u16>,
-}
-
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
-pub enum MemorySpace {
-    Program,
-    Data,
-}
-
-#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
-pub enum Watch {
-    MemoryAddress {
-        space: MemorySpace,
-        address: Pointer,
-        data_type: DataType,
-    },
-    Symbol {
-        name: String,
-        data_type: DataType,
-    },
-    IoPort { port_letter: char, port_index: Option<u8> },
-    IoPin { port_letter: char, port_index: Option<u8> },
-    IoDataDirectionRegister { port_letter: char, port_index: Option<u8> },
-}
-
-#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
-pub enum DataType {
-    Char,
-    NullTerminated(Box<DataType>),
-    U8, U16, U32, U64, U128,
-    I8, I16, I32, I64, I128,
-    HighLowBit,
-    IoRegisterStatus,
-}
-
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
-pub struct Pointer {
-    pub address: u32,
-    pub natural_radix: u32,
+    list_symbols: Option<symbol_list::Format>,
 }
 
 fn parse_cmd_line() -> CommandLine {
@@ -114,6 +116,94 @@ fn parse_cmd_line() -> CommandLine {
             .help("Print a value both before the program starts and after the program completes")
             .multiple(true)
             .takes_value(true))
+        .arg(Arg::with_name("stop-when")
+            .long("stop-when")
+            .value_name("CONDITION")
+            .help("Stops the simulation as soon as the condition holds")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("assert-always")
+            .long("assert-always")
+            .value_name("CONDITION")
+            .help("Fails the simulation if the condition does not hold after any CPU cycle")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("assert-after")
+            .long("assert-after")
+            .value_name("CONDITION")
+            .help("Fails the simulation if the condition does not hold after the program completes")
+            .multiple(true)
+            .takes_value(true))
+        .arg(Arg::with_name("save-snapshot-at")
+            .long("save-snapshot-at")
+            .value_name("SYMBOL")
+            .help("Saves a snapshot of the simulator state when the program counter first reaches the given program symbol")
+            .requires("save-snapshot-to")
+            .takes_value(true))
+        .arg(Arg::with_name("save-snapshot-to")
+            .long("save-snapshot-to")
+            .value_name("FILE")
+            .help("The path to write the snapshot requested by '--save-snapshot-at' to")
+            .requires("save-snapshot-at")
+            .takes_value(true))
+        .arg(Arg::with_name("load-snapshot")
+            .long("load-snapshot")
+            .value_name("FILE")
+            .help("Resumes the simulation from a snapshot saved by an earlier run of the same executable")
+            .takes_value(true))
+        .arg(Arg::with_name("check-uninit")
+            .long("check-uninit")
+            .help("Reports every load from SRAM that has not been written since reset, failing the simulation if there are any"))
+        .arg(Arg::with_name("sanitize")
+            .long("sanitize")
+            .help("Reports every memory access outside of any known object, failing the simulation if there are any"))
+        .arg(Arg::with_name("ram-fill")
+            .long("ram-fill")
+            .value_name("zero|0xNN|random[:SEED]")
+            .help("Sets the initial contents of SRAM after the chip is flashed. Defaults to zero")
+            .takes_value(true))
+        .arg(Arg::with_name("stimulus")
+            .long("stimulus")
+            .value_name("FILE")
+            .help("Drives input pins from a script of timed events such as 'at cycle 1000: PINB3 = HIGH'")
+            .takes_value(true))
+        .arg(Arg::with_name("vcd")
+            .long("vcd")
+            .value_name("FILE")
+            .help("Records every IO register watch and numeric memory watch to a value change dump, for viewing in GTKWave")
+            .takes_value(true))
+        .arg(Arg::with_name("mcu")
+            .long("mcu")
+            .value_name("MCU")
+            .help("Sets the MCU to simulate, such as 'atmega2560' or 'attiny85'. Defaults to atmega328")
+            .takes_value(true))
+        .arg(Arg::with_name("frequency")
+            .long("frequency")
+            .value_name("HZ")
+            .help("Sets the CPU clock frequency. Defaults to F_CPU from the executable's '.mmcu' section if it has one, otherwise 16 MHz")
+            .takes_value(true))
+        .arg(Arg::with_name("uart")
+            .long("uart")
+            .value_name("N")
+            .help("Connects USART N to the simulator's input and output. Defaults to USART0")
+            .takes_value(true))
+        .arg(Arg::with_name("uart-input")
+            .long("uart-input")
+            .value_name("FILE")
+            .help("Sends the contents of a file to the UART. Defaults to standard input if the executable is not read from it")
+            .takes_value(true))
+        .arg(Arg::with_name("uart-log")
+            .long("uart-log")
+            .value_name("FILE")
+            .help("Writes every byte sent and received over the UART to a file, along with the CPU cycle it was sent in")
+            .takes_value(true))
+        .arg(Arg::with_name("list-symbols")
+            .long("list-symbols")
+            .help("Lists the symbols that can be watched by name, along with their addresses, sizes and declared types, and exits"))
+        .arg(Arg::with_name("json")
+            .long("json")
+            .requires("list-symbols")
+            .help("Prints the list of symbols as JSON"))
         .arg(Arg::with_name("EXECUTABLE PATH")
             .help("A path to the executable file to run. Defaults to standard input if not specified.")
             .required(false)
@@ -126,10 +216,20 @@ fn parse_cmd_line() -> CommandLine {
            .multiple(true)
            .help("Sets the level of verbosity"))
         .after_help(include_str!("../doc/cli_extended_help.txt"))
-        .get_matches();
+        .get_matches_safe()
+        .unwrap_or_else(|e| match e.kind {
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+            // clap exits with 1 by default, which would look like a failed test.
+            _ => {
+                eprintln!("{}", e.message);
+                process::exit(Error::CommandLine(e.message).exit_code());
+            },
+        });
 
     let parse_watches = |arg_name: &str| {
-        matches.values_of_lossy(arg_name).unwrap_or_else(|| Vec::new()).into_iter().flat_map(|watch| parse_watch(&watch).unwrap()).collect::<Vec<Watch>>()
+        matches.values_of_lossy(arg_name).unwrap_or_else(|| Vec::new()).into_iter().flat_map(|watch| {
+            parse_watch(&watch).unwrap_or_else(|e| fail(e.context(format!("invalid WATCHABLE '{}'", watch))))
+        }).collect::<Vec<Watch>>()
     };
 
     let print_on_everything = parse_watches("watch");
@@ -139,104 +239,130 @@ fn parse_cmd_line() -> CommandLine {
     let print_after = parse_watches("print-after").into_iter().chain(print_before_after.clone()).collect::<Vec<Watch>>();
     let print_on_change = parse_watches("print-on-change").into_iter().chain(print_on_everything).collect::<Vec<Watch>>();
 
+    let parse_conditions = |arg_name: &str| {
+        matches.values_of_lossy(arg_name).unwrap_or_default().into_iter().map(|condition| {
+            condition::parse_condition(&condition).unwrap_or_else(|e| fail(e.context(format!("invalid CONDITION '{}'", condition))))
+        }).collect::<Vec<Condition>>()
+    };
+
     CommandLine {
         executable_path: matches.value_of("EXECUTABLE PATH").map(Into::into),
+        mcu_name: matches.value_of("mcu").unwrap_or(DEFAULT_MCU_NAME).to_owned(),
         gdb_server_port: if matches.is_present("gdb") { Some(DEFAULT_GDB_PORT) } else { None },
-        print_before, print_on_change, print_after,
-    }
-}
-
-fn fetch_only_section_of_kind<'data, 'obj>(
-    section_kind: object::SectionKind,
-    object: &'obj object::read::File<'data>,
-) -> Result<object::read::Section<'data, 'obj>, String> {
-    use object::read::{Object, ObjectSection};
-
-    let matching_sections = object.sections().filter(|s| {
-        s.kind() == section_kind
-    }).collect::<Vec<_>>();
-
-    match matching_sections.len() {
-        0 => Err(format!("there is no ELF section of kind {:?}", section_kind)),
-        1 => Ok(matching_sections.into_iter().next().unwrap()),
-        _ => {
-            let section_names = matching_sections.iter().map(|s| s.name().map(ToOwned::to_owned).unwrap_or(String::new())).collect::<Vec<_>>();
-            Err(format!("there is more than one {:?} section (names: {})", section_kind, section_names.join(", ")))
+        stop_when: parse_conditions("stop-when"),
+        assert_always: parse_conditions("assert-always"),
+        assert_after: parse_conditions("assert-after"),
+        save_snapshot: matches.value_of("save-snapshot-at").map(ToOwned::to_owned)
+            .and_then(|symbol| matches.value_of("save-snapshot-to").map(|path| (symbol, path.into()))),
+        load_snapshot: matches.value_of("load-snapshot").map(Into::into),
+        check_uninit: matches.is_present("check-uninit"),
+        sanitize: matches.is_present("sanitize"),
+        stimulus: matches.value_of("stimulus").map(|path| Stimulus::load(path.as_ref()).unwrap_or_else(|e| fail(e))),
+        vcd_path: matches.value_of("vcd").map(Into::into),
+        frequency: matches.value_of("frequency").map(|frequency| match frequency.parse() {
+            Ok(frequency) if frequency > 0 => frequency,
+            _ => fail(Error::CommandLine(format!("invalid frequency '{}', expected a whole number of Hz", frequency))),
+        }),
+        uart: matches.value_of("uart").map(|uart| match uart.parse() {
+            Ok(uart) if uart < 10 => uart,
+            _ => fail(Error::CommandLine(format!("invalid USART '{}', expected a number such as 0 or 1", uart))),
+        }),
+        uart_input: matches.value_of("uart-input").map(Into::into),
+        uart_log: matches.value_of("uart-log").map(Into::into),
+        ram_fill: matches.value_of("ram-fill").map(|fill| fill.parse().unwrap_or_else(|e: Error| fail(e.context(format!("invalid RAM fill '{}'", fill))))),
+        list_symbols: if matches.is_present("list-symbols") {
+            Some(if matches.is_present("json") { symbol_list::Format::Json } else { symbol_list::Format::Text })
+        } else {
+            None
         },
+        print_before, print_on_change, print_after,
     }
 }
 
-#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
-pub struct WatchableSymbol {
-    pub name: String,
-    pub memory_space: MemorySpace,
-    /// The pointer as relative to the start of the memory space.
-    pub address: Pointer,
-}
+fn main() {
+    let original_command_line = self::parse_cmd_line();
+    let mut command_line = original_command_line.clone();
 
-// TODO: it should be possible to ask for the list of these from the command line.
-fn parse_watchable_symbols_from_elf(elf_data: &[u8], avr: &simavr::Avr) -> Vec<WatchableSymbol> {
-    use object::read::{Object, ObjectSection};
+    let firmware_buffer = read_executable(command_line.executable_path.as_ref().map(|p| p as _)).unwrap_or_else(|e| {
+        fail(Error::Elf(format!("could not open firmware: {}", e)))
+    });
 
-    let object = object::read::File::parse(elf_data).unwrap();
-    let mut watchables = Vec::new();
+    if let Some(format) = command_line.list_symbols {
+        let watchable_symbols = avr_sim::parse_watchable_symbols_from_elf(&firmware_buffer).unwrap_or_else(|e| fail(e));
+        let debug_info = DebugInfo::parse(&firmware_buffer).unwrap_or_else(|e| {
+            eprintln!("warning: could not read debug information, types and source lines will not be listed: {}", e);
+            DebugInfo::default()
+        });
 
-    let text_section = fetch_only_section_of_kind(object::SectionKind::Text, &object).unwrap();
-    let data_section = fetch_only_section_of_kind(object::SectionKind::Data, &object).unwrap();
+        symbol_list::print(format, &watchable_symbols, &debug_info);
+        return;
+    }
 
-    'symbols: for (_, symbol) in object.symbols() {
-        let symbol_name = if let Some(name) = symbol.name() {
-            name.trim()
-        } else {
-            continue 'symbols; // skip nameless symbols
-        };
+    let mut simulator = Simulator::new(&command_line.mcu_name, &firmware_buffer).unwrap_or_else(|e| fail(e));
 
-        if symbol_name.is_empty() {
-            continue 'symbols; // skip symbols with empty name.
+    if let Some(ram_fill) = command_line.ram_fill {
+        if let Some(seed) = simulator.fill_ram(ram_fill) {
+            if ram_fill == (RamFill::Random { seed: None }) {
+                eprintln!("note: filling RAM with random seed {} (use '--ram-fill random:{}' to reproduce)", seed, seed);
+            }
         }
+    }
 
-        let (parent_section, memory_space, extra_offset) = if symbol.section_index() == Some(text_section.index()) {
-            (&text_section, MemorySpace::Program, 0)
-        } else if symbol.section_index() == Some(data_section.index()) {
-            let data_section_load_start = avr.raw().ioend as u32 + 1;
-            (&data_section, MemorySpace::Data, data_section_load_start)
-        } else {
-            continue 'symbols; // skip symbols not in text or data sections
-        };
-
-        let relative_address = Pointer { address: extra_offset + (symbol.address() - parent_section.address()) as u32, natural_radix: 16 };
-        watchables.push(WatchableSymbol {
-            name: symbol_name.to_owned(), memory_space, address: relative_address,
-        });
+    if let Some(frequency) = command_line.frequency {
+        simulator.set_frequency(frequency);
     }
 
-    watchables
-}
+    if let Err(e) = simulator.attach_uart(command_line.uart.unwrap_or(0)) {
+        // Not every MCU has a USART, so only fail if one was asked for.
+        if command_line.uart.is_some() || command_line.uart_input.is_some() {
+            fail(e);
+        }
+    }
 
-fn main() {
-    let original_command_line = self::parse_cmd_line();
-    let mut command_line = original_command_line.clone();
+    let uart_input = match command_line.uart_input {
+        Some(ref path) => {
+            let input = std::fs::read(path).unwrap_or_else(|e| fail(Error::CommandLine(format!("could not read '{}': {}", path.display(), e))));
+            simulator.send_uart_input(&input).unwrap_or_else(|e| fail(e));
+            None
+        },
+        // Standard input has already been read if the executable came from it.
+        None if command_line.executable_path.is_some() => Some(read_stdin_in_background()),
+        None => None,
+    };
 
-    let mut avr = simavr::Avr::new("atmega328").unwrap();
+    let mut uart_log = command_line.uart_log.as_ref().map(|path| {
+        let file = std::fs::File::create(path).unwrap_or_else(|e| fail(Error::CommandLine(format!("could not create '{}': {}", path.display(), e))));
+        io::BufWriter::new(file)
+    });
 
-    let (firmware, firmware_buffer) = open_firmware(command_line.executable_path.as_ref().map(|p| p as _)).expect("could not open firmware");
+    let watchable_symbols = simulator.watchable_symbols().to_owned();
+    print_warnings_for_unresolved_watches(&mut command_line, &simulator);
+    check_conditions_are_resolved(&command_line, &simulator);
 
-    avr.flash(&firmware);
-    simavr::uart::attach_to_stdout(&mut avr);
+    let snapshot_address = command_line.save_snapshot.as_ref().map(|(symbol_name, _)| {
+        match watchable_symbols.iter().find(|s| s.name == *symbol_name && s.memory_space == MemorySpace::Program) {
+            Some(symbol) => symbol.address.address,
+            None => fail(Error::MissingSymbol(format!("cannot save a snapshot at '{}' because it is not a program symbol in the ELF file", symbol_name))),
+        }
+    });
+    // Everything that changes the text of the transcript, including the times in the headings of changed values.
+    let output_fingerprint = snapshot::hash(format!("{:?} {:?} {} {:?}", command_line.print_before, command_line.print_on_change,
+                                                    simulator.frequency(), command_line.uart).as_bytes());
 
-    // NOTE: this always needs to happen after the AVR program is flashed.
-    let watchable_symbols = parse_watchable_symbols_from_elf(&firmware_buffer, &avr);
-    print_warnings_for_unresolved_watches(&mut command_line, &watchable_symbols);
+    if command_line.check_uninit {
+        simulator.enable_uninit_checks();
+    }
+    if command_line.sanitize {
+        simulator.enable_sanitizer();
+    }
+    if let Some(ref stimulus) = command_line.stimulus {
+        simulator.set_stimulus(stimulus).unwrap_or_else(|e| fail(e.context("invalid stimulus script")));
+    }
 
-    let print_config = match avr_print::Config::new(&watchable_symbols) {
-        Ok(config) => Some(config),
-        Err(message) => {
-            eprintln!("warning: cannot intercept and print the libavrlit debug stream: {}.", message);
-            None
-        },
-    };
+    print_diagnostics(&mut simulator);
 
     if let Some(gdb_port) = command_line.gdb_server_port {
+        let avr = simulator.avr_mut();
         avr.raw_mut().gdb_port = gdb_port as i32;
         avr.raw_mut().state = simavr::sys::cpu_Stopped as _;
 
@@ -256,29 +382,94 @@ fn main() {
         println!("  {}", example_gdb_command);
     }
 
+    if command_line.save_snapshot.is_some() {
+        output::start_recording();
+    }
+
+    match command_line.load_snapshot {
+        Some(ref snapshot_path) => {
+            let snapshot = Snapshot::load(snapshot_path).unwrap_or_else(|e| {
+                fail(e.context(format!("could not load snapshot '{}'", snapshot_path.display())))
+            });
+
+            // Replay what an uninterrupted run would have printed up until the snapshot point.
+            if snapshot.output_fingerprint == output_fingerprint {
+                output!("{}", snapshot.transcript);
+            } else {
+                eprintln!("warning: the watches or clock frequency differ from the run that saved the snapshot, output from before the snapshot point will not be shown");
+                dump_values("before_execution", &command_line.print_before[..], &simulator);
+            }
+
+            if let Err(e) = simulator.restore(&snapshot) {
+                fail(e.context(format!("could not restore snapshot '{}'", snapshot_path.display())));
+            }
+        },
+        None => {
+            dump_values("before_execution", &command_line.print_before[..], &simulator);
+        },
+    }
 
-    dump_values("before_execution", &command_line.print_before[..], &watchable_symbols, &avr);
+    let mut vcd_recorder = command_line.vcd_path.as_ref().map(|path| {
+        let file = std::fs::File::create(path).unwrap_or_else(|e| fail(Error::CommandLine(format!("could not create '{}': {}", path.display(), e))));
+        let watches = command_line.print_before.iter().chain(command_line.print_on_change.iter()).chain(command_line.print_after.iter()).cloned().collect::<Vec<_>>();
 
-    let mut prior_values_watched_onchange = get_current_values(&command_line.print_on_change[..], &watchable_symbols, &avr);
+        VcdRecorder::new(io::BufWriter::new(file), &watches, &simulator).unwrap_or_else(|e| fail(e.context("could not start recording")))
+    });
+
+    let mut prior_values_watched_onchange = get_current_values(&command_line.print_on_change[..], &simulator);
+    let mut failed_assertion = None;
 
     loop {
-        let current_cycle_number = avr.raw().run_cycle_count;
+        if snapshot_address == Some(simulator.avr().raw().pc) {
+            let (symbol_name, snapshot_path) = command_line.save_snapshot.take().unwrap();
+            let snapshot = simulator.snapshot(output_fingerprint, output::recorded_transcript()).and_then(|snapshot| snapshot.save(&snapshot_path));
 
-        let sim_state =  avr.run_cycle();
+            if let Err(e) = snapshot {
+                fail(e.context(format!("could not save snapshot to '{}'", snapshot_path.display())));
+            }
+            eprintln!("note: saved snapshot at '{}' in CPU cycle #{} to '{}'", symbol_name, simulator.avr().raw().cycle, snapshot_path.display());
+        }
 
-        if let Some(print_config) = print_config.as_ref() {
-            if let Some(c) = print_config.consume_character(&avr).expect("could not read from libavrlit debug stream") {
-                print!("{}", c);
+        if let Some(receiver) = uart_input.as_ref() {
+            while let Ok(input) = receiver.try_recv() {
+                simulator.send_uart_input(&input).unwrap_or_else(|e| fail(e));
             }
         }
 
-        dump_onchanged_watches(&mut prior_values_watched_onchange, &command_line, &watchable_symbols, &avr, current_cycle_number);
+        let step = simulator.step().unwrap_or_else(|e| fail(e));
+
+        output!("{}", simulator.take_uart_output());
+        output!("{}", simulator.take_debug_stream());
 
-        match sim_state {
+        let uart_transfers = simulator.take_uart_transfers();
+        if let Some(log) = uart_log.as_mut() {
+            for transfer in uart_transfers {
+                writeln!(log, "{}", transfer).unwrap_or_else(|e| fail(Error::CommandLine(format!("could not write the UART log: {}", e))));
+            }
+        }
+        print_diagnostics(&mut simulator);
+
+        dump_onchanged_watches(&mut prior_values_watched_onchange, &command_line, &simulator, step.cycle);
+
+        if let Some(recorder) = vcd_recorder.as_mut() {
+            recorder.record(&simulator).unwrap_or_else(|e| fail(e));
+        }
+
+        if let Some(condition) = first_condition_matching(&command_line.assert_always, false, &simulator) {
+            report_failed_assertion(condition, &format!("in CPU cycle #{} at PC 0x{:04x}", step.cycle, step.pc), &simulator);
+            failed_assertion = Some(condition);
+            break;
+        }
+
+        if let Some(condition) = first_condition_matching(&command_line.stop_when, true, &simulator) {
+            eprintln!("note: stopping simulation in CPU cycle #{} at PC 0x{:04x} because '{}' holds", step.cycle, step.pc, condition);
+            break;
+        }
+
+        match step.state {
             simavr::State::Running | simavr::State::Stopped => (),
             simavr::State::Crashed => {
-                writeln!(stderr(), "simulation crashed").unwrap();
-                process::exit(1);
+                fail(Error::SimulationFault(format!("simulation crashed in CPU cycle #{} at PC 0x{:04x}", step.cycle, step.pc)));
             },
             // Keep running when in setup,limbo,etc.
             state if !state.is_running() => break,
@@ -287,20 +478,90 @@ fn main() {
         }
     }
 
-    dump_onchanged_watches(&mut prior_values_watched_onchange, &command_line, &watchable_symbols, &avr, avr.raw().run_cycle_count);
+    dump_onchanged_watches(&mut prior_values_watched_onchange, &command_line, &simulator, simulator.avr().raw().cycle);
+
+    dump_values("after_execution", &command_line.print_after[..], &simulator);
+
+    let total_cycles = simulator.avr().raw().cycle;
+    outputln!();
+    outputln!("Simulated {} CPU cycles ({} at {} Hz)", total_cycles, simulator.time_of_cycle(total_cycles), simulator.frequency());
+
+    if let Some(mut log) = uart_log {
+        log.flush().unwrap_or_else(|e| fail(Error::CommandLine(format!("could not write the UART log: {}", e))));
+    }
+
+    if let Some(recorder) = vcd_recorder {
+        recorder.finish().unwrap_or_else(|e| fail(e));
+    }
+
+    if let Some((symbol_name, _)) = command_line.save_snapshot {
+        fail(Error::SimulationFault(format!("the program never reached '{}', so no snapshot was saved", symbol_name)));
+    }
+
+    for condition in command_line.assert_after.iter() {
+        if first_condition_matching(std::slice::from_ref(condition), false, &simulator).is_some() {
+            report_failed_assertion(condition, &format!("after execution in CPU cycle #{} at PC 0x{:04x}", simulator.avr().raw().cycle, simulator.avr().raw().pc), &simulator);
+            failed_assertion = Some(condition);
+        }
+    }
+
+    if failed_assertion.is_some() || simulator.found_errors() {
+        process::exit(avr_sim::error::TEST_FAILURE_EXIT_CODE);
+    }
+}
+
+/// Reports an error that prevents the simulation from running and exits.
+fn fail(error: Error) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(error.exit_code());
+}
+
+/// Prints warnings and errors found by the simulator.
+fn print_diagnostics(simulator: &mut Simulator) {
+    for diagnostic in simulator.take_diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+}
+
+/// Finds the first condition that evaluates to the given truth value.
+///
+/// Conditions that cannot be evaluated are fatal errors. Conditions on local
+/// variables that are out of scope or optimized out match neither truth value.
+fn first_condition_matching<'c>(
+    conditions: &'c [Condition],
+    expected: bool,
+    simulator: &Simulator,
+) -> Option<&'c Condition> {
+    conditions.iter().find(|condition| {
+        match condition.evaluate(simulator.avr(), simulator.watchable_symbols(), simulator.debug_info()) {
+            Ok(Some(holds)) => holds == expected,
+            Ok(None) => false,
+            Err(e) => fail(e.context(format!("could not evaluate condition '{}'", condition))),
+        }
+    })
+}
+
+fn report_failed_assertion(
+    condition: &Condition,
+    when: &str,
+    simulator: &Simulator,
+) {
+    eprintln!("error: assertion '{}' failed {}", condition, when);
 
-    dump_values("after_execution", &command_line.print_after[..], &watchable_symbols, &avr);
+    let operands = condition.describe_operands(simulator.avr(), simulator.watchable_symbols(), simulator.debug_info());
+    if !operands.is_empty() {
+        eprintln!("note: {}", operands);
+    }
 }
 
 fn dump_onchanged_watches(
     prior_values_watched_onchange: &mut BTreeMap<Watch, WatchState>,
     command_line: &CommandLine,
-    watchable_symbols: &[WatchableSymbol],
-    avr: &simavr::Avr,
+    simulator: &Simulator,
     current_cycle_number: u64,
 ) {
     let changed_watches = {
-        let current_values_watched_onchange = self::get_current_values(&command_line.print_on_change[..], watchable_symbols, &avr);
+        let current_values_watched_onchange = self::get_current_values(&command_line.print_on_change[..], simulator);
         let changed_watches = self::get_changed_watches(prior_values_watched_onchange, &current_values_watched_onchange);
 
         *prior_values_watched_onchange = current_values_watched_onchange;
@@ -308,7 +569,7 @@ fn dump_onchanged_watches(
     };
 
     if !changed_watches.is_empty() {
-        print_heading(&format!("Dumping watches values changed in CPU cycle #{}", current_cycle_number));
+        print_heading(&format!("Dumping watches values changed in CPU cycle #{} ({})", current_cycle_number, simulator.time_of_cycle(current_cycle_number)));
 
         for (watch, current_value) in changed_watches {
             dump_value("changed", &watch, &current_value);
@@ -334,29 +595,27 @@ fn get_changed_watches(before: &BTreeMap<Watch, WatchState>, after: &BTreeMap<Wa
 
 fn dump_values(label: &str,
                watches: &[Watch],
-               watchable_symbols: &[WatchableSymbol],
-               avr: &simavr::Avr) {
+               simulator: &Simulator) {
     if !watches.is_empty() {
         print_heading(&format!("Dumping all {}", label.replace("_", " ")));
 
         for watch in watches {
-            dump_watch(label, watch, watchable_symbols, avr);
+            dump_watch(label, watch, simulator);
         }
     }
 }
 
 /// Gets the current values of the given watches.
-fn get_current_values(watches: &[Watch], watchable_symbols: &[WatchableSymbol], avr: &simavr::Avr) -> BTreeMap<Watch, WatchState> {
+fn get_current_values(watches: &[Watch], simulator: &Simulator) -> BTreeMap<Watch, WatchState> {
     watches.iter().flat_map(|watch| {
-        warn_on_error(&format!("get {:?}", watch), || watch.current_value(&avr, watchable_symbols)).map(|value| (watch.clone(), value))
+        warn_on_error(&format!("get {:?}", watch), || simulator.read_watch(watch)).map(|value| (watch.clone(), value))
     }).collect()
 }
 
 fn dump_watch(label: &str,
               watch: &Watch,
-              watchable_symbols: &[WatchableSymbol],
-              avr: &simavr::Avr) {
-    let current_value = warn_on_error(&format!("get {:?}", watch), || watch.current_value(&avr, watchable_symbols));
+              simulator: &Simulator) {
+    let current_value = warn_on_error(&format!("get {:?}", watch), || simulator.read_watch(watch));
 
     if let Some(current_value) = current_value {
         self::dump_value(label, watch, &current_value);
@@ -372,16 +631,16 @@ fn dump_value(label: &str,
     if is_multi_line {
         for (i, line) in current_value.lines().enumerate() {
             let line_details = if is_multi_line { format!("[line {}]", i + 1) } else { "".to_owned() };
-            println!("{}({}){} = {}", label, watch.location(), line_details, line);
+            outputln!("{}({}){} = {}", label, watch.location(), line_details, line);
         }
     } else {
-        println!("{}({}) = {}", label, watch.location(), current_value);
+        outputln!("{}({}) = {}", label, watch.location(), current_value);
     }
 }
 
 fn print_warnings_for_unresolved_watches(
     command_line: &mut CommandLine,
-    watchable_symbols: &[WatchableSymbol],
+    simulator: &Simulator,
 ) {
     let watchlists = vec![
         &mut command_line.print_before,
@@ -393,21 +652,14 @@ fn print_warnings_for_unresolved_watches(
     unique_watches.sort();
     unique_watches.dedup();
 
-    // Identify and warn about missing watches.
+    // Identify and warn about missing and out of range watches.
     let missing_watches = unique_watches.into_iter().filter(|w| {
-        match *w {
-            Watch::Symbol { ref name, .. } => {
-                if let Some(..) = watchable_symbols.iter().find(|s| s.name == *name) {
-                    false
-                } else {
-                    eprintln!("the symbol '{}' does not exist in the ELF file, or the ELF file contains no debug information", name);
-                    true
-                }
+        match simulator.check_watch(w) {
+            Ok(()) => false,
+            Err(e) => {
+                eprintln!("{}", e);
+                true
             },
-            Watch::MemoryAddress { .. } => false, // technically, we could do range checks here.
-            Watch::IoPort { .. } |
-                Watch::IoPin { .. } |
-                Watch::IoDataDirectionRegister { .. } => false, // technically, we could check that the device supports this port.
         }
     }).cloned().collect::<Vec<_>>();
 
@@ -419,14 +671,29 @@ fn print_warnings_for_unresolved_watches(
     }
 }
 
-fn print_heading(heading: &str) {
-    println!();
-    println!("{}", heading);
-    for _ in 0..heading.len() {
-        print!("=");
+/// Conditions cannot simply be skipped like watches, so unresolved symbols and addresses are fatal.
+fn check_conditions_are_resolved(
+    command_line: &CommandLine,
+    simulator: &Simulator,
+) {
+    let conditions = command_line.stop_when.iter()
+        .chain(command_line.assert_always.iter())
+        .chain(command_line.assert_after.iter());
+
+    for condition in conditions {
+        for watch in condition.watches() {
+            if let Err(e) = simulator.check_watch(watch) {
+                fail(e.context(format!("the condition '{}' cannot be evaluated", condition)));
+            }
+        }
     }
-    println!();
-    println!();
+}
+
+fn print_heading(heading: &str) {
+    outputln!();
+    outputln!("{}", heading);
+    outputln!("{}", "=".repeat(heading.len()));
+    outputln!();
 }
 
 fn warn_on_error<T, E>(what_we_are_doing: &str, f: impl FnOnce() -> Result<T, E>)
@@ -440,448 +707,3 @@ fn warn_on_error<T, E>(what_we_are_doing: &str, f: impl FnOnce() -> Result<T, E>
         },
     }
 }
-
-impl Watch {
-    fn current_value(&self,
-        avr: &simavr::Avr,
-        watchable_symbols: &[WatchableSymbol],
-    ) -> Result<WatchState, String> {
-        fn read_io_port(port_letter: char, port_index: Option<u8>, avr: &simavr::Avr, f: impl FnOnce(IoState) -> u8) -> Result<WatchState, String> {
-            let io_state = read_io_state(port_letter, avr);
-            let relevant_value = f(io_state);
-
-            match port_index {
-                Some(i) => {
-                    let mask = (0b1) << i;
-                    Ok(WatchState::HighLowBit(relevant_value & mask == mask))
-                },
-                None => {
-                    Ok(WatchState::IoRegisterStatus(relevant_value))
-                },
-            }
-        }
-
-        match *self {
-            Watch::MemoryAddress { space, address, ref data_type } => {
-                let bytes = self::read_current_memory_address(space, address, avr)?;
-                data_type.as_watch_state_from_bytes(bytes)
-            },
-            Watch::Symbol { ref name, ref data_type } => {
-                let bytes = match watchable_symbols.iter().find(|s| s.name == *name) {
-                    Some(WatchableSymbol { memory_space, address, .. }) => {
-                        self::read_current_memory_address(*memory_space, address.clone(), avr)?
-                    },
-                    None => return Err(format!("the symbol '{}' does not exist in the ELF file, or the ELF file contains no debug information", name)),
-                };
-                data_type.as_watch_state_from_bytes(bytes)
-            },
-            Watch::IoPort { port_letter, port_index } => {
-                read_io_port(port_letter, port_index, avr, |s| s.port)
-            },
-            Watch::IoPin { port_letter, port_index } => {
-                read_io_port(port_letter, port_index, avr, |s| s.pin)
-            },
-            Watch::IoDataDirectionRegister { port_letter, port_index } => {
-                read_io_port(port_letter, port_index, avr, |s| s.data_direction_register)
-            },
-        }
-
-    }
-
-    fn location(&self) -> String {
-        match *self {
-            Watch::MemoryAddress { ref space, ref address, .. } => format!("{} ({})", address, space.human_label()),
-            Watch::Symbol { ref name, .. } => name.to_owned(),
-            Watch::IoPort { port_letter, port_index } => format!("IO PORT{}{}", port_letter, if let Some(i) = port_index { i.to_string() } else { String::new() }),
-            Watch::IoPin { port_letter, port_index } => format!("IO PIN{}{}", port_letter, if let Some(i) = port_index { i.to_string() } else { String::new() }),
-            Watch::IoDataDirectionRegister { port_letter, port_index } => format!("IO DDR{}{}", port_letter, if let Some(i) = port_index { i.to_string() } else { String::new() }),
-        }
-    }
-}
-
-/// Gets an immutable byte slice starting at the specified AVR memory address.
-fn read_current_memory_address<'avr>(
-    space: MemorySpace,
-    address: Pointer,
-    avr: &'avr simavr::Avr,
-) -> Result<&'avr [u8], String> {
-    let (memory_space_start_host_ptr, memory_space_size) = memory_space_slice_parts(space, avr);
-    let memory_space_byte_slice = unsafe { std::slice::from_raw_parts(memory_space_start_host_ptr, memory_space_size) };
-
-    Ok(&memory_space_byte_slice[address.address as usize..])
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub enum WatchState {
-    Char(char),
-    Array { elements: Vec<WatchState>, data_type: DataType },
-    U8(u8),
-    U16(u16),
-    U32(u32),
-    U64(u64),
-    U128(u128),
-    I8(i8),
-    I16(i16),
-    I32(i32),
-    I64(i64),
-    I128(i128),
-    HighLowBit(bool),
-    IoRegisterStatus(u8),
-}
-
-impl std::fmt::Display for WatchState {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            WatchState::Char(c) => std::fmt::Display::fmt(&c, fmt),
-            WatchState::U8(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::U16(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::U32(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::U64(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::U128(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::I8(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::I16(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::I32(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::I64(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::I128(i) => std::fmt::Display::fmt(&i, fmt),
-            WatchState::Array { ref elements, ref data_type } => {
-                let formatted_str = if *data_type == DataType::Char {
-                    let chars = elements.iter().map(|e| if let WatchState::Char(c) = e { c } else { unreachable!() });
-                    format!("{:?}", chars.collect::<String>())
-                } else {
-                    format!("{:?}", elements)
-                };
-
-                write!(fmt, "{}", formatted_str)
-            },
-            WatchState::HighLowBit(b) => {
-                let label = if b { "HIGH" } else { "LOW" };
-                write!(fmt, "{}", label)
-            },
-            WatchState::IoRegisterStatus(r) => {
-                for i in 0..8 {
-                    let is_set = r & (1<<i) == (1<<i);
-                    write!(fmt, "{}: {}", i, if is_set { "HIGH" } else { " LOW" })?;
-                    write!(fmt, ", ")?;
-                }
-
-                Ok(())
-            },
-        }
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct IoState {
-    port: u8,
-    pin: u8,
-    data_direction_register: u8,
-}
-
-const fn avr_ioctl_def(a: char, b: char, c: char, d: char) -> u32 {
-    ((a as u32) << 24) | ((b as u32) << 16) | ((c as u32) << 8)| ((d as u32))
-}
-
-const fn avr_ioctl_ioport_get_state(port_name: char) -> u32 {
-    avr_ioctl_def('i', 'o', 's', port_name)
-}
-
-fn read_io_state(port_letter: char, avr: &simavr::Avr) -> IoState {
-    let mut state: simavr::sys::avr_ioport_state_t = unsafe { std::mem::zeroed() };
-
-    let result = unsafe {
-        simavr::sys::avr_ioctl(avr.underlying(), avr_ioctl_ioport_get_state(port_letter), &mut state as *mut _ as *mut libc::c_void)
-    };
-
-    if result != 0 {
-        panic!("avr ioctl failed for port '{}'", port_letter);
-    }
-
-    IoState {
-        port: state.port() as u8,
-        pin: state.pin() as u8,
-        data_direction_register: state.ddr() as u8,
-    }
-}
-
-/// Gets a mutable byte slice starting at the specified AVR memory address.
-fn read_current_memory_address_mut<'avr>(
-    space: MemorySpace,
-    address: Pointer,
-    avr: &'avr simavr::Avr,
-) -> Result<&'avr mut [u8], String> {
-    let (memory_space_start_host_ptr, memory_space_size) = memory_space_slice_parts(space, avr);
-    let memory_space_byte_slice = unsafe { std::slice::from_raw_parts_mut(memory_space_start_host_ptr as *mut _, memory_space_size) };
-
-    Ok(&mut memory_space_byte_slice[address.address as usize..])
-}
-
-fn memory_space_slice_parts<'avr>(space: MemorySpace, avr: &'avr simavr::Avr) -> (*const u8, usize) {
-    match space {
-        MemorySpace::Data => {
-            let (data_space_start, data_space_size) = unsafe {
-                ((*avr.underlying()).data as *const u8, (*avr.underlying()).ramend as usize) // N.B. 'ramend' really is the size. misnomer.
-            };
-
-            (data_space_start, data_space_size)
-        },
-        MemorySpace::Program => unimplemented!("watches on program space"),
-    }
-}
-
-
-impl DataType {
-    fn as_watch_state_from_bytes(&self, bytes: &[u8]) -> Result<WatchState, String> {
-        self.as_watch_state_from_bytes_internal(bytes).map(|(s, _)| s)
-    }
-
-    fn as_watch_state_from_bytes_internal<'b>(&self, bytes: &'b [u8]) -> Result<(WatchState, &'b [u8]), String> {
-        let parse_number = |byte_count: usize, interpret_bytes: fn(&[u8]) -> WatchState| {
-            bytes.get(0..byte_count).ok_or("end of memory".to_string())
-                .map(interpret_bytes)
-                .map(|s| (s, &bytes[byte_count..]))
-        };
-
-        match *self {
-            DataType::U8 => bytes.get(0).cloned().map(WatchState::U8).ok_or("end of memory".to_string()).map(|s| (s, &bytes[1..])),
-            DataType::I8 => bytes.get(0).map(|&b| WatchState::I8(b as i8)).ok_or("end of memory".to_string()).map(|s| (s, &bytes[1..])),
-            DataType::U16 => parse_number(2, |bytes| WatchState::U16(ByteOrder::read_u16(bytes))),
-            DataType::I16 => parse_number(2, |bytes| WatchState::I16(ByteOrder::read_i16(bytes))),
-            DataType::U32 => parse_number(4, |bytes| WatchState::U32(ByteOrder::read_u32(bytes))),
-            DataType::I32 => parse_number(4, |bytes| WatchState::I32(ByteOrder::read_i32(bytes))),
-            DataType::U64 => parse_number(8, |bytes| WatchState::U64(ByteOrder::read_u64(bytes))),
-            DataType::I64 => parse_number(8, |bytes| WatchState::I64(ByteOrder::read_i64(bytes))),
-            DataType::U128 => parse_number(16, |bytes| WatchState::U128(ByteOrder::read_u128(bytes))),
-            DataType::I128 => parse_number(16, |bytes| WatchState::I128(ByteOrder::read_i128(bytes))),
-            DataType::Char => bytes.get(0).map(|&b| WatchState::Char(b as char)).ok_or("end of memory".to_string()).map(|s| (s, &bytes[1..])),
-            DataType::NullTerminated(ref element_type) => {
-                let mut elements: Vec<WatchState> = Vec::new();
-
-                let bytes_after_null = if let Some(first_null_index) = bytes.iter().position(|&b| b == 0) {
-                    let before_and_including_null = &bytes[0..first_null_index + 1];
-                    let after_null = &bytes[first_null_index..];
-
-                    let mut left_to_process = before_and_including_null;
-
-                    while left_to_process.len() > 1 { // wait until null empty.
-                        let (element, remaining) = element_type.as_watch_state_from_bytes_internal(left_to_process)?;
-                        elements.push(element);
-                        left_to_process = remaining;
-                    }
-
-                    after_null
-                } else {
-                    &[]
-                };
-
-                Ok((WatchState::Array { elements, data_type: *element_type.clone() }, bytes_after_null))
-            },
-            DataType::HighLowBit => bytes.get(0).cloned().map(|b| WatchState::HighLowBit(if b != 0 { true } else { false })).ok_or("end of memory".to_string()).map(|s| (s, &bytes[1..])),
-            DataType::IoRegisterStatus => bytes.get(0).cloned().map(WatchState::IoRegisterStatus).ok_or("end of memory".to_string()).map(|s| (s, &bytes[1..])),
-        }
-    }
-}
-
-// Parses a watch from a string. one watch may correspond to multiple backend watches.
-fn parse_watch(s: &str) -> Result<Vec<Watch>, String> {
-    let s = s.trim();
-
-    fn io_port_from_str(remaining: &str, f: impl FnOnce(char, Option<u8>) -> Vec<Watch>) -> Result<Vec<Watch>, String> {
-        let equals_char_index = match remaining.find('=') {
-            Some(index) => index,
-            None => return Err(format!("expected IO port to include an equals sign and a data type")),
-        };
-        let specified_port = &remaining[(equals_char_index+1)..];
-        let port_letter = match specified_port.chars().nth(0).map(|mut c| { c.make_ascii_uppercase(); c }) {
-            Some(c) => c,
-            None => return Err(format!("expected IO port to include a port letter and an optional index after the equals sign")),
-        };
-        let port_index: Option<u8> = match specified_port.chars().nth(1) {
-            Some(c) => match c.to_string().parse() {
-                Ok(c) => Some(c),
-                Err(_) => return Err(format!("port index is not an integer")),
-            },
-            None => None,
-        };
-
-        Ok(f(port_letter, port_index))
-    }
-
-    if let Some(remaining) = util::try_consume("datamem", &s) {
-        let mut equals_char_indices = remaining.match_indices('=').map(|(i, _)| i);
-
-        let (address, data_type): (String, String) = match (equals_char_indices.next(), equals_char_indices.next()) {
-            (None, None) => return Err(format!("expected data memory address to include an address and data type separated by equals signs")),
-            (Some(_), None) => return Err(format!("expected data memory address to include a data type separated by equals sign")),
-            (Some(a), Some(dt)) => (remaining.chars().skip(a + 1).take(dt - a - 1).collect(), remaining.chars().skip(dt + 1).collect()),
-            (None, Some(_)) => unreachable!(),
-        };
-
-        address.parse().and_then(|address| data_type.parse().map(|dt| (address, dt))).map(|(address, data_type)| {
-            vec![Watch::MemoryAddress { address, data_type, space: MemorySpace::Data }]
-        })
-    } else if let Some(remaining) = util::try_consume("io-port", &s) {
-        io_port_from_str(remaining, |port_letter, port_index| vec![Watch::IoPort { port_letter, port_index }])
-    } else if let Some(remaining) = util::try_consume("io-pin", &s) {
-        io_port_from_str(remaining, |port_letter, port_index| vec![Watch::IoPin { port_letter, port_index }])
-    } else if let Some(remaining) = util::try_consume("io-ddr", &s) {
-        io_port_from_str(remaining, |port_letter, port_index| vec![Watch::IoDataDirectionRegister { port_letter, port_index }])
-    } else if let Some(remaining) = util::try_consume("io", &s) {
-        io_port_from_str(remaining, |port_letter, port_index| vec![
-            Watch::IoPort { port_letter, port_index },
-            Watch::IoPin { port_letter, port_index },
-            Watch::IoDataDirectionRegister { port_letter, port_index },
-        ])
-    } else if s.chars().filter(|&c| c == '=').count() >= 1 { // symbol name watchables only have one equals sign
-        let (symbol_name, data_type_str) = s.split_at(s.find("=").unwrap());
-        let data_type_str = &data_type_str[1..];
-
-        data_type_str.parse().map(|data_type| {
-            vec![Watch::Symbol { name: symbol_name.to_owned(), data_type }]
-        })
-    } else {
-        Err(format!("invalid WATCHABLE: {}", s))
-    }
-}
-
-impl std::str::FromStr for Pointer {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, String> {
-        let s = s.trim();
-
-        let first_char = s.chars().next();
-        let second_char = s.chars().skip(1).next();
-
-        if s.chars().all(|c| c.is_digit(10)) {
-            u32::from_str_radix(s, 10).map(|address| Pointer { address, natural_radix: 10 }).map_err(|e| format!("could not parse base-10 integer ({:?}) as pointer: {}", s, e))
-        } else if first_char == Some('0') && (second_char == Some('x') || second_char == Some('X')) {
-            let (_, hex_digits) = s.split_at(2);
-            u32::from_str_radix(hex_digits, 16).map(|address| Pointer { address, natural_radix: 16 }).map_err(|e| format!("could not parse base-16 integer ({:?}) as pointer: {}", hex_digits, e))
-        } else {
-            Err(format!("invalid pointer value: {:?}", s))
-        }
-    }
-}
-
-impl std::str::FromStr for DataType {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, String> {
-        match s.trim() {
-            "u8" => Ok(DataType::U8), "i8" => Ok(DataType::I8),
-            "u16" => Ok(DataType::U16), "i16" => Ok(DataType::I16),
-            "u32" => Ok(DataType::U32), "i32" => Ok(DataType::I32),
-            "u64" => Ok(DataType::U64), "i64" => Ok(DataType::I64),
-            "u128" => Ok(DataType::U128), "i128" => Ok(DataType::I128),
-            "char" => Ok(DataType::Char),
-            s => {
-                if let Some(inner) = util::try_consume("null_terminated", s) {
-                    if let Some(inner) = util::try_consume("=", inner) {
-                        DataType::from_str(inner).map(|dt| DataType::NullTerminated(Box::new(dt)))
-                    } else {
-                        Err(format!("null terminated types must have an inner type separated by the equals sign: {:?}", inner))
-                    }
-                } else {
-                    Err(format!("invalid data type: {:#?}", s))
-                }
-            },
-        }
-    }
-}
-
-mod util {
-    /// Consume the desired string and return the remainder.
-    pub fn try_consume<'h>(desired: &str, haystack: &'h str)
-        -> Option<&'h str> {
-        if haystack.starts_with(desired) {
-            let (_, remaining) = haystack.split_at(desired.len());
-            Some(remaining)
-        } else {
-            None
-        }
-    }
-}
-
-impl std::fmt::Display for Pointer {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self.natural_radix {
-            10 => write!(fmt, "{}", self.address),
-            16 => write!(fmt, "0x{:x}", self.address),
-            _ => unimplemented!(),
-        }
-    }
-}
-
-impl MemorySpace {
-    pub fn human_label(&self) -> &'static str {
-        match *self {
-            MemorySpace::Program => "program memory",
-            MemorySpace::Data => "data memory",
-        }
-    }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn can_parse_data_memory_address() {
-        assert_eq!(Ok(vec![Watch::MemoryAddress {
-            space: MemorySpace::Data,
-            address: Pointer { address: 999, natural_radix: 10 },
-            data_type: DataType::U8,
-        }]), parse_watch("datamem=999=u8"));
-
-        assert_eq!(Ok(vec![Watch::MemoryAddress {
-            space: MemorySpace::Data,
-            address: Pointer { address: 999, natural_radix: 10 },
-            data_type: DataType::Char,
-        }]), parse_watch("datamem=999=char"));
-
-        assert_eq!(Ok(vec![Watch::MemoryAddress {
-            space: MemorySpace::Data,
-            address: Pointer { address: 1, natural_radix: 16 },
-            data_type: DataType::NullTerminated(Box::new(DataType::Char)),
-        }]), parse_watch("datamem=0x01=null_terminated=char"));
-
-        assert_eq!(Ok(vec![Watch::MemoryAddress {
-            space: MemorySpace::Data,
-            address: Pointer { address: 0x77, natural_radix: 16 },
-            data_type: DataType::I32,
-        }]), parse_watch("datamem=0x77=i32"));
-    }
-
-    #[test]
-    fn can_parse_watchable_symbol() {
-        assert_eq!(Ok(vec![Watch::Symbol {
-            name: "TEST_BUFFER".to_owned(),
-            data_type: DataType::U8,
-        }]), parse_watch("TEST_BUFFER=u8"));
-    }
-
-    #[test]
-    fn can_parse_watchable_io_port() {
-        assert_eq!(Ok(vec![Watch::IoPort {
-            port_letter: 'A',
-            port_index: None,
-        }]), parse_watch("io-port=a"));
-        assert_eq!(Ok(vec![Watch::IoPort {
-            port_letter: 'D',
-            port_index: Some(3),
-        }]), parse_watch("io-port=D3"));
-        assert_eq!(Ok(vec![Watch::IoPin {
-            port_letter: 'E',
-            port_index: None,
-        }]), parse_watch("io-pin=E"));
-        assert_eq!(Ok(vec![Watch::IoDataDirectionRegister {
-            port_letter: 'A',
-            port_index: Some(0),
-        }]), parse_watch("io-ddr=A0"));
-        assert_eq!(Ok(vec![
-            Watch::IoPort { port_letter: 'D', port_index: Some(7) },
-            Watch::IoPin { port_letter: 'D', port_index: Some(7) },
-            Watch::IoDataDirectionRegister { port_letter: 'D', port_index: Some(7) },
-        ]), parse_watch("io=D7"));
-    }
-}
@@ -0,0 +1,42 @@
+//! The simulator's standard output stream.
+//!
+//! Everything that the simulated program causes to be printed goes through
+//! here so that a transcript of it can be recorded.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static TRANSCRIPT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Prints to the simulator output.
+macro_rules! output {
+    ($($arg:tt)*) => { $crate::output::write(&format!($($arg)*)) };
+}
+
+/// Prints to the simulator output, with a newline.
+macro_rules! outputln {
+    () => { $crate::output::write("\n") };
+    ($($arg:tt)*) => { $crate::output::write(&format!("{}\n", format!($($arg)*))) };
+}
+
+/// Writes text to standard output, appending it to the transcript if one is being recorded.
+pub fn write(text: &str) {
+    print!("{}", text);
+
+    TRANSCRIPT.with(|transcript| {
+        if let Some(transcript) = transcript.borrow_mut().as_mut() {
+            transcript.push_str(text);
+        }
+    });
+}
+
+/// Starts recording everything that is written from now on.
+pub fn start_recording() {
+    TRANSCRIPT.with(|transcript| *transcript.borrow_mut() = Some(String::new()));
+}
+
+/// Gets everything that has been written since recording started.
+pub fn recorded_transcript() -> String {
+    TRANSCRIPT.with(|transcript| transcript.borrow().clone().unwrap_or_default())
+}
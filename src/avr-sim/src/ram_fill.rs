@@ -0,0 +1,98 @@
+//! Initial SRAM contents.
+//!
+//! Real hardware does not zero SRAM on power up, so code that relies on it
+//! only works by accident. Filling SRAM with a pattern makes such bugs show up.
+
+use crate::{Error, Result};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RamFill {
+    /// Sets every byte to the same value.
+    Byte(u8),
+    /// Fills SRAM with pseudorandom bytes, reproducible with the same seed.
+    Random { seed: Option<u64> },
+}
+
+impl RamFill {
+    /// Fills SRAM, leaving the register file and IO space untouched.
+    ///
+    /// Returns the seed that was used if the contents are random.
+    pub fn apply(&self, avr: &mut simavr::Avr) -> Option<u64> {
+        let raw = avr.raw_mut();
+        let sram_start = raw.ioend as usize + 1;
+        let sram = unsafe { std::slice::from_raw_parts_mut(raw.data.add(sram_start), raw.ramend as usize + 1 - sram_start) };
+
+        match *self {
+            RamFill::Byte(value) => {
+                sram.iter_mut().for_each(|b| *b = value);
+                None
+            },
+            RamFill::Random { seed } => {
+                let seed = seed.unwrap_or_else(|| {
+                    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+                });
+
+                let mut generator = SplitMix64(seed);
+                for chunk in sram.chunks_mut(8) {
+                    chunk.copy_from_slice(&generator.next().to_le_bytes()[..chunk.len()]);
+                }
+                Some(seed)
+            },
+        }
+    }
+}
+
+impl FromStr for RamFill {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        match s {
+            "zero" => Ok(RamFill::Byte(0)),
+            "random" => Ok(RamFill::Random { seed: None }),
+            _ => if let Some(seed) = s.strip_prefix("random:") {
+                seed.parse().map(|seed| RamFill::Random { seed: Some(seed) }).map_err(|e| Error::CommandLine(format!("invalid random seed {:?}: {}", seed, e)))
+            } else if let Some(hex_digits) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                u8::from_str_radix(hex_digits, 16).map(RamFill::Byte).map_err(|e| Error::CommandLine(format!("invalid fill byte {:?}: {}", s, e)))
+            } else {
+                Err(Error::CommandLine(format!("expected 'zero', '0xNN', 'random' or 'random:<SEED>' but got {:?}", s)))
+            },
+        }
+    }
+}
+
+/// A tiny PRNG whose output will never change between versions, unlike the `rand` crate.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_ram_fill() {
+        assert_eq!(Ok(RamFill::Byte(0)), "zero".parse());
+        assert_eq!(Ok(RamFill::Byte(0xa5)), "0xA5".parse());
+        assert_eq!(Ok(RamFill::Random { seed: None }), "random".parse());
+        assert_eq!(Ok(RamFill::Random { seed: Some(42) }), "random:42".parse());
+        assert!("0x100".parse::<RamFill>().is_err());
+        assert!("ones".parse::<RamFill>().is_err());
+    }
+
+    #[test]
+    fn random_fill_is_reproducible() {
+        let (mut a, mut b) = (SplitMix64(7), SplitMix64(7));
+        assert_eq!((0..4).map(|_| a.next()).collect::<Vec<_>>(), (0..4).map(|_| b.next()).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,168 @@
+//! Detection of memory accesses outside of any known object.
+//!
+//! The sanitizer checks every load and store before it executes, flagging
+//!
+//!   * writes into program memory or read-only sections mapped into the data space
+//!   * loads and stores past the end of RAM
+//!   * writes into IO space that do not correspond to an IO register on the MCU
+//!   * multi-byte stores through a pointer that straddle two data symbols
+
+use crate::debug_info::DebugInfo;
+use crate::diagnostic::Diagnostic;
+use crate::instruction::{self, Effects, PointerRegister};
+use crate::io_registers;
+use std::collections::BTreeSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Violation {
+    WriteToProgramMemory,
+    WriteToReadOnlyData,
+    LoadPastRamEnd,
+    StorePastRamEnd,
+    WriteToUndefinedIoRegister,
+    StoreStraddlesSymbols,
+}
+
+pub struct Sanitizer {
+    ioend: u16,
+    ramend: u16,
+    /// The IO registers that exist on the MCU, if known.
+    io_registers: Option<&'static [io_registers::IoRegister]>,
+    /// The last indirect store, used to find multi-byte stores.
+    last_store: Option<LastStore>,
+    /// The violations that have already been reported, so that loops do not flood the output.
+    reported: BTreeSet<(u32, Violation)>,
+    /// Reports that have not yet been taken.
+    diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct LastStore {
+    pc: u32,
+    instruction_size: u32,
+    pointer_register: PointerRegister,
+    address: u16,
+}
+
+impl Sanitizer {
+    pub fn new(avr: &simavr::Avr) -> Self {
+        let io_registers = io_registers::for_mcu(avr.name());
+        let mut diagnostics = Vec::new();
+
+        if io_registers.is_none() {
+            diagnostics.push(Diagnostic::warning(format!("the IO registers of '{}' are not known, so the check for writes to IO space is off", avr.name())));
+        }
+
+        Sanitizer {
+            ioend: avr.raw().ioend,
+            ramend: avr.raw().ramend,
+            io_registers,
+            last_store: None,
+            reported: BTreeSet::new(),
+            diagnostics,
+        }
+    }
+
+    /// Checks the instruction that is about to be executed.
+    pub fn before_instruction(&mut self, avr: &simavr::Avr, debug_info: &DebugInfo) {
+        if avr.raw().state != simavr::sys::cpu_Running as _ {
+            return;
+        }
+
+        let opcode = instruction::opcode_at_pc(avr);
+
+        if instruction::is_store_program_memory(opcode) {
+            let z = instruction::z_register(avr) as u32;
+
+            if let Some(section) = debug_info.code_section_at(z) {
+                self.report(avr, debug_info, Violation::WriteToProgramMemory,
+                            format!("write to program memory at 0x{:04x} in section '{}'", z, section.name));
+            }
+        }
+
+        let effects = instruction::decode_next(avr);
+
+        for address in effects.loads() {
+            if address > self.ramend {
+                self.report(avr, debug_info, Violation::LoadPastRamEnd,
+                            format!("load from {}, past the end of RAM at 0x{:04x}", debug_info.describe_data_address(address as u32), self.ramend));
+            }
+        }
+
+        for address in effects.stores() {
+            self.check_store(avr, debug_info, address);
+        }
+
+        self.check_straddling_store(avr, debug_info, &effects);
+    }
+
+    /// Takes all reports made since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Checks whether any violations have been reported.
+    pub fn found_errors(&self) -> bool {
+        !self.reported.is_empty()
+    }
+
+    fn check_store(&mut self, avr: &simavr::Avr, debug_info: &DebugInfo, address: u16) {
+        if address > self.ramend {
+            if let Some(section) = debug_info.read_only_data_section_at(address as u32) {
+                self.report(avr, debug_info, Violation::WriteToReadOnlyData,
+                            format!("write to 0x{:04x} in read-only section '{}'", address, section.name));
+            } else {
+                self.report(avr, debug_info, Violation::StorePastRamEnd,
+                            format!("store to {}, past the end of RAM at 0x{:04x}", debug_info.describe_data_address(address as u32), self.ramend));
+            }
+        } else if address >= 0x20 && address <= self.ioend {
+            if let Some(io_registers) = self.io_registers {
+                if !io_registers.iter().any(|register| register.0 == address) {
+                    self.report(avr, debug_info, Violation::WriteToUndefinedIoRegister,
+                                format!("write to 0x{:04x} in IO space, which is not an IO register on '{}'", address, avr.name()));
+                }
+            }
+        }
+    }
+
+    /// Multi-byte values are stored one byte at a time by consecutive instructions
+    /// that use the same pointer register.
+    fn check_straddling_store(&mut self, avr: &simavr::Avr, debug_info: &DebugInfo, effects: &Effects) {
+        let pc = avr.raw().pc;
+        let store = match (effects.pointer_register, effects.stores().next()) {
+            (Some(pointer_register), Some(address)) => LastStore { pc, instruction_size: effects.size, pointer_register, address },
+            _ => {
+                self.last_store = None;
+                return;
+            },
+        };
+
+        if let Some(last_store) = self.last_store.replace(store) {
+            let is_same_value = last_store.pc + last_store.instruction_size == pc &&
+                last_store.pointer_register == store.pointer_register &&
+                (last_store.address as i32 - store.address as i32).abs() == 1;
+
+            if !is_same_value {
+                return;
+            }
+
+            let (last_object, object) = (debug_info.object_at(last_store.address as u32), debug_info.object_at(store.address as u32));
+
+            if last_object != object {
+                self.report(avr, debug_info, Violation::StoreStraddlesSymbols,
+                            format!("multi-byte store to {} and {} straddles more than one symbol",
+                                    debug_info.describe_data_address(last_store.address as u32),
+                                    debug_info.describe_data_address(store.address as u32)));
+            }
+        }
+    }
+
+    fn report(&mut self, avr: &simavr::Avr, debug_info: &DebugInfo, violation: Violation, description: String) {
+        let pc = avr.raw().pc;
+
+        if self.reported.insert((pc, violation)) {
+            self.diagnostics.push(Diagnostic::error(format!("sanitizer: {} in CPU cycle #{} ({})",
+                                                           description, avr.raw().cycle, debug_info.describe_pc(pc))));
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! A simulated AVR with a test program flashed onto it.
+
+use crate::avr_print;
+use crate::debug_info::DebugInfo;
+use crate::diagnostic::Diagnostic;
+use crate::error::{Error, Result};
+use crate::ram_fill::RamFill;
+use crate::sanitizer::Sanitizer;
+use crate::snapshot::{self, Snapshot};
+use crate::stimulus::{ActiveStimulus, Stimulus};
+use crate::uart::{Transfer, Uart};
+use crate::uninit::UninitChecker;
+use crate::watch::{Watch, WatchState, WatchableSymbol};
+use std::{fmt, path::Path, time::Duration};
+
+pub struct Simulator {
+    /// Declared before `avr` so that its IRQ hooks are unregistered while the AVR still exists.
+    uart: Option<Uart>,
+    avr: simavr::Avr,
+    firmware_hash: u64,
+    /// The contents of IO space at reset, to check snapshots against.
+    reset_io: Vec<u8>,
+    watchable_symbols: Vec<WatchableSymbol>,
+    debug_info: DebugInfo,
+    /// How to read the libavrlit debug stream, if the program links libavrlit.
+    print_config: Option<avr_print::Config>,
+    /// Everything written to the debug stream that has not yet been taken.
+    debug_stream: String,
+    uninit_checker: Option<UninitChecker>,
+    sanitizer: Option<Sanitizer>,
+    stimulus: Option<ActiveStimulus>,
+    /// Everything transmitted over the UART that has not yet been taken.
+    uart_output: Vec<u8>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The result of executing a single CPU cycle.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The CPU cycle that the instruction started in.
+    pub cycle: u64,
+    /// The address of the instruction that was executed.
+    pub pc: u32,
+    pub state: simavr::State,
+}
+
+/// An amount of time on the simulated CPU's clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SimulatedTime(pub Duration);
+
+/// Why a simulation stopped running.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The program has finished executing.
+    Finished,
+    /// The stop condition was satisfied.
+    Stopped,
+    /// The simulated CPU crashed.
+    Crashed,
+}
+
+impl Simulator {
+    /// Creates a simulator for the given MCU and flashes an ELF executable onto it.
+    pub fn new(mcu_name: &str, elf_data: &[u8]) -> Result<Self> {
+        // simavr aborts on invalid ELF files, so check it is valid first.
+        object::read::File::parse(elf_data).map_err(|e| Error::Elf(format!("invalid ELF file: {}", e)))?;
+
+        let firmware = simavr::Firmware::read_elf(elf_data).map_err(|e| Error::Elf(format!("could not read ELF file: {}", e)))?;
+        let mut avr = simavr::Avr::new(mcu_name).map_err(|e| Error::CommandLine(format!("could not create a simulated '{}': {}", mcu_name, e)))?;
+
+        // Flashing also sets the clock frequency if the executable declares one in its '.mmcu' section.
+        avr.flash(&firmware);
+
+        let reset_io = snapshot::io_space(&avr).to_vec();
+        let watchable_symbols = crate::parse_watchable_symbols_from_elf(elf_data)?;
+
+        let mut diagnostics = Vec::new();
+
+        let debug_info = DebugInfo::parse(elf_data).unwrap_or_else(|e| {
+            diagnostics.push(Diagnostic::warning(format!("could not read debug information, diagnostics will not include source locations: {}", e)));
+            DebugInfo::default()
+        });
+
+        let print_config = match avr_print::Config::new(&watchable_symbols) {
+            Ok(config) => Some(config),
+            Err(message) => {
+                diagnostics.push(Diagnostic::warning(format!("cannot intercept and print the libavrlit debug stream: {}.", message)));
+                None
+            },
+        };
+
+        Ok(Simulator {
+            avr,
+            firmware_hash: snapshot::hash(elf_data),
+            reset_io,
+            watchable_symbols,
+            debug_info,
+            print_config,
+            debug_stream: String::new(),
+            uninit_checker: None,
+            sanitizer: None,
+            stimulus: None,
+            uart: None,
+            uart_output: Vec::new(),
+            diagnostics,
+        })
+    }
+
+    /// Creates a simulator for the given MCU and flashes an ELF file from disk onto it.
+    pub fn from_elf_file(mcu_name: &str, path: &Path) -> Result<Self> {
+        let elf_data = std::fs::read(path).map_err(|e| Error::Elf(format!("could not read '{}': {}", path.display(), e)))?;
+        Simulator::new(mcu_name, &elf_data)
+    }
+
+    /// Sets the initial contents of SRAM. Must be called before the simulation starts.
+    ///
+    /// Returns the seed that was used if the contents are random.
+    pub fn fill_ram(&mut self, ram_fill: RamFill) -> Option<u64> {
+        ram_fill.apply(&mut self.avr)
+    }
+
+    /// Reports every load from uninitialized SRAM as a diagnostic.
+    pub fn enable_uninit_checks(&mut self) {
+        self.uninit_checker = Some(UninitChecker::new(&self.avr));
+    }
+
+    /// Reports every memory access outside of any known object as a diagnostic.
+    pub fn enable_sanitizer(&mut self) {
+        let mut sanitizer = Sanitizer::new(&self.avr);
+        self.diagnostics.extend(sanitizer.take_diagnostics());
+        self.sanitizer = Some(sanitizer);
+    }
+
+    /// Drives input pins as described by a stimulus script while the simulation runs.
+    pub fn set_stimulus(&mut self, stimulus: &Stimulus) -> Result<()> {
+        self.stimulus = Some(stimulus.resolve(&self.watchable_symbols)?);
+        Ok(())
+    }
+
+    /// Connects to the USART with the given index so that its output can be
+    /// taken and input can be sent to it.
+    ///
+    /// Any USART that was attached before is detached first.
+    pub fn attach_uart(&mut self, index: u8) -> Result<()> {
+        self.uart = None;
+        self.uart = Some(Uart::attach(&mut self.avr, index)?);
+        Ok(())
+    }
+
+    /// Queues bytes for the program to receive over the attached UART.
+    pub fn send_uart_input(&mut self, bytes: &[u8]) -> Result<()> {
+        match self.uart.as_mut() {
+            Some(uart) => uart.send(bytes),
+            None => Err(Error::Ioctl("cannot send input, no USART is attached".to_owned())),
+        }
+    }
+
+    /// Gets the clock frequency of the simulated CPU in Hz.
+    pub fn frequency(&self) -> u32 {
+        self.avr.frequency()
+    }
+
+    /// Sets the clock frequency of the simulated CPU in Hz, overriding the one from the executable.
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.avr.set_frequency(frequency);
+    }
+
+    /// Gets how much simulated time has passed by the start of the given CPU cycle.
+    pub fn time_of_cycle(&self, cycle: u64) -> SimulatedTime {
+        let nanoseconds = cycle as u128 * 1_000_000_000 / self.frequency().max(1) as u128;
+        SimulatedTime(Duration::from_nanos(nanoseconds as u64))
+    }
+
+    /// Executes a single CPU cycle.
+    pub fn step(&mut self) -> Result<Step> {
+        let (cycle, pc) = (self.avr.raw().cycle, self.avr.raw().pc);
+
+        if let Some(stimulus) = self.stimulus.as_mut() {
+            stimulus.before_instruction(&self.avr)?;
+        }
+        if let Some(uart) = self.uart.as_mut() {
+            uart.before_instruction(&self.avr);
+        }
+
+        if let Some(uninit_checker) = self.uninit_checker.as_mut() {
+            uninit_checker.before_instruction(&self.avr, &self.debug_info);
+        }
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            sanitizer.before_instruction(&self.avr, &self.debug_info);
+        }
+
+        let state = self.avr.run_cycle();
+
+        if let Some(uninit_checker) = self.uninit_checker.as_mut() {
+            uninit_checker.after_instruction(&self.avr);
+            self.diagnostics.extend(uninit_checker.take_diagnostics());
+        }
+        if let Some(sanitizer) = self.sanitizer.as_mut() {
+            self.diagnostics.extend(sanitizer.take_diagnostics());
+        }
+
+        if let Some(uart) = self.uart.as_mut() {
+            self.uart_output.extend(uart.after_instruction(&self.avr));
+        }
+
+        if let Some(print_config) = self.print_config.as_ref() {
+            if let Some(c) = print_config.consume_character(&self.avr).map_err(|e| e.context("could not read from libavrlit debug stream"))? {
+                self.debug_stream.push(c);
+            }
+        }
+
+        Ok(Step { cycle, pc, state })
+    }
+
+    /// Runs the program until `stop` returns true or the program finishes.
+    ///
+    /// The stop condition is checked after every CPU cycle.
+    pub fn run_until(
+        &mut self,
+        mut stop: impl FnMut(&Simulator, &Step) -> bool,
+    ) -> Result<RunOutcome> {
+        loop {
+            let step = self.step()?;
+
+            if stop(self, &step) {
+                return Ok(RunOutcome::Stopped);
+            }
+
+            match step.state {
+                simavr::State::Running | simavr::State::Stopped => (),
+                simavr::State::Crashed => return Ok(RunOutcome::Crashed),
+                // Keep running when in setup,limbo,etc.
+                state if !state.is_running() => return Ok(RunOutcome::Finished),
+                // We don't care about other states.
+                _ => (),
+            }
+        }
+    }
+
+    /// Runs the program until it finishes.
+    pub fn run_to_completion(&mut self) -> Result<RunOutcome> {
+        self.run_until(|_, _| false)
+    }
+
+    /// Reads the current value of a watch.
+    pub fn read_watch(&self, watch: &Watch) -> Result<WatchState> {
+        watch.current_value(&self.avr, &self.watchable_symbols, &self.debug_info)
+    }
+
+    /// Checks that a watch refers to a symbol in the executable and memory that exists on the MCU.
+    pub fn check_watch(&self, watch: &Watch) -> Result<()> {
+        watch.check_reachable(&self.avr, &self.watchable_symbols, &self.debug_info)
+    }
+
+    /// Overwrites the value of a watch.
+    pub fn write_watch(&mut self, watch: &Watch, value: &WatchState) -> Result<()> {
+        watch.write_value(value, &self.avr, &self.watchable_symbols)
+    }
+
+    /// Takes everything written to the libavrlit debug stream since the last call.
+    pub fn take_debug_stream(&mut self) -> String {
+        std::mem::take(&mut self.debug_stream)
+    }
+
+    /// Takes everything transmitted over the attached UART since the last call.
+    pub fn take_uart_output(&mut self) -> String {
+        std::mem::take(&mut self.uart_output).into_iter().map(|b| b as char).collect()
+    }
+
+    /// Takes every byte sent or received over the attached UART since the last call.
+    pub fn take_uart_transfers(&mut self) -> Vec<Transfer> {
+        self.uart.as_mut().map(|uart| uart.take_transfers()).unwrap_or_default()
+    }
+
+    /// Takes all warnings and errors reported since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Checks whether any of the enabled checkers have found an error.
+    pub fn found_errors(&self) -> bool {
+        self.uninit_checker.as_ref().map(|c| c.found_errors()).unwrap_or(false) ||
+            self.sanitizer.as_ref().map(|s| s.found_errors()).unwrap_or(false)
+    }
+
+    /// Captures the current state of the simulation.
+    ///
+    /// Fails if a peripheral such as a timer or the USART has been used, because
+    /// its state cannot be saved.
+    pub fn snapshot(&self, output_fingerprint: u64, transcript: String) -> Result<Snapshot> {
+        Snapshot::capture(&self.avr, &self.reset_io, self.firmware_hash, output_fingerprint, transcript)
+    }
+
+    /// Restores a snapshot taken from the same executable.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.firmware_hash != self.firmware_hash {
+            return Err(Error::SimulationFault("the snapshot was taken from a different executable".to_owned()));
+        }
+
+        snapshot.restore(&mut self.avr)?;
+
+        // The snapshot does not record which bytes were initialized.
+        if let Some(uninit_checker) = self.uninit_checker.as_mut() {
+            uninit_checker.define_all();
+        }
+
+        Ok(())
+    }
+
+    /// Gets the symbols that can be watched by name.
+    pub fn watchable_symbols(&self) -> &[WatchableSymbol] {
+        &self.watchable_symbols
+    }
+
+    pub fn debug_info(&self) -> &DebugInfo {
+        &self.debug_info
+    }
+
+    pub fn avr(&self) -> &simavr::Avr {
+        &self.avr
+    }
+
+    pub fn avr_mut(&mut self) -> &mut simavr::Avr {
+        &mut self.avr
+    }
+}
+
+impl fmt::Display for SimulatedTime {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let nanoseconds = self.0.as_nanos();
+
+        if nanoseconds < 1_000 {
+            write!(fmt, "{} ns", nanoseconds)
+        } else if nanoseconds < 1_000_000 {
+            write!(fmt, "{:.3} µs", nanoseconds as f64 / 1e3)
+        } else if nanoseconds < 1_000_000_000 {
+            write!(fmt, "{:.3} ms", nanoseconds as f64 / 1e6)
+        } else {
+            write!(fmt, "{:.3} s", nanoseconds as f64 / 1e9)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn simulated_time_uses_the_largest_sensible_unit() {
+        assert_eq!("62 ns", SimulatedTime(Duration::from_nanos(62)).to_string());
+        assert_eq!("62.500 µs", SimulatedTime(Duration::from_nanos(62_500)).to_string());
+        assert_eq!("1.000 ms", SimulatedTime(Duration::from_millis(1)).to_string());
+        assert_eq!("2.500 s", SimulatedTime(Duration::from_millis(2_500)).to_string());
+    }
+}
@@ -0,0 +1,290 @@
+//! Saving and restoring the state of a simulated AVR.
+//!
+//! A snapshot contains the register file, IO registers, SRAM, EEPROM, flash,
+//! the program counter and the cycle count. Registers are restored by writing
+//! them straight into data memory, which simavr's peripherals never see, and
+//! internal peripheral state such as pending cycle timers or UART buffers is
+//! not captured. Snapshots are therefore refused unless every peripheral
+//! register still holds its reset value, so that a resumed run behaves
+//! exactly like an uninterrupted one.
+
+use crate::io_registers;
+use crate::{Error, Result, avr_ioctl_def};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"AVRSNAP\0";
+const FORMAT_VERSION: u32 = 1;
+
+const AVR_IOCTL_EEPROM_GET: u32 = avr_ioctl_def('e', 'e', 'g', 'p');
+
+type ByteOrder = byteorder::LittleEndian;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The name of the MCU the snapshot was taken on.
+    pub mcu_name: String,
+    /// A hash of the firmware the snapshot was taken from.
+    pub firmware_hash: u64,
+    /// A hash of the command line options that affect output up until the snapshot point.
+    pub output_fingerprint: u64,
+    /// Everything printed to standard output before the snapshot was taken.
+    pub transcript: String,
+    pub pc: u32,
+    pub cycle: u64,
+    pub run_cycle_count: u64,
+    pub cpu_state: i32,
+    pub interrupt_state: i8,
+    pub sreg: [u8; 8],
+    /// The entire data space, including general purpose and IO registers.
+    pub data: Vec<u8>,
+    pub flash: Vec<u8>,
+    pub eeprom: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Captures the current state of the AVR.
+    ///
+    /// `reset_io` is the contents of IO space at reset, which is used to check
+    /// that no peripheral has been used.
+    pub fn capture(
+        avr: &simavr::Avr,
+        reset_io: &[u8],
+        firmware_hash: u64,
+        output_fingerprint: u64,
+        transcript: String,
+    ) -> Result<Self> {
+        check_peripherals_are_idle(avr, reset_io)?;
+        let raw = avr.raw();
+
+        Ok(Snapshot {
+            mcu_name: avr.name().to_owned(),
+            firmware_hash, output_fingerprint, transcript,
+            pc: raw.pc,
+            cycle: raw.cycle,
+            run_cycle_count: raw.run_cycle_count,
+            cpu_state: raw.state,
+            interrupt_state: raw.interrupt_state,
+            sreg: raw.sreg,
+            data: data_space(avr).to_vec(),
+            flash: flash(avr).to_vec(),
+            eeprom: eeprom(avr).map(|e| e.to_vec()).unwrap_or_default(),
+        })
+    }
+
+    /// Overwrites the state of the AVR with the snapshot.
+    pub fn restore(&self, avr: &mut simavr::Avr) -> Result<()> {
+        if self.mcu_name != avr.name() {
+            return Err(Error::SimulationFault(format!("the snapshot was taken on a '{}' but the simulated MCU is a '{}'", self.mcu_name, avr.name())));
+        }
+
+        let memories: [(&str, &mut [u8], &[u8]); 2] = [
+            ("data", data_space(avr), &self.data),
+            ("flash", flash(avr), &self.flash),
+        ];
+
+        for (memory_name, current, saved) in memories.iter() {
+            if current.len() != saved.len() {
+                return Err(Error::SimulationFault(format!("the snapshot {} memory is {} bytes but the MCU has {} bytes", memory_name, saved.len(), current.len())));
+            }
+        }
+
+        for (_, current, saved) in memories {
+            current.copy_from_slice(saved);
+        }
+
+        match eeprom(avr) {
+            Some(eeprom) if eeprom.len() == self.eeprom.len() => eeprom.copy_from_slice(&self.eeprom),
+            None if self.eeprom.is_empty() => (),
+            _ => return Err(Error::SimulationFault("the snapshot EEPROM size does not match the MCU".to_owned())),
+        }
+
+        let raw = avr.raw_mut();
+        raw.pc = self.pc;
+        raw.cycle = self.cycle;
+        raw.run_cycle_count = self.run_cycle_count;
+        raw.state = self.cpu_state as _;
+        raw.interrupt_state = self.interrupt_state;
+        raw.sreg = self.sreg;
+
+        Ok(())
+    }
+
+    /// Reads a snapshot from disk.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).map_err(|e| Error::CommandLine(format!("could not open '{}': {}", path.display(), e)))?;
+        let mut file = io::BufReader::new(file);
+        Snapshot::read(&mut file).map_err(|e| Error::SimulationFault(format!("could not read snapshot: {}", e)))
+    }
+
+    /// Writes the snapshot to disk.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path).map_err(|e| Error::CommandLine(format!("could not create '{}': {}", path.display(), e)))?;
+        let mut file = io::BufWriter::new(file);
+        self.write(&mut file).and_then(|_| file.flush()).map_err(|e| Error::CommandLine(format!("could not write '{}': {}", path.display(), e)))
+    }
+
+    fn read(read: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        read.read_exact(&mut magic)?;
+
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the file is not an avr-sim snapshot"));
+        }
+
+        let version = read.read_u32::<ByteOrder>()?;
+        if version != FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported snapshot format version {}", version)));
+        }
+
+        let mcu_name = read_string(read)?;
+        let firmware_hash = read.read_u64::<ByteOrder>()?;
+        let output_fingerprint = read.read_u64::<ByteOrder>()?;
+        let transcript = read_string(read)?;
+        let pc = read.read_u32::<ByteOrder>()?;
+        let cycle = read.read_u64::<ByteOrder>()?;
+        let run_cycle_count = read.read_u64::<ByteOrder>()?;
+        let cpu_state = read.read_i32::<ByteOrder>()?;
+        let interrupt_state = read.read_i8()?;
+        let mut sreg = [0u8; 8];
+        read.read_exact(&mut sreg)?;
+        let data = read_bytes(read)?;
+        let flash = read_bytes(read)?;
+        let eeprom = read_bytes(read)?;
+
+        Ok(Snapshot {
+            mcu_name, firmware_hash, output_fingerprint, transcript,
+            pc, cycle, run_cycle_count, cpu_state, interrupt_state, sreg,
+            data, flash, eeprom,
+        })
+    }
+
+    fn write(&self, write: &mut impl Write) -> io::Result<()> {
+        write.write_all(MAGIC)?;
+        write.write_u32::<ByteOrder>(FORMAT_VERSION)?;
+        write_bytes(write, self.mcu_name.as_bytes())?;
+        write.write_u64::<ByteOrder>(self.firmware_hash)?;
+        write.write_u64::<ByteOrder>(self.output_fingerprint)?;
+        write_bytes(write, self.transcript.as_bytes())?;
+        write.write_u32::<ByteOrder>(self.pc)?;
+        write.write_u64::<ByteOrder>(self.cycle)?;
+        write.write_u64::<ByteOrder>(self.run_cycle_count)?;
+        write.write_i32::<ByteOrder>(self.cpu_state)?;
+        write.write_i8(self.interrupt_state)?;
+        write.write_all(&self.sreg)?;
+        write_bytes(write, &self.data)?;
+        write_bytes(write, &self.flash)?;
+        write_bytes(write, &self.eeprom)?;
+        Ok(())
+    }
+}
+
+/// Gets the contents of IO space, from the first IO register to the last.
+pub(crate) fn io_space(avr: &simavr::Avr) -> &[u8] {
+    &data_space(avr)[0x20..=avr.raw().ioend as usize]
+}
+
+/// Checks that every peripheral register holds the value it had at reset.
+fn check_peripherals_are_idle(avr: &simavr::Avr, reset_io: &[u8]) -> Result<()> {
+    let registers = io_registers::for_mcu(avr.name()).unwrap_or(io_registers::CORE_REGISTERS);
+
+    for (offset, (&current, &at_reset)) in io_space(avr).iter().zip(reset_io).enumerate() {
+        let address = 0x20 + offset as u16;
+        let name = registers.iter().find(|register| register.0 == address).map(|register| register.1);
+
+        if current == at_reset || name.map(io_registers::is_plain_storage).unwrap_or(false) {
+            continue;
+        }
+
+        let register = match name {
+            Some(name) => format!("{} (0x{:02x})", name, address),
+            None => format!("the IO register at 0x{:02x}", address),
+        };
+        return Err(Error::SimulationFault(format!(
+            "cannot save a snapshot while a peripheral is in use, {} holds 0x{:02x} instead of its reset value 0x{:02x}, and peripheral state cannot be saved",
+            register, current, at_reset)));
+    }
+
+    Ok(())
+}
+
+/// Hashes bytes with 64-bit FNV-1a.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn read_bytes(read: &mut impl Read) -> io::Result<Vec<u8>> {
+    let length = read.read_u32::<ByteOrder>()?;
+    let mut bytes = vec![0; length as usize];
+    read.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_string(read: &mut impl Read) -> io::Result<String> {
+    String::from_utf8(read_bytes(read)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_bytes(write: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    write.write_u32::<ByteOrder>(bytes.len() as u32)?;
+    write.write_all(bytes)
+}
+
+#[allow(clippy::mut_from_ref)]
+fn data_space(avr: &simavr::Avr) -> &mut [u8] {
+    // N.B. unlike the name suggests, 'ramend' is the last valid address.
+    unsafe { std::slice::from_raw_parts_mut(avr.raw().data, avr.raw().ramend as usize + 1) }
+}
+
+#[allow(clippy::mut_from_ref)]
+fn flash(avr: &simavr::Avr) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(avr.raw().flash, avr.raw().flashend as usize + 1) }
+}
+
+/// Gets the EEPROM contents, if the MCU has any.
+#[allow(clippy::mut_from_ref)]
+fn eeprom(avr: &simavr::Avr) -> Option<&mut [u8]> {
+    let size = avr.raw().e2end as usize + 1;
+    let mut descriptor = simavr::sys::avr_eeprom_desc_t { ee: std::ptr::null_mut(), offset: 0, size: size as u32 };
+
+    // N.B. the EEPROM ioctl does not return a meaningful status code, a null pointer means
+    // that there is no EEPROM.
+    unsafe {
+        simavr::sys::avr_ioctl(avr.underlying(), AVR_IOCTL_EEPROM_GET, &mut descriptor as *mut _ as *mut libc::c_void);
+    }
+
+    if descriptor.ee.is_null() || avr.raw().e2end == 0 {
+        None
+    } else {
+        Some(unsafe { std::slice::from_raw_parts_mut(descriptor.ee, size) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_round_trip_snapshot() {
+        let snapshot = Snapshot {
+            mcu_name: "atmega328".to_owned(),
+            firmware_hash: hash(b"firmware"),
+            output_fingerprint: hash(b"options"),
+            transcript: "before_execution(OUTPUT_VALUE) = 0\n".to_owned(),
+            pc: 0x1234, cycle: 99_999, run_cycle_count: 12,
+            cpu_state: 2, interrupt_state: -1,
+            sreg: [0, 1, 0, 1, 0, 0, 0, 1],
+            data: vec![0xaa; 0x900], flash: vec![0xff; 0x8000], eeprom: vec![0x11; 0x400],
+        };
+
+        let mut buffer = Vec::new();
+        snapshot.write(&mut buffer).unwrap();
+
+        assert_eq!(snapshot, Snapshot::read(&mut &buffer[..]).unwrap());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_snapshots() {
+        assert!(Snapshot::read(&mut &b"ELF\x7fnot a snapshot"[..]).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! Scripted input on GPIO pins.
+//!
+//! A stimulus script drives input pins at given points in the simulation so
+//! that programs which poll pins or handle pin change interrupts can be tested.
+//!
+//! ```text
+//! # Press the button after a while.
+//! at cycle 1000: PINB3 = HIGH
+//! at symbol wait_for_button: PIND = 0x04
+//! ```
+
+use crate::{Error, MemorySpace, Result, WatchableSymbol, avr_ioctl_def};
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stimulus {
+    pub events: Vec<Event>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub trigger: Trigger,
+    pub action: Action,
+    /// The line of the script that the event was declared on.
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// At the start of the given CPU cycle.
+    Cycle(u64),
+    /// The first time the program counter reaches a function or label.
+    Symbol(String),
+}
+
+/// Drives an input pin, or every pin of a port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Action {
+    pub port_letter: char,
+    pub pin_index: Option<u8>,
+    pub value: u8,
+}
+
+/// A stimulus whose symbols have been resolved to addresses.
+pub(crate) struct ActiveStimulus {
+    pending: Vec<(ResolvedTrigger, Action)>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ResolvedTrigger {
+    Cycle(u64),
+    Pc(u32),
+}
+
+impl Stimulus {
+    /// Reads a stimulus script from a file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| Error::CommandLine(format!("could not read '{}': {}", path.display(), e)))?;
+        source.parse().map_err(|e: Error| e.context(format!("invalid stimulus script '{}'", path.display())))
+    }
+
+    /// Finds the addresses of the symbols that events are triggered by.
+    pub(crate) fn resolve(&self, watchable_symbols: &[WatchableSymbol]) -> Result<ActiveStimulus> {
+        let pending = self.events.iter().map(|event| {
+            let trigger = match event.trigger {
+                Trigger::Cycle(cycle) => ResolvedTrigger::Cycle(cycle),
+                Trigger::Symbol(ref name) => match watchable_symbols.iter().find(|s| s.name == *name && s.memory_space == MemorySpace::Program) {
+                    Some(symbol) => ResolvedTrigger::Pc(symbol.address.address),
+                    None => return Err(Error::MissingSymbol(format!("line {}: '{}' is not a program symbol in the ELF file", event.line, name))),
+                },
+            };
+
+            Ok((trigger, event.action))
+        }).collect::<Result<_>>()?;
+
+        Ok(ActiveStimulus { pending })
+    }
+}
+
+impl ActiveStimulus {
+    /// Applies every event that is due before the instruction at the current PC executes.
+    pub fn before_instruction(&mut self, avr: &simavr::Avr) -> Result<()> {
+        let (cycle, pc) = (avr.raw().cycle, avr.raw().pc);
+        let is_due = |trigger: &ResolvedTrigger| match *trigger {
+            ResolvedTrigger::Cycle(at) => cycle >= at,
+            ResolvedTrigger::Pc(address) => pc == address,
+        };
+
+        let (due, pending) = std::mem::take(&mut self.pending).into_iter().partition(|(trigger, _)| is_due(trigger));
+        self.pending = pending;
+
+        for (_, action) in due {
+            action.apply(avr)?;
+        }
+        Ok(())
+    }
+}
+
+impl Action {
+    fn apply(&self, avr: &simavr::Avr) -> Result<()> {
+        let irq_index = match self.pin_index {
+            Some(index) => simavr::sys::IOPORT_IRQ_PIN0 + index as u32,
+            None => simavr::sys::IOPORT_IRQ_PIN_ALL,
+        };
+
+        unsafe {
+            let irq = simavr::sys::avr_io_getirq(avr.underlying(), avr_ioctl_ioport_getirq(self.port_letter), irq_index as _);
+            if irq.is_null() {
+                return Err(Error::Ioctl(format!("cannot drive PIN{}, the MCU may not have this port", self.port_letter)));
+            }
+
+            simavr::sys::avr_raise_irq(irq, self.value as u32);
+        }
+
+        Ok(())
+    }
+}
+
+const fn avr_ioctl_ioport_getirq(port_name: char) -> u32 {
+    avr_ioctl_def('i', 'o', 'g', port_name)
+}
+
+impl std::str::FromStr for Stimulus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut events = Vec::new();
+
+        for (index, line) in s.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let event = parse_event(line, line_number).map_err(|e| e.context(format!("line {}", line_number)))?;
+            events.push(event);
+        }
+
+        Ok(Stimulus { events })
+    }
+}
+
+/// Parses `at <cycle N|symbol NAME>: PIN<LETTER>[INDEX] = <VALUE>`.
+fn parse_event(line: &str, line_number: usize) -> Result<Event> {
+    let (trigger, action) = match (line.strip_prefix("at "), line.find(':')) {
+        (Some(_), Some(colon_index)) => (line[3..colon_index].trim(), line[colon_index + 1..].trim()),
+        _ => return Err(Error::CommandLine(format!("expected 'at cycle <N>: ...' or 'at symbol <NAME>: ...' but got {:?}", line))),
+    };
+
+    let trigger = if let Some(cycle) = trigger.strip_prefix("cycle ") {
+        cycle.trim().parse().map(Trigger::Cycle).map_err(|e| Error::CommandLine(format!("invalid cycle number {:?}: {}", cycle.trim(), e)))?
+    } else if let Some(symbol) = trigger.strip_prefix("symbol ") {
+        Trigger::Symbol(symbol.trim().to_owned())
+    } else {
+        return Err(Error::CommandLine(format!("expected 'cycle <N>' or 'symbol <NAME>' after 'at' but got {:?}", trigger)));
+    };
+
+    let (target, value) = match action.find('=') {
+        Some(index) => (action[..index].trim(), action[index + 1..].trim()),
+        None => return Err(Error::CommandLine(format!("expected 'PIN<LETTER>[INDEX] = <VALUE>' but got {:?}", action))),
+    };
+
+    let mut target_chars = match target.strip_prefix("PIN") {
+        Some(port) => port.chars(),
+        None => return Err(Error::CommandLine(format!("only input pins can be driven, expected 'PIN<LETTER>[INDEX]' but got {:?}", target))),
+    };
+    let port_letter = match target_chars.next() {
+        Some(letter) if letter.is_ascii_alphabetic() => letter.to_ascii_uppercase(),
+        _ => return Err(Error::CommandLine(format!("expected a port letter after 'PIN' in {:?}", target))),
+    };
+    let pin_index = match target_chars.as_str() {
+        "" => None,
+        index => match index.parse::<u8>() {
+            Ok(index) if index < 8 => Some(index),
+            _ => return Err(Error::CommandLine(format!("invalid pin index {:?}, expected 0 to 7", index))),
+        },
+    };
+
+    let value = parse_value(value).ok_or_else(|| Error::CommandLine(format!("invalid value {:?}, expected HIGH, LOW or a number", value)))?;
+    if pin_index.is_some() && value > 1 {
+        return Err(Error::CommandLine(format!("a single pin can only be set to HIGH or LOW, not {}", value)));
+    }
+
+    Ok(Event { trigger, action: Action { port_letter, pin_index, value }, line: line_number })
+}
+
+fn parse_value(s: &str) -> Option<u8> {
+    match s {
+        "HIGH" | "high" => Some(1),
+        "LOW" | "low" => Some(0),
+        _ if s.starts_with("0x") || s.starts_with("0X") => u8::from_str_radix(&s[2..], 16).ok(),
+        _ if s.starts_with("0b") || s.starts_with("0B") => u8::from_str_radix(&s[2..], 2).ok(),
+        _ => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_script() {
+        let script = "# comment\n\nat cycle 1000: PINB3 = HIGH\nat symbol wait_for_button: PIND = 0x04 # press\n";
+
+        assert_eq!(Ok(Stimulus { events: vec![
+            Event {
+                trigger: Trigger::Cycle(1000),
+                action: Action { port_letter: 'B', pin_index: Some(3), value: 1 },
+                line: 3,
+            },
+            Event {
+                trigger: Trigger::Symbol("wait_for_button".to_owned()),
+                action: Action { port_letter: 'D', pin_index: None, value: 4 },
+                line: 4,
+            },
+        ]}), script.parse());
+    }
+
+    #[test]
+    fn rejects_invalid_events() {
+        assert!("at cycle x: PINB3 = HIGH".parse::<Stimulus>().is_err());
+        assert!("at cycle 10: PORTB3 = HIGH".parse::<Stimulus>().is_err());
+        assert!("at cycle 10: PINB9 = HIGH".parse::<Stimulus>().is_err());
+        assert!("at cycle 10: PINB3 = 0x04".parse::<Stimulus>().is_err());
+        assert!("PINB3 = HIGH".parse::<Stimulus>().is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! Listing of the symbols in an executable that can be watched.
+
+use avr_sim::debug_info::DebugInfo;
+use avr_sim::{MemorySpace, WatchableSymbol};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Prints every watchable symbol, sorted by memory space and address.
+pub fn print(format: Format, watchable_symbols: &[WatchableSymbol], debug_info: &DebugInfo) {
+    let mut symbols = watchable_symbols.iter().collect::<Vec<_>>();
+    symbols.sort_by_key(|s| (s.memory_space, s.address.address, &s.name));
+
+    match format {
+        Format::Text => print_text(&symbols, debug_info),
+        Format::Json => print_json(&symbols, debug_info),
+    }
+}
+
+fn print_text(symbols: &[&WatchableSymbol], debug_info: &DebugInfo) {
+    let header = ["NAME", "SPACE", "ADDRESS", "SIZE", "SECTION", "TYPE", "DECLARED AT"];
+
+    let rows = symbols.iter().map(|symbol| {
+        let declaration = debug_info.declaration(&symbol.name);
+
+        vec![
+            symbol.name.clone(),
+            memory_space_name(symbol.memory_space).to_owned(),
+            format!("0x{:04x}", symbol.address.address),
+            symbol.size.to_string(),
+            symbol.section.clone(),
+            declaration.map(|d| d.type_name.clone()).unwrap_or_else(|| "-".to_owned()),
+            declaration.and_then(|d| d.location.as_ref()).map(ToString::to_string).unwrap_or_else(|| "-".to_owned()),
+        ]
+    }).collect::<Vec<_>>();
+
+    let column_widths = (0..header.len()).map(|column| {
+        rows.iter().map(|row| row[column].len()).chain(std::iter::once(header[column].len())).max().unwrap()
+    }).collect::<Vec<_>>();
+
+    let print_row = |cells: Vec<&str>| {
+        let padded = cells.iter().zip(column_widths.iter()).map(|(cell, &width)| format!("{:width$}", cell, width = width)).collect::<Vec<_>>();
+        println!("{}", padded.join("  ").trim_end());
+    };
+
+    print_row(header.to_vec());
+    for row in rows.iter() {
+        print_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn print_json(symbols: &[&WatchableSymbol], debug_info: &DebugInfo) {
+    let symbols = symbols.iter().map(|symbol| {
+        let declaration = debug_info.declaration(&symbol.name);
+
+        serde_json::json!({
+            "name": symbol.name,
+            "memory_space": memory_space_name(symbol.memory_space),
+            "address": symbol.address.address,
+            "size": symbol.size,
+            "section": symbol.section,
+            "type": declaration.map(|d| &d.type_name),
+            "declared_at": declaration.and_then(|d| d.location.as_ref()).map(|location| serde_json::json!({
+                "path": location.path.display().to_string(),
+                "line": location.line,
+            })),
+        })
+    }).collect::<Vec<_>>();
+
+    println!("{}", serde_json::to_string_pretty(&symbols).unwrap());
+}
+
+fn memory_space_name(space: MemorySpace) -> &'static str {
+    match space {
+        MemorySpace::Program => "program",
+        MemorySpace::Data => "data",
+    }
+}
@@ -0,0 +1,256 @@
+//! The types of variables, as described by DWARF.
+//!
+//! Watch expressions use these to follow pointers, index arrays and find
+//! struct members without the user spelling out every offset.
+
+use crate::debug_info::Reader;
+use crate::DataType;
+use std::collections::HashMap;
+
+/// Refers to a type in [`Types`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TypeId(usize);
+
+/// A type with typedefs and qualifiers such as `const` stripped off.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// An integer, character, boolean or enum.
+    Scalar(DataType),
+    /// A data space pointer or reference. `void *` has no pointee.
+    Pointer { pointee: Option<TypeId> },
+    Array { element: TypeId, count: Option<u32> },
+    /// A struct, class or union.
+    Struct { size: u32, members: Vec<Member> },
+    /// A type that cannot be watched, such as a float or a function.
+    Opaque { size: Option<u32> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub name: String,
+    /// The offset in bytes from the start of the struct.
+    pub offset: u32,
+    pub type_id: TypeId,
+}
+
+/// Every type referred to by a global or local variable.
+#[derive(Clone, Debug, Default)]
+pub struct Types {
+    types: Vec<Type>,
+}
+
+impl Types {
+    pub fn get(&self, id: TypeId) -> &Type {
+        &self.types[id.0]
+    }
+
+    /// Gets the number of bytes a value of the type occupies, if it is known.
+    pub fn size_of(&self, id: TypeId) -> Option<u32> {
+        match *self.get(id) {
+            Type::Scalar(ref data_type) => Some(data_type.minimum_size()),
+            // Pointers are 16 bits on AVR.
+            Type::Pointer { .. } => Some(2),
+            Type::Array { element, count } => Some(self.size_of(element)? * count?),
+            Type::Struct { size, .. } => Some(size),
+            Type::Opaque { size } => size,
+        }
+    }
+
+    /// Gets the watch data type that a value of the type is printed as, if there is one.
+    ///
+    /// Pointers are printed as addresses, and character arrays as strings.
+    pub fn data_type(&self, id: TypeId) -> Option<DataType> {
+        match *self.get(id) {
+            Type::Scalar(ref data_type) => Some(data_type.clone()),
+            Type::Pointer { .. } => Some(DataType::U16),
+            Type::Array { element, .. } if *self.get(element) == Type::Scalar(DataType::Char) => {
+                Some(DataType::NullTerminated(Box::new(DataType::Char)))
+            },
+            Type::Array { .. } | Type::Struct { .. } | Type::Opaque { .. } => None,
+        }
+    }
+}
+
+/// Adds the types of a single compilation unit to [`Types`].
+///
+/// DWARF refers to types by their offset in the unit, so each offset is only
+/// read once. This also stops self-referential structs from recursing forever.
+pub(crate) struct UnitTypes<'t> {
+    types: &'t mut Types,
+    ids: HashMap<gimli::UnitOffset, TypeId>,
+}
+
+impl<'t> UnitTypes<'t> {
+    pub fn new(types: &'t mut Types) -> Self {
+        UnitTypes { types, ids: HashMap::new() }
+    }
+
+    pub fn data_type(&self, id: TypeId) -> Option<DataType> {
+        self.types.data_type(id)
+    }
+
+    /// Reads the type at the given offset.
+    pub fn resolve(
+        &mut self,
+        dwarf: &gimli::Dwarf<Reader>,
+        unit: &gimli::Unit<Reader>,
+        offset: gimli::UnitOffset,
+    ) -> Result<TypeId, gimli::Error> {
+        if let Some(&id) = self.ids.get(&offset) {
+            return Ok(id);
+        }
+
+        let entry = unit.entry(offset)?;
+        let inner_type = match entry.attr_value(gimli::DW_AT_type)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+
+        // Qualifiers and typedefs do not change how a value is read.
+        if let gimli::DW_TAG_typedef | gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type = entry.tag() {
+            let id = match inner_type {
+                Some(inner_type) => self.resolve(dwarf, unit, inner_type)?,
+                None => self.push(Type::Opaque { size: None }), // void
+            };
+            self.ids.insert(offset, id);
+            return Ok(id);
+        }
+
+        // Register the type before reading what it refers to, in case that refers back to it.
+        let id = self.push(Type::Opaque { size: None });
+        self.ids.insert(offset, id);
+
+        let size = entry.attr_value(gimli::DW_AT_byte_size)?.and_then(|size| size.udata_value()).map(|size| size as u32);
+
+        let ty = match entry.tag() {
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type | gimli::DW_TAG_rvalue_reference_type => Type::Pointer {
+                pointee: match inner_type {
+                    Some(inner_type) => Some(self.resolve(dwarf, unit, inner_type)?),
+                    None => None,
+                },
+            },
+            gimli::DW_TAG_array_type => match inner_type {
+                Some(inner_type) => {
+                    // 'int x[3][2]' is a single DWARF type with a subrange for each dimension.
+                    let mut counts = array_counts(unit, offset)?;
+                    let outer_count = if counts.is_empty() { None } else { counts.remove(0) };
+
+                    let mut element = self.resolve(dwarf, unit, inner_type)?;
+                    for count in counts.into_iter().rev() {
+                        element = self.push(Type::Array { element, count });
+                    }
+
+                    Type::Array { element, count: outer_count }
+                },
+                None => Type::Opaque { size },
+            },
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => Type::Struct {
+                size: size.unwrap_or(0),
+                members: self.members(dwarf, unit, offset)?,
+            },
+            gimli::DW_TAG_enumeration_type => match size.and_then(|size| integer_type(size, false)) {
+                Some(data_type) => Type::Scalar(data_type),
+                None => Type::Opaque { size },
+            },
+            gimli::DW_TAG_base_type => match base_type(dwarf, unit, &entry, size)? {
+                Some(data_type) => Type::Scalar(data_type),
+                None => Type::Opaque { size },
+            },
+            _ => Type::Opaque { size },
+        };
+
+        self.types.types[id.0] = ty;
+        Ok(id)
+    }
+
+    fn push(&mut self, ty: Type) -> TypeId {
+        self.types.types.push(ty);
+        TypeId(self.types.types.len() - 1)
+    }
+
+    fn members(
+        &mut self,
+        dwarf: &gimli::Dwarf<Reader>,
+        unit: &gimli::Unit<Reader>,
+        offset: gimli::UnitOffset,
+    ) -> Result<Vec<Member>, gimli::Error> {
+        let mut members = Vec::new();
+        let mut tree = unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            if entry.tag() != gimli::DW_TAG_member {
+                continue;
+            }
+
+            let (name, type_offset) = match (entry.attr_value(gimli::DW_AT_name)?, entry.attr_value(gimli::DW_AT_type)?) {
+                (Some(name), Some(gimli::AttributeValue::UnitRef(type_offset))) => (dwarf.attr_string(unit, name)?.to_string_lossy().into_owned(), type_offset),
+                _ => continue, // anonymous members
+            };
+            // Union members have no offset.
+            let offset = entry.attr_value(gimli::DW_AT_data_member_location)?.and_then(|offset| offset.udata_value()).unwrap_or(0) as u32;
+
+            members.push(Member { name, offset, type_id: self.resolve(dwarf, unit, type_offset)? });
+        }
+
+        Ok(members)
+    }
+}
+
+/// Gets the number of elements in each dimension of an array, outermost first.
+fn array_counts(unit: &gimli::Unit<Reader>, offset: gimli::UnitOffset) -> Result<Vec<Option<u32>>, gimli::Error> {
+    let mut counts = Vec::new();
+    let mut tree = unit.entries_tree(Some(offset))?;
+    let mut children = tree.root()?.children();
+
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        if entry.tag() != gimli::DW_TAG_subrange_type {
+            continue;
+        }
+
+        let count = match (entry.attr_value(gimli::DW_AT_count)?, entry.attr_value(gimli::DW_AT_upper_bound)?) {
+            (Some(count), _) => count.udata_value().map(|count| count as u32),
+            (None, Some(upper_bound)) => upper_bound.udata_value().map(|bound| bound as u32 + 1),
+            (None, None) => None,
+        };
+        counts.push(count);
+    }
+
+    Ok(counts)
+}
+
+fn base_type(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &gimli::Unit<Reader>,
+    entry: &gimli::DebuggingInformationEntry<Reader>,
+    size: Option<u32>,
+) -> Result<Option<DataType>, gimli::Error> {
+    let is_plain_char = match entry.attr_value(gimli::DW_AT_name)? {
+        Some(name) => dwarf.attr_string(unit, name)?.slice() == b"char",
+        None => false,
+    };
+
+    Ok(match (entry.attr_value(gimli::DW_AT_encoding)?, size) {
+        (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed_char)), Some(1)) |
+            (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_unsigned_char)), Some(1)) if is_plain_char => Some(DataType::Char),
+        (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed)), Some(size)) |
+            (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_signed_char)), Some(size)) => integer_type(size, true),
+        (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_unsigned)), Some(size)) |
+            (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_unsigned_char)), Some(size)) |
+            (Some(gimli::AttributeValue::Encoding(gimli::DW_ATE_boolean)), Some(size)) => integer_type(size, false),
+        _ => None,
+    })
+}
+
+fn integer_type(size: u32, is_signed: bool) -> Option<DataType> {
+    match (size, is_signed) {
+        (1, false) => Some(DataType::U8), (1, true) => Some(DataType::I8),
+        (2, false) => Some(DataType::U16), (2, true) => Some(DataType::I16),
+        (4, false) => Some(DataType::U32), (4, true) => Some(DataType::I32),
+        (8, false) => Some(DataType::U64), (8, true) => Some(DataType::I64),
+        (16, false) => Some(DataType::U128), (16, true) => Some(DataType::I128),
+        _ => None,
+    }
+}
@@ -0,0 +1,184 @@
+//! Serial communication with the simulated program over a USART.
+//!
+//! Bytes that the program transmits are collected rather than printed
+//! straight away, so that they can be written in order with everything else
+//! the simulator prints. Bytes are received from a queue whenever the USART
+//! is ready for them.
+
+use crate::{Error, Result, avr_ioctl_def};
+use std::collections::VecDeque;
+use std::fmt;
+use std::os::raw::c_void;
+
+/// A USART that is connected to the simulator.
+pub(crate) struct Uart {
+    index: u8,
+    /// Shared with the simavr IRQ hooks, which are given a pointer to it.
+    hooks: Box<HookState>,
+    input_irq: *mut simavr::sys::avr_irq_t,
+    output_irq: *mut simavr::sys::avr_irq_t,
+    /// Null if the USART does not say when it is ready to receive.
+    xon_irq: *mut simavr::sys::avr_irq_t,
+    xoff_irq: *mut simavr::sys::avr_irq_t,
+    /// Bytes that have not yet been received by the program.
+    pending_input: VecDeque<u8>,
+    transfers: Vec<Transfer>,
+}
+
+#[derive(Default)]
+struct HookState {
+    /// Whether the receiver is enabled and has room in its buffer.
+    ready_for_input: bool,
+    output: Vec<u8>,
+}
+
+/// A byte sent over a USART.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    /// The CPU cycle the byte was sent or received in.
+    pub cycle: u64,
+    pub direction: Direction,
+    pub byte: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the program.
+    Transmit,
+    /// Received by the program.
+    Receive,
+}
+
+impl Uart {
+    /// Connects to the USART with the given index, such as 1 for USART1.
+    pub fn attach(avr: &mut simavr::Avr, index: u8) -> Result<Self> {
+        let name = (b'0' + index) as char;
+        let mut hooks = Box::<HookState>::default();
+        let param = &mut *hooks as *mut HookState as *mut c_void;
+
+        unsafe {
+            let uart = avr_ioctl_def('u', 'a', 'r', name);
+            let irq = |index| simavr::sys::avr_io_getirq(avr.underlying(), uart, index as _);
+            let (input_irq, output_irq, xon_irq, xoff_irq) = (
+                irq(simavr::sys::UART_IRQ_INPUT), irq(simavr::sys::UART_IRQ_OUTPUT),
+                irq(simavr::sys::UART_IRQ_OUT_XON), irq(simavr::sys::UART_IRQ_OUT_XOFF),
+            );
+
+            if input_irq.is_null() || output_irq.is_null() {
+                return Err(Error::Ioctl(format!("cannot connect to USART{}, the MCU may not have it", index)));
+            }
+
+            // Stop simavr from also printing transmitted bytes itself.
+            let mut flags: u32 = 0;
+            simavr::sys::avr_ioctl(avr.underlying(), avr_ioctl_def('u', 'a', 'g', name), &mut flags as *mut u32 as *mut _);
+            flags &= !simavr::sys::AVR_UART_FLAG_STDIO;
+            simavr::sys::avr_ioctl(avr.underlying(), avr_ioctl_def('u', 'a', 's', name), &mut flags as *mut u32 as *mut _);
+
+            let (xon_irq, xoff_irq) = if xon_irq.is_null() || xoff_irq.is_null() {
+                (std::ptr::null_mut(), std::ptr::null_mut())
+            } else {
+                (xon_irq, xoff_irq)
+            };
+
+            // The hooks are unregistered when the UART is dropped, before `hooks` is freed.
+            simavr::sys::avr_irq_register_notify(output_irq, Some(output_hook), param);
+            if !xon_irq.is_null() {
+                simavr::sys::avr_irq_register_notify(xon_irq, Some(xon_hook), param);
+                simavr::sys::avr_irq_register_notify(xoff_irq, Some(xoff_hook), param);
+            }
+
+            Ok(Uart { index, hooks, input_irq, output_irq, xon_irq, xoff_irq, pending_input: VecDeque::new(), transfers: Vec::new() })
+        }
+    }
+
+    /// Queues bytes for the program to receive.
+    ///
+    /// Fails if the USART never says when it is ready to receive, as the
+    /// bytes would never be passed to it.
+    pub fn send(&mut self, bytes: &[u8]) -> Result<()> {
+        if self.xon_irq.is_null() {
+            return Err(Error::Ioctl(format!("USART{} does not report when it is ready to receive, so input cannot be sent to it", self.index)));
+        }
+
+        self.pending_input.extend(bytes);
+        Ok(())
+    }
+
+    /// Passes the next queued byte to the USART if it is ready to receive it.
+    pub fn before_instruction(&mut self, avr: &simavr::Avr) {
+        if !self.hooks.ready_for_input {
+            return;
+        }
+
+        if let Some(byte) = self.pending_input.pop_front() {
+            self.transfers.push(Transfer { cycle: avr.raw().cycle, direction: Direction::Receive, byte });
+            unsafe { simavr::sys::avr_raise_irq(self.input_irq, byte as u32) };
+        }
+    }
+
+    /// Takes the bytes transmitted by the program during the last instruction.
+    pub fn after_instruction(&mut self, avr: &simavr::Avr) -> Vec<u8> {
+        let output = std::mem::take(&mut self.hooks.output);
+        let cycle = avr.raw().cycle;
+
+        self.transfers.extend(output.iter().map(|&byte| Transfer { cycle, direction: Direction::Transmit, byte }));
+        output
+    }
+
+    /// Takes every byte sent or received since the last call.
+    pub fn take_transfers(&mut self) -> Vec<Transfer> {
+        std::mem::take(&mut self.transfers)
+    }
+}
+
+impl Drop for Uart {
+    fn drop(&mut self) {
+        let param = &mut *self.hooks as *mut HookState as *mut c_void;
+
+        unsafe {
+            simavr::sys::avr_irq_unregister_notify(self.output_irq, Some(output_hook), param);
+            if !self.xon_irq.is_null() {
+                simavr::sys::avr_irq_unregister_notify(self.xon_irq, Some(xon_hook), param);
+                simavr::sys::avr_irq_unregister_notify(self.xoff_irq, Some(xoff_hook), param);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Transfer {
+    /// Formats the transfer as a line of a transcript, such as `1234 TX 0x41 'A'`.
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Transmit => "TX",
+            Direction::Receive => "RX",
+        };
+
+        write!(fmt, "{} {} 0x{:02x} {:?}", self.cycle, direction, self.byte, self.byte as char)
+    }
+}
+
+unsafe extern "C" fn output_hook(_irq: *mut simavr::sys::avr_irq_t, value: u32, param: *mut c_void) {
+    let hooks = &mut *(param as *mut HookState);
+    hooks.output.push(value as u8);
+}
+
+unsafe extern "C" fn xon_hook(_irq: *mut simavr::sys::avr_irq_t, _value: u32, param: *mut c_void) {
+    let hooks = &mut *(param as *mut HookState);
+    hooks.ready_for_input = true;
+}
+
+unsafe extern "C" fn xoff_hook(_irq: *mut simavr::sys::avr_irq_t, _value: u32, param: *mut c_void) {
+    let hooks = &mut *(param as *mut HookState);
+    hooks.ready_for_input = false;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transfers_are_formatted_as_transcript_lines() {
+        assert_eq!("1234 TX 0x41 'A'", Transfer { cycle: 1234, direction: Direction::Transmit, byte: b'A' }.to_string());
+        assert_eq!("99 RX 0x0a '\\n'", Transfer { cycle: 99, direction: Direction::Receive, byte: b'\n' }.to_string());
+    }
+}
@@ -0,0 +1,119 @@
+//! Detection of loads from uninitialized SRAM.
+//!
+//! Every byte of SRAM has a shadow bit recording whether it has been written
+//! since the chip was reset. The register file and IO space are always
+//! considered defined.
+
+use crate::debug_info::DebugInfo;
+use crate::diagnostic::Diagnostic;
+use crate::instruction::{self, Effects};
+use std::collections::BTreeSet;
+
+pub struct UninitChecker {
+    /// The first SRAM address.
+    sram_start: u16,
+    /// Whether each byte of SRAM has been written, indexed from `sram_start`.
+    defined: Vec<bool>,
+    /// The decoded instruction that is being executed, if the CPU is running.
+    current_instruction: Option<Effects>,
+    /// The stack pointer before the current cycle.
+    stack_pointer_before: u16,
+    /// The PCs that have already been reported, so that loops do not flood the output.
+    reported_pcs: BTreeSet<u32>,
+    /// Reports that have not yet been taken.
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl UninitChecker {
+    /// Creates a checker for a freshly reset AVR, with all of SRAM undefined.
+    pub fn new(avr: &simavr::Avr) -> Self {
+        let sram_start = avr.raw().ioend + 1;
+        let sram_size = (avr.raw().ramend + 1 - sram_start) as usize;
+
+        UninitChecker {
+            sram_start,
+            defined: vec![false; sram_size],
+            current_instruction: None,
+            stack_pointer_before: 0,
+            reported_pcs: BTreeSet::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Marks all of SRAM as defined.
+    pub fn define_all(&mut self) {
+        self.defined.iter_mut().for_each(|d| *d = true);
+    }
+
+    /// Checks the loads of the instruction that is about to be executed.
+    pub fn before_instruction(&mut self, avr: &simavr::Avr, debug_info: &DebugInfo) {
+        self.stack_pointer_before = instruction::stack_pointer(avr);
+
+        if avr.raw().state != simavr::sys::cpu_Running as _ {
+            self.current_instruction = None;
+            return;
+        }
+
+        let effects = instruction::decode_next(avr);
+        let pc = avr.raw().pc;
+
+        let undefined_loads = effects.loads().filter(|&address| !self.is_defined(address)).collect::<Vec<_>>();
+
+        if !undefined_loads.is_empty() && self.reported_pcs.insert(pc) {
+            let addresses = undefined_loads.iter().map(|&a| debug_info.describe_data_address(a as u32)).collect::<Vec<_>>();
+
+            self.diagnostics.push(Diagnostic::error(format!("read of uninitialized memory at {} in CPU cycle #{} ({})",
+                                                           addresses.join(", "), avr.raw().cycle, debug_info.describe_pc(pc))));
+        }
+
+        self.current_instruction = Some(effects);
+    }
+
+    /// Marks the stores of the instruction that was just executed as defined.
+    pub fn after_instruction(&mut self, avr: &simavr::Avr) {
+        let stack_pointer_after = instruction::stack_pointer(avr);
+
+        let expected_stack_pointer = match self.current_instruction.take() {
+            Some(effects) => {
+                effects.stores().for_each(|address| self.define(address));
+
+                if effects.writes_stack_pointer() {
+                    return;
+                }
+                (self.stack_pointer_before as i32 + effects.stack_pointer_delta) as u16
+            },
+            None => self.stack_pointer_before,
+        };
+
+        // Interrupts are serviced after the instruction, by pushing the return address
+        // to the stack. This is the only way the stack pointer moves unexpectedly.
+        if stack_pointer_after < expected_stack_pointer {
+            (stack_pointer_after + 1..=expected_stack_pointer).for_each(|address| self.define(address));
+        }
+    }
+
+    /// Takes all reports made since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Checks whether any uninitialized reads have been reported.
+    pub fn found_errors(&self) -> bool {
+        !self.reported_pcs.is_empty()
+    }
+
+    fn is_defined(&self, address: u16) -> bool {
+        match address.checked_sub(self.sram_start) {
+            Some(offset) => self.defined.get(offset as usize).cloned().unwrap_or(true),
+            None => true,
+        }
+    }
+
+    fn define(&mut self, address: u16) {
+        if let Some(offset) = address.checked_sub(self.sram_start) {
+            if let Some(defined) = self.defined.get_mut(offset as usize) {
+                *defined = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Recording of watched values as a Value Change Dump, for viewing in tools such as GTKWave.
+
+use crate::{DataType, Error, Result, Simulator, Watch, WatchState};
+use std::io::Write;
+
+/// Writes a signal for every IO register and numeric memory watch, with a
+/// timestamp derived from the CPU cycle and the clock frequency.
+pub struct VcdRecorder<W: Write> {
+    out: W,
+    signals: Vec<Signal>,
+    frequency: u32,
+    /// The time of the last change written, so that each timestamp is only written once.
+    last_time: Option<u128>,
+}
+
+struct Signal {
+    watch: Watch,
+    identifier: String,
+    width: u32,
+    /// The last value written, formatted as it appears in the dump.
+    last_value: Option<String>,
+}
+
+/// The timescale of the dump. Picoseconds are fine grained enough that common
+/// clock frequencies such as 16 MHz have a whole number of ticks per cycle.
+const PICOSECONDS_PER_SECOND: u128 = 1_000_000_000_000;
+
+impl<W: Write> VcdRecorder<W> {
+    /// Writes the header and the initial value of every signal.
+    ///
+    /// Watches that are not numeric, such as strings, are skipped. The width
+    /// of each signal is taken from its current value, so locals that are not
+    /// in scope yet need an explicit type.
+    pub fn new(out: W, watches: &[Watch], simulator: &Simulator) -> Result<Self> {
+        let mut signals: Vec<Signal> = Vec::new();
+
+        for watch in watches {
+            if signals.iter().any(|s| s.watch == *watch) {
+                continue;
+            }
+
+            let state = simulator.read_watch(watch)?;
+            if let Some(width) = signal_width(watch, &state) {
+                signals.push(Signal { watch: watch.clone(), identifier: identifier(signals.len()), width, last_value: None });
+            }
+        }
+
+        let mut recorder = VcdRecorder { out, signals, frequency: simulator.avr().frequency(), last_time: None };
+        recorder.write_header(simulator.avr().name()).map_err(io_error)?;
+        recorder.record(simulator)?;
+
+        Ok(recorder)
+    }
+
+    /// Writes every signal that has changed since the last call.
+    pub fn record(&mut self, simulator: &Simulator) -> Result<()> {
+        let time = self.time_of_cycle(simulator.avr().raw().cycle);
+
+        for i in 0..self.signals.len() {
+            let state = simulator.read_watch(&self.signals[i].watch)?;
+            let signal = &self.signals[i];
+            let value = format_value(signal.width, &state);
+
+            if signal.last_value.as_ref() == Some(&value) {
+                continue;
+            }
+
+            if self.last_time != Some(time) {
+                writeln!(self.out, "#{}", time).map_err(io_error)?;
+                self.last_time = Some(time);
+            }
+
+            if signal.width == 1 {
+                writeln!(self.out, "{}{}", value, signal.identifier).map_err(io_error)?;
+            } else {
+                writeln!(self.out, "b{} {}", value, signal.identifier).map_err(io_error)?;
+            }
+            self.signals[i].last_value = Some(value);
+        }
+
+        Ok(())
+    }
+
+    /// Flushes everything written so far.
+    pub fn finish(mut self) -> Result<()> {
+        self.out.flush().map_err(io_error)
+    }
+
+    fn time_of_cycle(&self, cycle: u64) -> u128 {
+        cycle as u128 * PICOSECONDS_PER_SECOND / self.frequency.max(1) as u128
+    }
+
+    fn write_header(&mut self, mcu_name: &str) -> std::io::Result<()> {
+        writeln!(self.out, "$version avr-sim {} $end", env!("CARGO_PKG_VERSION"))?;
+        writeln!(self.out, "$comment {} at {} Hz $end", mcu_name, self.frequency)?;
+        writeln!(self.out, "$timescale 1ps $end")?;
+        writeln!(self.out, "$scope module {} $end", mcu_name)?;
+        for signal in self.signals.iter() {
+            writeln!(self.out, "$var wire {} {} {} $end", signal.width, signal.identifier, reference(&signal.watch))?;
+        }
+        writeln!(self.out, "$upscope $end")?;
+        writeln!(self.out, "$enddefinitions $end")?;
+        Ok(())
+    }
+}
+
+/// Gets the number of bits in a signal, or `None` if the watch cannot be recorded.
+fn signal_width(watch: &Watch, state: &WatchState) -> Option<u32> {
+    match *state {
+        WatchState::HighLowBit(..) => Some(1),
+        WatchState::Char(..) | WatchState::U8(..) | WatchState::I8(..) | WatchState::IoRegisterStatus(..) => Some(8),
+        WatchState::U16(..) | WatchState::I16(..) => Some(16),
+        WatchState::U32(..) | WatchState::I32(..) => Some(32),
+        WatchState::U64(..) | WatchState::I64(..) => Some(64),
+        WatchState::U128(..) | WatchState::I128(..) => Some(128),
+        WatchState::Array { .. } => None,
+        // The value cannot be read yet, but an explicit type gives the width.
+        WatchState::OutOfScope | WatchState::OptimizedOut => match *watch {
+            Watch::Local { data_type: Some(ref data_type), .. } | Watch::Expression { data_type: Some(ref data_type), .. } => match *data_type {
+                DataType::NullTerminated(..) => None,
+                ref data_type => Some(data_type.minimum_size() * 8),
+            },
+            _ => None,
+        },
+    }
+}
+
+/// Formats a value as binary digits, or 'x' if it cannot be read.
+fn format_value(width: u32, state: &WatchState) -> String {
+    let bits: u128 = match *state {
+        WatchState::HighLowBit(b) => b as u128,
+        WatchState::Char(c) => c as u128 & 0xff,
+        WatchState::U8(i) => i.into(),
+        WatchState::U16(i) => i.into(),
+        WatchState::U32(i) => i.into(),
+        WatchState::U64(i) => i.into(),
+        WatchState::U128(i) => i,
+        // Two's complement, truncated to the width of the signal.
+        WatchState::I8(i) => i as u8 as u128,
+        WatchState::I16(i) => i as u16 as u128,
+        WatchState::I32(i) => i as u32 as u128,
+        WatchState::I64(i) => i as u64 as u128,
+        WatchState::I128(i) => i as u128,
+        WatchState::IoRegisterStatus(r) => r.into(),
+        WatchState::Array { .. } | WatchState::OutOfScope | WatchState::OptimizedOut => return "x".to_owned(),
+    };
+
+    if width == 1 {
+        bits.to_string()
+    } else {
+        format!("{:b}", bits)
+    }
+}
+
+/// Gets a short identifier for a signal, made of printable ASCII characters.
+fn identifier(mut index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!' + 1) as usize;
+
+    let mut identifier = String::new();
+    loop {
+        identifier.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
+
+/// Gets the name of a signal. Names in a dump cannot contain spaces.
+fn reference(watch: &Watch) -> String {
+    let location = watch.location();
+    let location = location.strip_prefix("IO ").unwrap_or(&location);
+
+    location.chars().map(|c| if c.is_whitespace() { '_' } else { c }).collect()
+}
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::CommandLine(format!("could not write the value change dump: {}", error))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values_are_formatted_as_binary() {
+        assert_eq!("1", format_value(1, &WatchState::HighLowBit(true)));
+        assert_eq!("101", format_value(8, &WatchState::U8(5)));
+        assert_eq!("1111111111111110", format_value(16, &WatchState::I16(-2)));
+        assert_eq!("x", format_value(16, &WatchState::OutOfScope));
+    }
+
+    #[test]
+    fn identifiers_are_unique() {
+        let identifiers = (0..10_000).map(identifier).collect::<std::collections::BTreeSet<_>>();
+
+        assert_eq!(10_000, identifiers.len());
+        assert_eq!("!", identifier(0));
+        assert!(identifiers.iter().all(|i| i.chars().all(|c| c.is_ascii_graphic())));
+    }
+}
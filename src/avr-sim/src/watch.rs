@@ -0,0 +1,893 @@
+//! Values that can be watched while the simulation runs.
+
+use crate::{Error, Result, avr_ioctl_def};
+use crate::debug_info::{DebugInfo, DATA_SPACE_ELF_OFFSET, EEPROM_ELF_OFFSET};
+use crate::expression::{self, Expression, Place};
+use crate::locals::{LocalValue, LocalVariable};
+use byteorder::ByteOrder as _;
+
+type ByteOrder = byteorder::LittleEndian;
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum MemorySpace {
+    Program,
+    Data,
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum Watch {
+    MemoryAddress {
+        space: MemorySpace,
+        address: Pointer,
+        data_type: DataType,
+    },
+    Symbol {
+        name: String,
+        data_type: DataType,
+    },
+    /// A local variable or parameter, found through the debug information.
+    Local {
+        scope: LocalScope,
+        name: String,
+        /// The type to read the variable as, or `None` to use its declared type.
+        data_type: Option<DataType>,
+    },
+    /// An expression that follows pointers or members, such as `node->next`.
+    Expression {
+        expression: Expression,
+        /// The type to read the value as, or `None` to use its declared type.
+        data_type: Option<DataType>,
+    },
+    IoPort { port_letter: char, port_index: Option<u8> },
+    IoPin { port_letter: char, port_index: Option<u8> },
+    IoDataDirectionRegister { port_letter: char, port_index: Option<u8> },
+}
+
+/// Where to look for a local variable.
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum LocalScope {
+    /// A function, as in `callStuff::d`.
+    Function(String),
+    /// A source line, as in `file.c:42:i`.
+    Line { path: String, line: u64 },
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum DataType {
+    Char,
+    NullTerminated(Box<DataType>),
+    U8, U16, U32, U64, U128,
+    I8, I16, I32, I64, I128,
+    HighLowBit,
+    IoRegisterStatus,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub struct Pointer {
+    pub address: u32,
+    /// The radix the address was written in, which is always 10 or 16.
+    pub(crate) natural_radix: u32,
+}
+
+
+fn fetch_only_section_of_kind<'data, 'obj>(
+    section_kind: object::SectionKind,
+    object: &'obj object::read::File<'data>,
+) -> Result<object::read::Section<'data, 'obj>> {
+    use object::read::{Object, ObjectSection};
+
+    let matching_sections = object.sections().filter(|s| {
+        s.kind() == section_kind
+    }).collect::<Vec<_>>();
+
+    match matching_sections.len() {
+        0 => Err(Error::Elf(format!("there is no ELF section of kind {:?}", section_kind))),
+        1 => Ok(matching_sections.into_iter().next().unwrap()),
+        _ => {
+            let section_names = matching_sections.iter().map(|s| s.name().map(ToOwned::to_owned).unwrap_or(String::new())).collect::<Vec<_>>();
+            Err(Error::Elf(format!("there is more than one {:?} section (names: {})", section_kind, section_names.join(", "))))
+        },
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub struct WatchableSymbol {
+    pub name: String,
+    pub memory_space: MemorySpace,
+    /// The pointer as relative to the start of the memory space.
+    pub address: Pointer,
+    /// The size in bytes, or zero if the ELF file does not say.
+    pub size: u32,
+    /// The name of the ELF section that the symbol is defined in.
+    pub section: String,
+}
+
+pub fn parse_watchable_symbols_from_elf(elf_data: &[u8]) -> Result<Vec<WatchableSymbol>> {
+    use object::read::{Object, ObjectSection};
+
+    let object = object::read::File::parse(elf_data).map_err(|e| Error::Elf(e.to_string()))?;
+    let mut watchables = Vec::new();
+
+    let text_section = fetch_only_section_of_kind(object::SectionKind::Text, &object)?;
+    // Initialized and zeroed variables live in different sections, such as '.data' and '.bss'.
+    let data_sections = object.sections().filter(|s| {
+        let is_variable = s.kind() == object::SectionKind::Data || s.kind() == object::SectionKind::UninitializedData;
+        is_variable && s.address() >= DATA_SPACE_ELF_OFFSET && s.address() < EEPROM_ELF_OFFSET
+    }).collect::<Vec<_>>();
+
+    'symbols: for (_, symbol) in object.symbols() {
+        let symbol_name = if let Some(name) = symbol.name() {
+            name.trim()
+        } else {
+            continue 'symbols; // skip nameless symbols
+        };
+
+        if symbol_name.is_empty() {
+            continue 'symbols; // skip symbols with empty name.
+        }
+
+        let (parent_section, memory_space, relative_address) = if symbol.section_index() == Some(text_section.index()) {
+            (&text_section, MemorySpace::Program, symbol.address() - text_section.address())
+        } else if let Some(data_section) = data_sections.iter().find(|s| symbol.section_index() == Some(s.index())) {
+            (data_section, MemorySpace::Data, symbol.address() - DATA_SPACE_ELF_OFFSET)
+        } else {
+            continue 'symbols; // skip symbols not in text or data sections
+        };
+
+        watchables.push(WatchableSymbol {
+            name: symbol_name.to_owned(),
+            memory_space,
+            address: Pointer { address: relative_address as u32, natural_radix: 16 },
+            size: symbol.size() as u32,
+            section: parent_section.name().unwrap_or("").to_owned(),
+        });
+    }
+
+    Ok(watchables)
+}
+
+impl Watch {
+    /// Reads the current value of the watch.
+    ///
+    /// Local variables that are not in scope or were optimized out are
+    /// [`WatchState::OutOfScope`] or [`WatchState::OptimizedOut`] rather than errors.
+    pub fn current_value(&self,
+        avr: &simavr::Avr,
+        watchable_symbols: &[WatchableSymbol],
+        debug_info: &DebugInfo,
+    ) -> Result<WatchState> {
+        fn read_io_port(port_letter: char, port_index: Option<u8>, avr: &simavr::Avr, f: impl FnOnce(IoState) -> u8) -> Result<WatchState> {
+            let io_state = read_io_state(port_letter, avr)?;
+            let relevant_value = f(io_state);
+
+            match port_index {
+                Some(i) => {
+                    let mask = (0b1) << i;
+                    Ok(WatchState::HighLowBit(relevant_value & mask == mask))
+                },
+                None => {
+                    Ok(WatchState::IoRegisterStatus(relevant_value))
+                },
+            }
+        }
+
+        match *self {
+            Watch::MemoryAddress { space, address, ref data_type } => {
+                let bytes = self::read_current_memory_address(space, address, avr)?;
+                data_type.as_watch_state_from_bytes(bytes)
+            },
+            Watch::Symbol { ref name, ref data_type } => {
+                let bytes = match watchable_symbols.iter().find(|s| s.name == *name) {
+                    Some(WatchableSymbol { memory_space, address, .. }) => {
+                        self::read_current_memory_address(*memory_space, address.clone(), avr)?
+                    },
+                    None => return Err(Error::missing_symbol(name)),
+                };
+                data_type.as_watch_state_from_bytes(bytes)
+            },
+            Watch::Local { ref scope, ref name, ref data_type } => {
+                let local = scope.find(name, debug_info)?;
+                let data_type = local_data_type(self, local, data_type)?;
+
+                match local.read(data_type.minimum_size(), avr, debug_info)? {
+                    LocalValue::OutOfScope => Ok(WatchState::OutOfScope),
+                    LocalValue::OptimizedOut => Ok(WatchState::OptimizedOut),
+                    LocalValue::Memory(address) => {
+                        let bytes = self::read_current_memory_address(MemorySpace::Data, Pointer { address, natural_radix: 16 }, avr)?;
+                        data_type.as_watch_state_from_bytes(bytes)
+                    },
+                    LocalValue::Bytes(bytes) => data_type.as_watch_state_from_bytes(&bytes),
+                }
+            },
+            Watch::Expression { ref expression, ref data_type } => {
+                let (place, declared_type) = expression.locate(avr, watchable_symbols, debug_info)?;
+                let data_type = data_type.clone().or(declared_type).ok_or_else(|| {
+                    Error::CommandLine(format!("the type of '{}' is not known, give one explicitly as in '{}=u8'", expression, expression))
+                })?;
+
+                match place {
+                    Place::Memory { space, address } => {
+                        let bytes = self::read_current_memory_address(space, Pointer { address, natural_radix: 16 }, avr)?;
+                        data_type.as_watch_state_from_bytes(bytes)
+                    },
+                    Place::Bytes(bytes) => data_type.as_watch_state_from_bytes(&bytes),
+                    Place::Unavailable(state) => Ok(state),
+                }
+            },
+            Watch::IoPort { port_letter, port_index } => {
+                read_io_port(port_letter, port_index, avr, |s| s.port)
+            },
+            Watch::IoPin { port_letter, port_index } => {
+                read_io_port(port_letter, port_index, avr, |s| s.pin)
+            },
+            Watch::IoDataDirectionRegister { port_letter, port_index } => {
+                read_io_port(port_letter, port_index, avr, |s| s.data_direction_register)
+            },
+        }
+
+    }
+
+    /// Checks that the watch refers to memory that exists on the simulated MCU.
+    pub fn check_reachable(&self,
+        avr: &simavr::Avr,
+        watchable_symbols: &[WatchableSymbol],
+        debug_info: &DebugInfo,
+    ) -> Result<()> {
+        let (space, address, data_type) = match *self {
+            Watch::MemoryAddress { space, address, ref data_type } => (space, address, data_type),
+            Watch::Symbol { ref name, ref data_type } => match watchable_symbols.iter().find(|s| s.name == *name) {
+                Some(symbol) => (symbol.memory_space, symbol.address, data_type),
+                None => return Err(Error::missing_symbol(name)),
+            },
+            // Locals move around, so they are checked every time they are read.
+            Watch::Local { ref scope, ref name, ref data_type } => {
+                return local_data_type(self, scope.find(name, debug_info)?, data_type).map(|_| ());
+            },
+            // Pointers can change, so only the symbols are checked up front.
+            Watch::Expression { ref expression, .. } => return expression.check_resolved(watchable_symbols, debug_info),
+            // simavr reports ports that the MCU lacks when they are read.
+            Watch::IoPort { .. } | Watch::IoPin { .. } | Watch::IoDataDirectionRegister { .. } => return Ok(()),
+        };
+
+        let space_size = memory_space_size(space, avr);
+        let end = address.address as u64 + data_type.minimum_size() as u64;
+
+        if end > space_size as u64 {
+            Err(Error::AddressOutOfRange(format!("{} is {} bytes but {} on '{}' ends at 0x{:04x}",
+                                                 self.location(), data_type.minimum_size(), space.human_label(), avr.name(), space_size - 1)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Overwrites the value of the watch.
+    ///
+    /// Only memory addresses and symbols can be written.
+    pub fn write_value(&self,
+        value: &WatchState,
+        avr: &simavr::Avr,
+        watchable_symbols: &[WatchableSymbol],
+    ) -> Result<()> {
+        let (space, address) = match *self {
+            Watch::MemoryAddress { space, address, .. } => (space, address),
+            Watch::Symbol { ref name, .. } => match watchable_symbols.iter().find(|s| s.name == *name) {
+                Some(symbol) => (symbol.memory_space, symbol.address),
+                None => return Err(Error::missing_symbol(name)),
+            },
+            Watch::Local { .. } | Watch::Expression { .. } | Watch::IoPort { .. } | Watch::IoPin { .. } | Watch::IoDataDirectionRegister { .. } => {
+                return Err(Error::CommandLine(format!("{} cannot be written to", self.location())));
+            },
+        };
+
+        let bytes = value.to_bytes()?;
+        let memory = self::read_current_memory_address_mut(space, address, avr)?;
+
+        memory.get_mut(0..bytes.len()).ok_or_else(end_of_memory)?.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Describes the location of the watch for use in output.
+    pub fn location(&self) -> String {
+        match *self {
+            Watch::MemoryAddress { ref space, ref address, .. } => format!("{} ({})", address, space.human_label()),
+            Watch::Symbol { ref name, .. } => name.to_owned(),
+            Watch::Local { scope: LocalScope::Function(ref function), ref name, .. } => format!("{}::{}", function, name),
+            Watch::Local { scope: LocalScope::Line { ref path, line }, ref name, .. } => format!("{}:{}:{}", path, line, name),
+            Watch::Expression { ref expression, .. } => expression.to_string(),
+            Watch::IoPort { port_letter, port_index } => format!("IO PORT{}{}", port_letter, if let Some(i) = port_index { i.to_string() } else { String::new() }),
+            Watch::IoPin { port_letter, port_index } => format!("IO PIN{}{}", port_letter, if let Some(i) = port_index { i.to_string() } else { String::new() }),
+            Watch::IoDataDirectionRegister { port_letter, port_index } => format!("IO DDR{}{}", port_letter, if let Some(i) = port_index { i.to_string() } else { String::new() }),
+        }
+    }
+}
+
+impl LocalScope {
+    fn find<'d>(&self, name: &str, debug_info: &'d DebugInfo) -> Result<&'d LocalVariable> {
+        let local = match *self {
+            LocalScope::Function(ref function) => debug_info.local_in_function(function, name),
+            LocalScope::Line { ref path, line } => debug_info.local_at_line(path, line, name),
+        };
+
+        local.ok_or_else(|| {
+            let location = match *self {
+                LocalScope::Function(ref function) => format!("the function '{}'", function),
+                LocalScope::Line { ref path, line } => format!("line {} of '{}'", line, path),
+            };
+            Error::MissingSymbol(format!("there is no local variable '{}' in {}, or the ELF file contains no debug information", name, location))
+        })
+    }
+}
+
+/// Gets the type to read a local as, preferring the one given on the command line.
+fn local_data_type(watch: &Watch, local: &LocalVariable, data_type: &Option<DataType>) -> Result<DataType> {
+    match data_type.as_ref().or(local.data_type.as_ref()) {
+        Some(data_type) => Ok(data_type.clone()),
+        None => Err(Error::CommandLine(format!("the type of {} cannot be watched, give one explicitly as in '{}=u8'", watch.location(), watch.location()))),
+    }
+}
+
+/// Gets an immutable byte slice starting at the specified AVR memory address.
+pub fn read_current_memory_address<'avr>(
+    space: MemorySpace,
+    address: Pointer,
+    avr: &'avr simavr::Avr,
+) -> Result<&'avr [u8]> {
+    let (memory_space_start_host_ptr, memory_space_size) = memory_space_slice_parts(space, avr);
+    let memory_space_byte_slice = unsafe { std::slice::from_raw_parts(memory_space_start_host_ptr, memory_space_size) };
+
+    memory_space_byte_slice.get(address.address as usize..).ok_or_else(|| address_out_of_range(space, address))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WatchState {
+    Char(char),
+    Array { elements: Vec<WatchState>, data_type: DataType },
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    HighLowBit(bool),
+    IoRegisterStatus(u8),
+    /// A local variable whose scope does not include the current instruction.
+    OutOfScope,
+    /// A local variable that the compiler did not keep at the current instruction.
+    OptimizedOut,
+}
+
+impl WatchState {
+    /// Checks whether there is a value, rather than a local that cannot be read right now.
+    pub fn is_available(&self) -> bool {
+        !matches!(*self, WatchState::OutOfScope | WatchState::OptimizedOut)
+    }
+
+    /// Gets the in-memory representation of the value.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        match *self {
+            WatchState::Char(c) => Ok(vec![c as u8]),
+            WatchState::U8(i) => Ok(vec![i]),
+            WatchState::U16(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::U32(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::U64(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::U128(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::I8(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::I16(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::I32(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::I64(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::I128(i) => Ok(i.to_le_bytes().to_vec()),
+            WatchState::HighLowBit(b) => Ok(vec![b as u8]),
+            WatchState::IoRegisterStatus(r) => Ok(vec![r]),
+            WatchState::OutOfScope | WatchState::OptimizedOut => {
+                Err(Error::CommandLine(format!("{} has no in-memory representation", self)))
+            },
+            WatchState::Array { ref elements, .. } => {
+                // Arrays are always null terminated.
+                let mut bytes = Vec::new();
+                for element in elements {
+                    bytes.extend(element.to_bytes()?);
+                }
+                bytes.push(0);
+                Ok(bytes)
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for WatchState {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WatchState::Char(c) => std::fmt::Display::fmt(&c, fmt),
+            WatchState::U8(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::U16(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::U32(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::U64(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::U128(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::I8(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::I16(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::I32(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::I64(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::I128(i) => std::fmt::Display::fmt(&i, fmt),
+            WatchState::Array { ref elements, ref data_type } => {
+                let formatted_str = if *data_type == DataType::Char {
+                    let chars = elements.iter().map(|e| if let WatchState::Char(c) = e { c } else { unreachable!() });
+                    format!("{:?}", chars.collect::<String>())
+                } else {
+                    format!("{:?}", elements)
+                };
+
+                write!(fmt, "{}", formatted_str)
+            },
+            WatchState::HighLowBit(b) => {
+                let label = if b { "HIGH" } else { "LOW" };
+                write!(fmt, "{}", label)
+            },
+            WatchState::IoRegisterStatus(r) => {
+                for i in 0..8 {
+                    let is_set = r & (1<<i) == (1<<i);
+                    write!(fmt, "{}: {}", i, if is_set { "HIGH" } else { " LOW" })?;
+                    write!(fmt, ", ")?;
+                }
+
+                Ok(())
+            },
+            WatchState::OutOfScope => write!(fmt, "<out of scope>"),
+            WatchState::OptimizedOut => write!(fmt, "<optimized out>"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IoState {
+    port: u8,
+    pin: u8,
+    data_direction_register: u8,
+}
+
+const fn avr_ioctl_ioport_get_state(port_name: char) -> u32 {
+    avr_ioctl_def('i', 'o', 's', port_name)
+}
+
+fn read_io_state(port_letter: char, avr: &simavr::Avr) -> Result<IoState> {
+    let mut state: simavr::sys::avr_ioport_state_t = unsafe { std::mem::zeroed() };
+
+    let result = unsafe {
+        simavr::sys::avr_ioctl(avr.underlying(), avr_ioctl_ioport_get_state(port_letter), &mut state as *mut _ as *mut libc::c_void)
+    };
+
+    if result != 0 {
+        return Err(Error::Ioctl(format!("avr ioctl failed for port '{}', the MCU may not have this port", port_letter)));
+    }
+
+    Ok(IoState {
+        port: state.port() as u8,
+        pin: state.pin() as u8,
+        data_direction_register: state.ddr() as u8,
+    })
+}
+
+/// Gets a mutable byte slice starting at the specified AVR memory address.
+pub fn read_current_memory_address_mut<'avr>(
+    space: MemorySpace,
+    address: Pointer,
+    avr: &'avr simavr::Avr,
+) -> Result<&'avr mut [u8]> {
+    let (memory_space_start_host_ptr, memory_space_size) = memory_space_slice_parts(space, avr);
+    let memory_space_byte_slice = unsafe { std::slice::from_raw_parts_mut(memory_space_start_host_ptr as *mut _, memory_space_size) };
+
+    memory_space_byte_slice.get_mut(address.address as usize..).ok_or_else(|| address_out_of_range(space, address))
+}
+
+fn address_out_of_range(space: MemorySpace, address: Pointer) -> Error {
+    Error::AddressOutOfRange(format!("the address {} is outside of {}", address, space.human_label()))
+}
+
+/// A value runs past the end of its memory space.
+fn end_of_memory() -> Error {
+    Error::AddressOutOfRange("end of memory".to_owned())
+}
+
+fn memory_space_slice_parts<'avr>(space: MemorySpace, avr: &'avr simavr::Avr) -> (*const u8, usize) {
+    match space {
+        MemorySpace::Data => {
+            let (data_space_start, data_space_size) = unsafe {
+                ((*avr.underlying()).data as *const u8, (*avr.underlying()).ramend as usize + 1) // 'ramend' is the last valid address.
+            };
+
+            (data_space_start, data_space_size)
+        },
+        MemorySpace::Program => unsafe {
+            ((*avr.underlying()).flash as *const u8, (*avr.underlying()).flashend as usize + 1)
+        },
+    }
+}
+
+
+/// Gets the number of bytes in a memory space.
+pub fn memory_space_size(space: MemorySpace, avr: &simavr::Avr) -> u32 {
+    memory_space_slice_parts(space, avr).1 as u32
+}
+
+impl DataType {
+    /// Gets the smallest number of bytes a value of this type occupies.
+    ///
+    /// Null terminated values occupy at least their terminator.
+    pub fn minimum_size(&self) -> u32 {
+        match *self {
+            DataType::U8 | DataType::I8 | DataType::Char => 1,
+            DataType::U16 | DataType::I16 => 2,
+            DataType::U32 | DataType::I32 => 4,
+            DataType::U64 | DataType::I64 => 8,
+            DataType::U128 | DataType::I128 => 16,
+            DataType::NullTerminated(..) => 1,
+            DataType::HighLowBit | DataType::IoRegisterStatus => 1,
+        }
+    }
+
+    fn as_watch_state_from_bytes(&self, bytes: &[u8]) -> Result<WatchState> {
+        self.as_watch_state_from_bytes_internal(bytes).map(|(s, _)| s)
+    }
+
+    fn as_watch_state_from_bytes_internal<'b>(&self, bytes: &'b [u8]) -> Result<(WatchState, &'b [u8])> {
+        let parse_number = |byte_count: usize, interpret_bytes: fn(&[u8]) -> WatchState| {
+            bytes.get(0..byte_count).ok_or_else(end_of_memory)
+                .map(interpret_bytes)
+                .map(|s| (s, &bytes[byte_count..]))
+        };
+
+        match *self {
+            DataType::U8 => bytes.get(0).cloned().map(WatchState::U8).ok_or_else(end_of_memory).map(|s| (s, &bytes[1..])),
+            DataType::I8 => bytes.get(0).map(|&b| WatchState::I8(b as i8)).ok_or_else(end_of_memory).map(|s| (s, &bytes[1..])),
+            DataType::U16 => parse_number(2, |bytes| WatchState::U16(ByteOrder::read_u16(bytes))),
+            DataType::I16 => parse_number(2, |bytes| WatchState::I16(ByteOrder::read_i16(bytes))),
+            DataType::U32 => parse_number(4, |bytes| WatchState::U32(ByteOrder::read_u32(bytes))),
+            DataType::I32 => parse_number(4, |bytes| WatchState::I32(ByteOrder::read_i32(bytes))),
+            DataType::U64 => parse_number(8, |bytes| WatchState::U64(ByteOrder::read_u64(bytes))),
+            DataType::I64 => parse_number(8, |bytes| WatchState::I64(ByteOrder::read_i64(bytes))),
+            DataType::U128 => parse_number(16, |bytes| WatchState::U128(ByteOrder::read_u128(bytes))),
+            DataType::I128 => parse_number(16, |bytes| WatchState::I128(ByteOrder::read_i128(bytes))),
+            DataType::Char => bytes.get(0).map(|&b| WatchState::Char(b as char)).ok_or_else(end_of_memory).map(|s| (s, &bytes[1..])),
+            DataType::NullTerminated(ref element_type) => {
+                let mut elements: Vec<WatchState> = Vec::new();
+
+                let bytes_after_null = if let Some(first_null_index) = bytes.iter().position(|&b| b == 0) {
+                    let before_and_including_null = &bytes[0..first_null_index + 1];
+                    let after_null = &bytes[first_null_index..];
+
+                    let mut left_to_process = before_and_including_null;
+
+                    while left_to_process.len() > 1 { // wait until null empty.
+                        let (element, remaining) = element_type.as_watch_state_from_bytes_internal(left_to_process)?;
+                        elements.push(element);
+                        left_to_process = remaining;
+                    }
+
+                    after_null
+                } else {
+                    &[]
+                };
+
+                Ok((WatchState::Array { elements, data_type: *element_type.clone() }, bytes_after_null))
+            },
+            DataType::HighLowBit => bytes.get(0).cloned().map(|b| WatchState::HighLowBit(if b != 0 { true } else { false })).ok_or_else(end_of_memory).map(|s| (s, &bytes[1..])),
+            DataType::IoRegisterStatus => bytes.get(0).cloned().map(WatchState::IoRegisterStatus).ok_or_else(end_of_memory).map(|s| (s, &bytes[1..])),
+        }
+    }
+}
+
+// Parses a watch from a string. one watch may correspond to multiple backend watches.
+pub fn parse_watch(s: &str) -> Result<Vec<Watch>> {
+    let s = s.trim();
+
+    fn io_port_from_str(remaining: &str, f: impl FnOnce(char, Option<u8>) -> Vec<Watch>) -> Result<Vec<Watch>> {
+        let equals_char_index = match remaining.find('=') {
+            Some(index) => index,
+            None => return Err(Error::CommandLine("expected IO port to include an equals sign and a data type".to_owned())),
+        };
+        let specified_port = &remaining[(equals_char_index+1)..];
+        let port_letter = match specified_port.chars().nth(0).map(|mut c| { c.make_ascii_uppercase(); c }) {
+            Some(c) => c,
+            None => return Err(Error::CommandLine("expected IO port to include a port letter and an optional index after the equals sign".to_owned())),
+        };
+        let port_index: Option<u8> = match specified_port.chars().nth(1) {
+            Some(c) => match c.to_string().parse() {
+                Ok(c) => Some(c),
+                Err(_) => return Err(Error::CommandLine("port index is not an integer".to_owned())),
+            },
+            None => None,
+        };
+
+        Ok(f(port_letter, port_index))
+    }
+
+    if let Some(remaining) = util::try_consume("datamem", &s) {
+        let mut equals_char_indices = remaining.match_indices('=').map(|(i, _)| i);
+
+        let (address, data_type): (String, String) = match (equals_char_indices.next(), equals_char_indices.next()) {
+            (None, None) => return Err(Error::CommandLine("expected data memory address to include an address and data type separated by equals signs".to_owned())),
+            (Some(_), None) => return Err(Error::CommandLine("expected data memory address to include a data type separated by equals sign".to_owned())),
+            (Some(a), Some(dt)) => (remaining.chars().skip(a + 1).take(dt - a - 1).collect(), remaining.chars().skip(dt + 1).collect()),
+            (None, Some(_)) => unreachable!(),
+        };
+
+        let (address, data_type): (Pointer, DataType) = address.parse().and_then(|address| data_type.parse().map(|dt| (address, dt)))?;
+
+        // The data space is addressed by 16-bit pointers on every AVR.
+        if address.address > u16::MAX as u32 {
+            return Err(Error::AddressOutOfRange(format!("the data memory address {} does not fit in 16 bits", address)));
+        }
+
+        Ok(vec![Watch::MemoryAddress { address, data_type, space: MemorySpace::Data }])
+    } else if let Some(remaining) = util::try_consume("io-port", &s) {
+        io_port_from_str(remaining, |port_letter, port_index| vec![Watch::IoPort { port_letter, port_index }])
+    } else if let Some(remaining) = util::try_consume("io-pin", &s) {
+        io_port_from_str(remaining, |port_letter, port_index| vec![Watch::IoPin { port_letter, port_index }])
+    } else if let Some(remaining) = util::try_consume("io-ddr", &s) {
+        io_port_from_str(remaining, |port_letter, port_index| vec![Watch::IoDataDirectionRegister { port_letter, port_index }])
+    } else if let Some(remaining) = util::try_consume("io", &s) {
+        io_port_from_str(remaining, |port_letter, port_index| vec![
+            Watch::IoPort { port_letter, port_index },
+            Watch::IoPin { port_letter, port_index },
+            Watch::IoDataDirectionRegister { port_letter, port_index },
+        ])
+    } else if expression::is_expression(s.split('=').next().unwrap()) {
+        let (expression, data_type) = match s.find('=') {
+            Some(index) => (&s[..index], Some(s[index + 1..].parse()?)),
+            None => (s, None),
+        };
+
+        Ok(vec![Watch::Expression { expression: expression::parse_expression(expression)?, data_type }])
+    } else if let Some(watch) = parse_local(s)? {
+        Ok(vec![watch])
+    } else if s.chars().filter(|&c| c == '=').count() >= 1 { // symbol name watchables only have one equals sign
+        let (symbol_name, data_type_str) = s.split_at(s.find("=").unwrap());
+        let data_type_str = &data_type_str[1..];
+
+        data_type_str.parse().map(|data_type| {
+            vec![Watch::Symbol { name: symbol_name.to_owned(), data_type }]
+        })
+    } else {
+        Err(Error::CommandLine(format!("invalid WATCHABLE: {}", s)))
+    }
+}
+
+/// Parses `function::name` or `path:line:name`, each with an optional `=type`.
+fn parse_local(s: &str) -> Result<Option<Watch>> {
+    let (location, data_type) = match s.find('=') {
+        Some(index) => (&s[..index], Some(s[index + 1..].parse()?)),
+        None => (s, None),
+    };
+
+    let (scope, name) = if let Some(index) = location.rfind("::") {
+        (LocalScope::Function(location[..index].to_owned()), &location[index + 2..])
+    } else {
+        let parts = location.rsplitn(3, ':').collect::<Vec<_>>();
+
+        match parts[..] {
+            [name, line, path] if !path.is_empty() && !line.is_empty() && line.chars().all(|c| c.is_ascii_digit()) => {
+                let line = line.parse().map_err(|e| Error::CommandLine(format!("invalid line number {:?}: {}", line, e)))?;
+                (LocalScope::Line { path: path.to_owned(), line }, name)
+            },
+            _ => return Ok(None),
+        }
+    };
+
+    if name.is_empty() {
+        return Err(Error::CommandLine(format!("expected a variable name after the last colon: {}", s)));
+    }
+
+    Ok(Some(Watch::Local { scope, name: name.to_owned(), data_type }))
+}
+
+impl std::str::FromStr for Pointer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        let first_char = s.chars().next();
+        let second_char = s.chars().skip(1).next();
+
+        if s.chars().all(|c| c.is_digit(10)) {
+            u32::from_str_radix(s, 10).map(|address| Pointer { address, natural_radix: 10 }).map_err(|e| Error::CommandLine(format!("could not parse base-10 integer ({:?}) as pointer: {}", s, e)))
+        } else if first_char == Some('0') && (second_char == Some('x') || second_char == Some('X')) {
+            let (_, hex_digits) = s.split_at(2);
+            u32::from_str_radix(hex_digits, 16).map(|address| Pointer { address, natural_radix: 16 }).map_err(|e| Error::CommandLine(format!("could not parse base-16 integer ({:?}) as pointer: {}", hex_digits, e)))
+        } else {
+            Err(Error::CommandLine(format!("invalid pointer value: {:?}", s)))
+        }
+    }
+}
+
+impl std::str::FromStr for DataType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "u8" => Ok(DataType::U8), "i8" => Ok(DataType::I8),
+            "u16" => Ok(DataType::U16), "i16" => Ok(DataType::I16),
+            "u32" => Ok(DataType::U32), "i32" => Ok(DataType::I32),
+            "u64" => Ok(DataType::U64), "i64" => Ok(DataType::I64),
+            "u128" => Ok(DataType::U128), "i128" => Ok(DataType::I128),
+            "char" => Ok(DataType::Char),
+            s => {
+                if let Some(inner) = util::try_consume("null_terminated", s) {
+                    if let Some(inner) = util::try_consume("=", inner) {
+                        DataType::from_str(inner).map(|dt| DataType::NullTerminated(Box::new(dt)))
+                    } else {
+                        Err(Error::CommandLine(format!("null terminated types must have an inner type separated by the equals sign: {:?}", inner)))
+                    }
+                } else {
+                    Err(Error::CommandLine(format!("invalid data type: {:#?}", s)))
+                }
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for DataType {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            DataType::Char => write!(fmt, "char"),
+            DataType::NullTerminated(ref inner) => write!(fmt, "null_terminated={}", inner),
+            DataType::U8 => write!(fmt, "u8"), DataType::I8 => write!(fmt, "i8"),
+            DataType::U16 => write!(fmt, "u16"), DataType::I16 => write!(fmt, "i16"),
+            DataType::U32 => write!(fmt, "u32"), DataType::I32 => write!(fmt, "i32"),
+            DataType::U64 => write!(fmt, "u64"), DataType::I64 => write!(fmt, "i64"),
+            DataType::U128 => write!(fmt, "u128"), DataType::I128 => write!(fmt, "i128"),
+            DataType::HighLowBit => write!(fmt, "bit"),
+            DataType::IoRegisterStatus => write!(fmt, "io_register"),
+        }
+    }
+}
+
+mod util {
+    /// Consume the desired string and return the remainder.
+    pub fn try_consume<'h>(desired: &str, haystack: &'h str)
+        -> Option<&'h str> {
+        if haystack.starts_with(desired) {
+            let (_, remaining) = haystack.split_at(desired.len());
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for Pointer {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.natural_radix {
+            10 => write!(fmt, "{}", self.address),
+            _ => write!(fmt, "0x{:x}", self.address),
+        }
+    }
+}
+
+impl MemorySpace {
+    pub fn human_label(&self) -> &'static str {
+        match *self {
+            MemorySpace::Program => "program memory",
+            MemorySpace::Data => "data memory",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_parse_data_memory_address() {
+        assert_eq!(Ok(vec![Watch::MemoryAddress {
+            space: MemorySpace::Data,
+            address: Pointer { address: 999, natural_radix: 10 },
+            data_type: DataType::U8,
+        }]), parse_watch("datamem=999=u8"));
+
+        assert_eq!(Ok(vec![Watch::MemoryAddress {
+            space: MemorySpace::Data,
+            address: Pointer { address: 999, natural_radix: 10 },
+            data_type: DataType::Char,
+        }]), parse_watch("datamem=999=char"));
+
+        assert_eq!(Ok(vec![Watch::MemoryAddress {
+            space: MemorySpace::Data,
+            address: Pointer { address: 1, natural_radix: 16 },
+            data_type: DataType::NullTerminated(Box::new(DataType::Char)),
+        }]), parse_watch("datamem=0x01=null_terminated=char"));
+
+        assert_eq!(Ok(vec![Watch::MemoryAddress {
+            space: MemorySpace::Data,
+            address: Pointer { address: 0x77, natural_radix: 16 },
+            data_type: DataType::I32,
+        }]), parse_watch("datamem=0x77=i32"));
+    }
+
+    #[test]
+    fn pointers_are_printed_in_the_radix_they_were_written_in() {
+        assert_eq!("999", "999".parse::<Pointer>().unwrap().to_string());
+        assert_eq!("0x77", "0x77".parse::<Pointer>().unwrap().to_string());
+        assert_eq!("0x1f", Pointer { address: 0x1f, natural_radix: 8 }.to_string());
+    }
+
+    #[test]
+    fn rejects_data_memory_address_wider_than_16_bits() {
+        match parse_watch("datamem=0x10000=u8") {
+            Err(Error::AddressOutOfRange(..)) => (),
+            result => panic!("expected an out of range address but got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn can_parse_watchable_symbol() {
+        assert_eq!(Ok(vec![Watch::Symbol {
+            name: "TEST_BUFFER".to_owned(),
+            data_type: DataType::U8,
+        }]), parse_watch("TEST_BUFFER=u8"));
+    }
+
+    #[test]
+    fn can_parse_dotted_watchable_symbol() {
+        assert_eq!(Ok(vec![Watch::Symbol {
+            name: "counter.1".to_owned(),
+            data_type: DataType::U8,
+        }]), parse_watch("counter.1=u8"));
+    }
+
+    #[test]
+    fn can_parse_watchable_local() {
+        assert_eq!(Ok(vec![Watch::Local {
+            scope: LocalScope::Function("callStuff".to_owned()),
+            name: "d".to_owned(),
+            data_type: Some(DataType::U16),
+        }]), parse_watch("callStuff::d=u16"));
+
+        assert_eq!(Ok(vec![Watch::Local {
+            scope: LocalScope::Line { path: "file.c".to_owned(), line: 42 },
+            name: "i".to_owned(),
+            data_type: None,
+        }]), parse_watch("file.c:42:i"));
+    }
+
+    #[test]
+    fn can_parse_watchable_expression() {
+        assert_eq!(Ok(vec![Watch::Expression {
+            expression: expression::parse_expression("POINTS[1].x").unwrap(),
+            data_type: None,
+        }]), parse_watch("POINTS[1].x"));
+
+        assert_eq!(Ok(vec![Watch::Expression {
+            expression: expression::parse_expression("*(char *)NAME").unwrap(),
+            data_type: Some(DataType::NullTerminated(Box::new(DataType::Char))),
+        }]), parse_watch("*(char *)NAME=null_terminated=char"));
+    }
+
+    #[test]
+    fn can_parse_watchable_io_port() {
+        assert_eq!(Ok(vec![Watch::IoPort {
+            port_letter: 'A',
+            port_index: None,
+        }]), parse_watch("io-port=a"));
+        assert_eq!(Ok(vec![Watch::IoPort {
+            port_letter: 'D',
+            port_index: Some(3),
+        }]), parse_watch("io-port=D3"));
+        assert_eq!(Ok(vec![Watch::IoPin {
+            port_letter: 'E',
+            port_index: None,
+        }]), parse_watch("io-pin=E"));
+        assert_eq!(Ok(vec![Watch::IoDataDirectionRegister {
+            port_letter: 'A',
+            port_index: Some(0),
+        }]), parse_watch("io-ddr=A0"));
+        assert_eq!(Ok(vec![
+            Watch::IoPort { port_letter: 'D', port_index: Some(7) },
+            Watch::IoPin { port_letter: 'D', port_index: Some(7) },
+            Watch::IoDataDirectionRegister { port_letter: 'D', port_index: Some(7) },
+        ]), parse_watch("io=D7"));
+    }
+}
@@ -0,0 +1,161 @@
+//! Tests of the library API, using a tiny hand-assembled ELF executable so
+//! that no AVR toolchain is needed.
+
+use avr_sim::{MemorySpace, RunOutcome, Simulator, Watch, WatchState};
+use avr_sim::ram_fill::RamFill;
+
+const DATA_SPACE_ELF_OFFSET: u32 = 0x800000;
+const COUNTER_ADDRESS: u32 = 0x100;
+const LIMIT_ADDRESS: u32 = 0x101;
+
+/// Increments `COUNTER` until it equals `LIMIT`, then sleeps with
+/// interrupts disabled so that the simulation finishes.
+const PROGRAM: &[u16] = &[
+    0x9180, 0x0100, // loop: lds r24, COUNTER
+    0x9583,         //       inc r24
+    0x9380, 0x0100, //       sts COUNTER, r24
+    0x9190, 0x0101, //       lds r25, LIMIT
+    0x1789,         //       cp r24, r25
+    0xf7b9,         //       brne loop
+    0x94f8,         //       cli
+    0x9588,         //       sleep
+];
+
+#[test]
+fn lists_the_symbols_of_the_executable() {
+    let symbols = avr_sim::parse_watchable_symbols_from_elf(&elf()).unwrap();
+
+    let counter = symbols.iter().find(|symbol| symbol.name == "COUNTER").unwrap();
+    assert_eq!(MemorySpace::Data, counter.memory_space);
+    assert_eq!(COUNTER_ADDRESS, counter.address.address);
+    assert_eq!(1, counter.size);
+    assert!(symbols.iter().any(|symbol| symbol.name == "LIMIT"));
+}
+
+#[test]
+fn runs_until_a_watch_holds_a_value_then_to_completion() {
+    let mut simulator = Simulator::new("atmega328p", &elf()).unwrap();
+    simulator.fill_ram(RamFill::Byte(0));
+
+    let counter = watch("COUNTER=u8");
+    let limit = watch("LIMIT=u8");
+    simulator.write_watch(&limit, &WatchState::U8(5)).unwrap();
+    assert_eq!(WatchState::U8(5), simulator.read_watch(&limit).unwrap());
+
+    let outcome = simulator.run_until(|simulator, _| simulator.read_watch(&counter).unwrap() == WatchState::U8(3)).unwrap();
+    assert_eq!(RunOutcome::Stopped, outcome);
+    assert_eq!(WatchState::U8(3), simulator.read_watch(&counter).unwrap());
+
+    assert_eq!(RunOutcome::Finished, simulator.run_to_completion().unwrap());
+    assert_eq!(WatchState::U8(5), simulator.read_watch(&counter).unwrap());
+}
+
+#[test]
+fn writing_a_watch_changes_what_the_program_sees() {
+    let mut simulator = Simulator::new("atmega328p", &elf()).unwrap();
+    simulator.fill_ram(RamFill::Byte(0));
+
+    let counter = watch("COUNTER=u8");
+    simulator.write_watch(&watch("LIMIT=u8"), &WatchState::U8(10)).unwrap();
+    simulator.write_watch(&counter, &WatchState::U8(7)).unwrap();
+
+    assert_eq!(RunOutcome::Finished, simulator.run_to_completion().unwrap());
+    assert_eq!(WatchState::U8(10), simulator.read_watch(&counter).unwrap());
+}
+
+#[test]
+fn a_usart_can_be_attached_again() {
+    let mut simulator = Simulator::new("atmega328p", &elf()).unwrap();
+    simulator.fill_ram(RamFill::Byte(0));
+    simulator.write_watch(&watch("LIMIT=u8"), &WatchState::U8(2)).unwrap();
+
+    assert!(simulator.send_uart_input(b"x").is_err());
+    simulator.attach_uart(0).unwrap();
+    simulator.attach_uart(0).unwrap();
+    simulator.send_uart_input(b"x").unwrap();
+
+    assert_eq!(RunOutcome::Finished, simulator.run_to_completion().unwrap());
+    assert_eq!("", simulator.take_uart_output());
+}
+
+fn watch(s: &str) -> Watch {
+    avr_sim::parse_watch(s).unwrap().remove(0)
+}
+
+/// Builds an ELF executable with `PROGRAM` in '.text' and the one byte
+/// variables `COUNTER` and `LIMIT` in '.bss'.
+fn elf() -> Vec<u8> {
+    const HEADER_SIZE: u32 = 52;
+    const SECTION_HEADER_SIZE: u16 = 40;
+    const SYMBOL_SIZE: u32 = 16;
+
+    let text: Vec<u8> = PROGRAM.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let strtab = b"\0COUNTER\0LIMIT\0".to_vec();
+    let shstrtab = b"\0.text\0.bss\0.symtab\0.strtab\0.shstrtab\0".to_vec();
+
+    let mut symtab = vec![0; SYMBOL_SIZE as usize];
+    for &(name_offset, address) in &[(1, COUNTER_ADDRESS), (9, LIMIT_ADDRESS)] {
+        push_u32(&mut symtab, name_offset);
+        push_u32(&mut symtab, DATA_SPACE_ELF_OFFSET + address);
+        push_u32(&mut symtab, 1);
+        symtab.push(0x11); // STB_GLOBAL, STT_OBJECT
+        symtab.push(0);
+        push_u16(&mut symtab, 2); // .bss
+    }
+
+    let text_offset = HEADER_SIZE;
+    let strtab_offset = text_offset + text.len() as u32;
+    let shstrtab_offset = strtab_offset + strtab.len() as u32;
+    let symtab_offset = align4(shstrtab_offset + shstrtab.len() as u32);
+    let section_headers_offset = align4(symtab_offset + symtab.len() as u32);
+
+    let mut elf = Vec::new();
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    push_u16(&mut elf, 2); // ET_EXEC
+    push_u16(&mut elf, 83); // EM_AVR
+    push_u32(&mut elf, 1); // EV_CURRENT
+    push_u32(&mut elf, 0); // entry point
+    push_u32(&mut elf, 0); // no program headers
+    push_u32(&mut elf, section_headers_offset);
+    push_u32(&mut elf, 0); // flags
+    push_u16(&mut elf, HEADER_SIZE as u16);
+    push_u16(&mut elf, 32); // program header size
+    push_u16(&mut elf, 0); // program header count
+    push_u16(&mut elf, SECTION_HEADER_SIZE);
+    push_u16(&mut elf, 6); // section count
+    push_u16(&mut elf, 5); // .shstrtab
+
+    elf.extend_from_slice(&text);
+    elf.extend_from_slice(&strtab);
+    elf.extend_from_slice(&shstrtab);
+    elf.resize(symtab_offset as usize, 0);
+    elf.extend_from_slice(&symtab);
+    elf.resize(section_headers_offset as usize, 0);
+
+    // name, type, flags, address, offset, size, link, info, alignment, entry size
+    let section_headers = [
+        [0; 10],
+        [1, 1, 0x6, 0, text_offset, text.len() as u32, 0, 0, 2, 0], // .text: PROGBITS, AX
+        [7, 8, 0x3, DATA_SPACE_ELF_OFFSET + COUNTER_ADDRESS, strtab_offset, 2, 0, 0, 1, 0], // .bss: NOBITS, WA
+        [12, 2, 0, 0, symtab_offset, symtab.len() as u32, 4, 1, 4, SYMBOL_SIZE], // .symtab
+        [20, 3, 0, 0, strtab_offset, strtab.len() as u32, 0, 0, 1, 0], // .strtab
+        [28, 3, 0, 0, shstrtab_offset, shstrtab.len() as u32, 0, 0, 1, 0], // .shstrtab
+    ];
+    for field in section_headers.iter().flatten() {
+        push_u32(&mut elf, *field);
+    }
+
+    elf
+}
+
+fn align4(offset: u32) -> u32 {
+    (offset + 3) & !3
+}
+
+fn push_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
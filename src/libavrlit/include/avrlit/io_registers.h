@@ -6,6 +6,26 @@
 
 typedef volatile uint8_t* IoReg;
 
+// N.B. these are data space addresses, which are IO addresses plus 0x20.
+
+#if defined(__AVR_ATmega48__) || defined(__AVR_ATmega48A__) || defined(__AVR_ATmega48P__) || defined(__AVR_ATmega48PA__) || \
+    defined(__AVR_ATmega88__) || defined(__AVR_ATmega88A__) || defined(__AVR_ATmega88P__) || defined(__AVR_ATmega88PA__) || \
+    defined(__AVR_ATmega168__) || defined(__AVR_ATmega168A__) || defined(__AVR_ATmega168P__) || defined(__AVR_ATmega168PA__) || \
+    defined(__AVR_ATmega328__) || defined(__AVR_ATmega328P__) || \
+    defined(__AVR_ATmega640__) || defined(__AVR_ATmega1280__) || defined(__AVR_ATmega2560__)
+
+// SE is bit 0 of SMCR.
+const IoReg SLEEP_REGISTER = (IoReg)0x53;
+const uint8_t SLEEP_ENABLE_BIT = 1<<0;
+
+#elif defined(__AVR_ATtiny25__) || defined(__AVR_ATtiny45__) || defined(__AVR_ATtiny85__)
+
+// SE is bit 5 of MCUCR.
+const IoReg SLEEP_REGISTER = (IoReg)0x55;
+const uint8_t SLEEP_ENABLE_BIT = 1<<5;
+
+#else
+
 // This will not work for all CPUs.
 const IoReg SLEEP_REGISTER = (IoReg)0x33;
 
@@ -13,3 +33,4 @@ const IoReg SLEEP_REGISTER = (IoReg)0x33;
 // 1<<5. Sometimes not. This will not work for all CPUs.
 const uint8_t SLEEP_ENABLE_BIT = 1<<1;
 
+#endif
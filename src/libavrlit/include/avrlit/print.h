@@ -18,7 +18,8 @@ void putc(char c) {
 }
 
 void puts(const char *str) {
-  while (char c = *str++) {
+  char c;
+  while ((c = *str++)) {
     putc(c);
   }
 }
@@ -15,7 +15,7 @@ void sleep_indefinitely(void) {
   asm("cli");
   sleep_enable();
 
-  while(true) {
+  while(1) {
     sleep_cpu();
   }
 }
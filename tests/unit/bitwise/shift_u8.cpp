@@ -1,4 +1,4 @@
-// RUN: @cxx @cxxflags -mmcu=atmega328p @file -o @tempfile -O0 && avr-sim @tempfile -w OUTPUT_VALUE=u8
+// RUN: @cxx @cxxflags @file -o @tempfile @optflags @ldflags && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8
 
 #include <avrlit/boilerplate/unit_test.h>
 
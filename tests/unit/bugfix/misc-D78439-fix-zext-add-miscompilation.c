@@ -1,4 +1,4 @@
-// RUN: @cxx @cxxflags -mmcu=atmega328p @file -o @tempfile -O0 && avr-sim @tempfile -w TEST_STATE=u32
+// RUN: @cc @cflags @file -o @tempfile @optflags @ldflags && @avr_sim --mcu @mcu @tempfile -w TEST_STATE=u32
 
 // This integration test validates AVR compilation of a zero extended addition.
 //
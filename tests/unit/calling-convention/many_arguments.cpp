@@ -1,4 +1,4 @@
-// RUN: @cxx @cxxflags -mmcu=atmega328p @file -o @tempfile -O0 && avr-sim @tempfile -w OUTPUT_VALUE=u16
+// RUN: @cxx @cxxflags @file -o @tempfile @optflags @ldflags && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u16
 
 // This test case is motivated by two patches
 //
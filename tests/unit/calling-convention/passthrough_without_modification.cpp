@@ -1,4 +1,4 @@
-// RUN: @cxx @cxxflags -mmcu=atmega328p @file -o @tempfile -O0 && avr-sim @tempfile -w OUT_U64_A=u64 -w OUT_I64_B=i64 -w OUT_U16_C=u16 -w OUT_U16_D=u16 -w OUT_U8_E=u8
+// RUN: @cxx @cxxflags @file -o @tempfile @optflags @ldflags && @avr_sim --mcu @mcu @tempfile -w OUT_U64_A=u64 -w OUT_I64_B=i64 -w OUT_U16_C=u16 -w OUT_U16_D=u16 -w OUT_U8_E=u8
 
 #include <avrlit/boilerplate/unit_test.h>
 
@@ -1,4 +1,4 @@
-// RUN: @cxx @cxxflags -mmcu=atmega328p @file -o @first_tempfile -O0 && avr-sim @first_tempfile -w TEST_BUFFER=null_terminated=char
+// RUN: @cxx @cxxflags @file -o @first_tempfile @optflags @ldflags && @avr_sim --mcu @mcu @first_tempfile -w TEST_BUFFER=null_terminated=char
 
 #include <avrlit/boilerplate/unit_test.h>
 
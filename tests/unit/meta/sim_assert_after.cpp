@@ -0,0 +1,17 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --assert-after 'OUTPUT_VALUE=u8 == 3' -p OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+
+volatile uint8_t OUTPUT_VALUE = 0;
+
+// The assertion only has to hold once the program finishes, so the
+// intermediate value of three does not count.
+//
+// CHECK: after_execution(OUTPUT_VALUE) = 2
+// CHECK: error: assertion 'OUTPUT_VALUE == 3' failed after execution in CPU cycle #[[\d+]] at PC 0x[[\w+]]
+// CHECK-NEXT: note: OUTPUT_VALUE = 2
+// CHECK: avr-sim exited with code 1
+void unit_test(void) {
+  OUTPUT_VALUE = 3;
+  OUTPUT_VALUE = 2;
+}
@@ -0,0 +1,18 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --assert-always 'OUTPUT_VALUE=u8 < 3' -p OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+
+volatile uint8_t OUTPUT_VALUE = 0;
+
+// The assertion is checked after every CPU cycle, so the simulation
+// stops in the cycle that breaks it, reports where, and fails.
+//
+// CHECK: error: assertion 'OUTPUT_VALUE < 3' failed in CPU cycle #[[\d+]] at PC 0x[[\w+]]
+// CHECK-NEXT: note: OUTPUT_VALUE = 3
+// CHECK: after_execution(OUTPUT_VALUE) = 3
+// CHECK: avr-sim exited with code 1
+void unit_test(void) {
+  for (uint8_t i = 0; i < 10; i++) {
+    OUTPUT_VALUE = i;
+  }
+}
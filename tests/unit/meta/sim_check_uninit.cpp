@@ -0,0 +1,24 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile --check-uninit -w OUTPUT_VALUE=u16
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint16_t OUTPUT_VALUE = 0;
+
+// Sums a stack buffer that is fully written before it is read, so
+// the uninitialized memory checker must not report anything.
+//
+// CHECK: after_execution(OUTPUT_VALUE) = 28
+void unit_test(void) {
+  volatile uint8_t buffer[8];
+
+  for (uint8_t i = 0; i < 8; i++) {
+    buffer[i] = i;
+  }
+
+  uint16_t sum = 0;
+  for (uint8_t i = 0; i < 8; i++) {
+    sum += buffer[i];
+  }
+
+  OUTPUT_VALUE = sum;
+}
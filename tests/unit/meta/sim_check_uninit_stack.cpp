@@ -0,0 +1,22 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --check-uninit -w OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// Only the first byte of the buffer is written, so reading the last one
+// reads whatever was left on the stack. The report names the PC, the
+// function and the line of the read, and the simulation fails.
+//
+// CHECK: error: read of uninitialized memory at 0x[[\w+]] in CPU cycle #[[\d+]] (PC 0x[[\w+]] in 'read_before_write' at [[.*]]sim_check_uninit_stack.cpp:17)
+// CHECK: after_execution(OUTPUT_VALUE) =
+// CHECK: avr-sim exited with code 1
+extern "C" __attribute__((noinline)) uint8_t read_before_write(void) {
+  volatile uint8_t buffer[4];
+  buffer[0] = 1;
+  return buffer[3];
+}
+
+void unit_test(void) {
+  OUTPUT_VALUE = read_before_write();
+}
@@ -1,4 +1,4 @@
-// RUN: @cxx @cxxflags -mmcu=atmega328p @file -o @tempfile -O0 && avr-sim @tempfile
+// RUN: @cc @cflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile
 
 #include <avrlit/boilerplate/unit_test.h>
 
@@ -0,0 +1,15 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile --frequency 1000000 -w OUTPUT_VALUE=u8
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// At 1 MHz every CPU cycle takes a microsecond, so simulated time is
+// printed next to each change and at the end of the run.
+//
+// CHECK: Dumping watches values changed in CPU cycle #[[\d+]] ([[\d+\.\d+]] [[(?:µs|ms)]])
+// CHECK: OUTPUT_VALUE = 7
+// CHECK: Simulated [[\d+]] CPU cycles ([[\d+\.\d+]] [[(?:µs|ms)]] at 1000000 Hz)
+void unit_test(void) {
+  OUTPUT_VALUE = 7;
+}
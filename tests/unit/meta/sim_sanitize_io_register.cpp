@@ -0,0 +1,22 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --sanitize -w OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// Data address 0x56 is reserved on every MCU with a table of IO registers,
+// so writing to it is reported with the PC, the function and the line of
+// the store, and the simulation fails.
+//
+// CHECK: error: sanitizer: write to 0x0056 in IO space, which is not an IO register on '[[\w+]]' in CPU cycle #[[\d+]] (PC 0x[[\w+]] in 'write_reserved_register' at [[.*]]sim_sanitize_io_register.cpp:16)
+// CHECK: after_execution(OUTPUT_VALUE) = 1
+// CHECK: avr-sim exited with code 1
+extern "C" __attribute__((noinline)) void write_reserved_register(void) {
+  volatile uint8_t *reserved = (volatile uint8_t *)0x56;
+  *reserved = 0xaa;
+}
+
+void unit_test(void) {
+  write_reserved_register();
+  OUTPUT_VALUE = 1;
+}
@@ -0,0 +1,22 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --sanitize -w OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// Stores to the first byte past the end of RAM. The report names the PC,
+// the function and the line of the store, and the simulation fails.
+//
+// CHECK: error: sanitizer: store to 0x[[\w+]], past the end of RAM at 0x[[\w+]] in CPU cycle #[[\d+]] (PC 0x[[\w+]] in 'store_past_ram_end' at [[.*]]sim_sanitize_ram_end.cpp:16)
+// CHECK: after_execution(OUTPUT_VALUE) = 1
+// CHECK: avr-sim exited with code 1
+extern "C" __attribute__((noinline)) void store_past_ram_end(void) {
+  volatile uint8_t *past_end = (volatile uint8_t *)(RAMEND + 1);
+  *past_end = 0xaa;
+}
+
+void unit_test(void) {
+  store_past_ram_end();
+  OUTPUT_VALUE = 1;
+}
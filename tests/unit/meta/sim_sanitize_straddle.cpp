@@ -0,0 +1,23 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --sanitize -w OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint8_t OUTPUT_VALUE = 0;
+volatile uint8_t NARROW = 0;
+
+// Stores a 16-bit value through a pointer to a single byte, so the second
+// byte lands outside of 'NARROW'. The report names the PC, the function
+// and the line of the store, and the simulation fails.
+//
+// CHECK: error: sanitizer: multi-byte store to [[.*]]'NARROW'[[.*]] straddles more than one symbol in CPU cycle #[[\d+]] (PC 0x[[\w+]] in 'store_wide' at [[.*]]sim_sanitize_straddle.cpp:17)
+// CHECK: after_execution(OUTPUT_VALUE) = 1
+// CHECK: avr-sim exited with code 1
+extern "C" __attribute__((noinline)) void store_wide(volatile uint8_t *target) {
+  volatile uint16_t *wide = (volatile uint16_t *)target;
+  *wide = 0x1234;
+}
+
+void unit_test(void) {
+  store_wide(&NARROW);
+  OUTPUT_VALUE = 1;
+}
@@ -0,0 +1,33 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8 -w io-port=B5 > @tempfile.full && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8 -w io-port=B5 --save-snapshot-at snapshot_point --save-snapshot-to @tempfile.snap > /dev/null && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8 -w io-port=B5 --load-snapshot @tempfile.snap > @tempfile.resumed && diff @tempfile.full @tempfile.resumed && echo "outputs are identical"
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8 -w io-port=B5 > @tempfile.full && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8 -w io-port=B5 --frequency 1000000 --save-snapshot-at snapshot_point --save-snapshot-to @tempfile.snap > /dev/null && @avr_sim --mcu @mcu @tempfile -w OUTPUT_VALUE=u8 -w io-port=B5 --load-snapshot @tempfile.snap > @tempfile.resumed 2> @tempfile.err && grep -q "clock frequency differ" @tempfile.err && ! grep -vxFf @tempfile.full @tempfile.resumed && echo "outputs are identical after the snapshot point"
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+volatile uint8_t OUTPUT_VALUE = 0;
+
+extern "C" __attribute__((noinline)) void snapshot_point(void) {
+  asm volatile("");
+}
+
+// A run resumed from a snapshot prints exactly what an uninterrupted run
+// prints, including the GPIO state and the cycle of every change. A snapshot
+// saved at another clock frequency has a transcript with other times in it,
+// so it is not replayed, but every line printed after it still matches.
+//
+// CHECK: outputs are identical
+void unit_test(void) {
+  DDRB |= (1 << 5);
+  PORTB |= (1 << 5);
+
+  for (uint8_t i = 0; i < 4; i++) {
+    OUTPUT_VALUE += i;
+  }
+
+  snapshot_point();
+
+  PORTB &= ~(1 << 5);
+  for (uint8_t i = 0; i < 4; i++) {
+    OUTPUT_VALUE += i;
+  }
+}
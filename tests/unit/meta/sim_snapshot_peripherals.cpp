@@ -0,0 +1,19 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --save-snapshot-at snapshot_point --save-snapshot-to @tempfile.snap 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+extern "C" __attribute__((noinline)) void snapshot_point(void) {
+  asm volatile("");
+}
+
+// The internal state of a running timer cannot be saved, so a snapshot
+// taken while it runs would not resume like an uninterrupted run.
+//
+// CHECK: error: could not save snapshot to '[[.*]]': cannot save a snapshot while a peripheral is in use
+// CHECK: avr-sim exited with code 7
+void unit_test(void) {
+  TCCR0B = (1 << CS00);
+  snapshot_point();
+  TCCR0B = 0;
+}
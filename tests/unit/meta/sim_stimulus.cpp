@@ -0,0 +1,23 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && printf 'at cycle 1000: PINB3 = HIGH\nat symbol wait_for_button: PIND = 0x04\n' > @tempfile.stim && @avr_sim --mcu @mcu @tempfile --stimulus @tempfile.stim -w OUTPUT_VALUE=u8
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+extern "C" __attribute__((noinline)) void wait_for_button(void) {
+  while (!(PIND & (1 << 2))) { }
+}
+
+// Polls input pins that only change because the stimulus script drives them.
+//
+// CHECK: after_execution(OUTPUT_VALUE) = 4
+void unit_test(void) {
+  DDRB &= ~(1 << 3);
+  DDRD = 0;
+
+  while (!(PINB & (1 << 3))) { }
+
+  wait_for_button();
+  OUTPUT_VALUE = PIND;
+}
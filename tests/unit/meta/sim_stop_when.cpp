@@ -0,0 +1,19 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && { @avr_sim --mcu @mcu @tempfile --stop-when 'io-port=B3 == HIGH' -p OUTPUT_VALUE=u8 2>&1; echo "avr-sim exited with code $?"; }
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+volatile uint8_t OUTPUT_VALUE = 0;
+
+// The simulation stops as soon as PB3 goes high, so the last store
+// never runs and the run still counts as a success.
+//
+// CHECK: note: stopping simulation in CPU cycle #[[\d+]] at PC 0x[[\w+]] because 'IO PORTB3 == 1' holds
+// CHECK: after_execution(OUTPUT_VALUE) = 1
+// CHECK: avr-sim exited with code 0
+void unit_test(void) {
+  OUTPUT_VALUE = 1;
+  DDRB |= (1 << 3);
+  PORTB |= (1 << 3);
+  OUTPUT_VALUE = 2;
+}
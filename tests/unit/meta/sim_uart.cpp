@@ -0,0 +1,37 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && printf 'hi' > @tempfile.in && @avr_sim --mcu @mcu @tempfile --uart-input @tempfile.in --uart-log @tempfile.uart -w OUTPUT_VALUE=u8 && cat @tempfile.uart
+// DIFF-IGNORE: ^\d+ (TX|RX)
+// REQUIRES: usart0
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+static char receive(void) {
+  while (!(UCSR0A & (1 << RXC0))) { }
+  return UDR0;
+}
+
+static void transmit(char c) {
+  while (!(UCSR0A & (1 << UDRE0))) { }
+  UDR0 = c;
+}
+
+// Echoes the bytes from the input file back in upper case. The transmitted
+// bytes are printed before the watches that are dumped after execution.
+//
+// CHECK: HI
+// CHECK: after_execution(OUTPUT_VALUE) = 2
+// CHECK: RX 0x68 'h'
+// CHECK: TX 0x48 'H'
+// CHECK: RX 0x69 'i'
+// CHECK: TX 0x49 'I'
+void unit_test(void) {
+  UBRR0 = 0;
+  UCSR0B = (1 << RXEN0) | (1 << TXEN0);
+
+  for (uint8_t i = 0; i < 2; i++) {
+    transmit(receive() - 'a' + 'A');
+    OUTPUT_VALUE++;
+  }
+}
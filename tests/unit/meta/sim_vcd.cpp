@@ -0,0 +1,25 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile --vcd @tempfile.vcd -w io-port=B5 -w OUTPUT_VALUE=u8 && cat @tempfile.vcd
+// DIFF-IGNORE: ^#\d+$
+
+#include <avrlit/boilerplate/unit_test.h>
+#include <avr/io.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// Both the IO pin and the variable are recorded as signals, and start
+// out low and zero.
+//
+// CHECK: $timescale 1ps $end
+// CHECK: $var wire 1 ! PORTB5 $end
+// CHECK: $var wire 8 " OUTPUT_VALUE $end
+// CHECK: $enddefinitions $end
+// CHECK: #0
+// CHECK: 0!
+// CHECK: b0 "
+// CHECK: 1!
+// CHECK: b11 "
+void unit_test(void) {
+  DDRB |= (1 << 5);
+  PORTB |= (1 << 5);
+  OUTPUT_VALUE = 3;
+}
@@ -0,0 +1,18 @@
+// RUN: @cc @cflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile -p datamem=0x8ff=u8 -p datamem=0x8ff=u16 -p datamem=0x900=u8 -w OUTPUT_VALUE=u8 2>&1
+// REQUIRES: atmega328p
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// The last byte of SRAM on the ATmega328 is at 0x8ff. Watches that
+// run past it are skipped with a warning rather than crashing the
+// simulator, and the remaining watches are still printed.
+//
+// CHECK: 0x8ff (data memory) is 2 bytes but data memory on '[[\w+]]' ends at 0x08ff
+// CHECK: 0x900 (data memory) is 1 bytes but data memory on '[[\w+]]' ends at 0x08ff
+// CHECK: after_execution(0x8ff (data memory)) =
+// CHECK: after_execution(OUTPUT_VALUE) = 7
+void unit_test(void) {
+  OUTPUT_VALUE = 7;
+}
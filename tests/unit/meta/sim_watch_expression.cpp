@@ -0,0 +1,24 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile -p 'POINTS[1].y' -p 'CURRENT->y' -p '*(u8 *)CURRENT' -p '(u8)POINTS[1].y'
+
+#include <avrlit/boilerplate/unit_test.h>
+
+struct Point {
+  uint8_t x;
+  uint16_t y;
+};
+
+Point POINTS[2];
+Point *CURRENT = 0;
+
+// Member offsets and element sizes come from the debug info. Casts
+// read a value as a different type.
+//
+// CHECK: after_execution(POINTS[1].y) = 300
+// CHECK: after_execution(CURRENT->y) = 300
+// CHECK: after_execution(*(u8 *)CURRENT) = 7
+// CHECK: after_execution((u8)POINTS[1].y) = 44
+void unit_test(void) {
+  POINTS[1].x = 7;
+  POINTS[1].y = 300;
+  CURRENT = &POINTS[1];
+}
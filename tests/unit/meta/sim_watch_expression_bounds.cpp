@@ -0,0 +1,16 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile -p 'BUFFER[4000000000]' -w OUTPUT_VALUE=u8 2>&1
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint16_t BUFFER[4];
+uint8_t OUTPUT_VALUE = 0;
+
+// An index so large that its byte offset does not fit in an address is
+// reported and the watch is skipped, rather than crashing the simulator.
+//
+// CHECK: 'BUFFER[4000000000]' runs past the end of memory
+// CHECK: after_execution(OUTPUT_VALUE) = 7
+void unit_test(void) {
+  BUFFER[0] = 1;
+  OUTPUT_VALUE = 7;
+}
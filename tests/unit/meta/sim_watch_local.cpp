@@ -0,0 +1,19 @@
+// RUN: @cxx @cxxflags @file -o @tempfile -O0 @ldflags && @avr_sim --mcu @mcu @tempfile --print-on-change 'callStuff::d=u16' -w OUTPUT_VALUE=u16
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint16_t OUTPUT_VALUE = 0;
+
+// Parameters live on the stack at -O0, relative to the frame base.
+// The watch can only be read while 'callStuff' is executing.
+//
+// CHECK: changed(callStuff::d) = 1234
+// CHECK: changed(callStuff::d) = <out of scope>
+// CHECK: after_execution(OUTPUT_VALUE) = 1235
+__attribute__((noinline)) void callStuff(uint16_t d) {
+  OUTPUT_VALUE = d + 1;
+}
+
+void unit_test(void) {
+  callStuff(1234);
+}
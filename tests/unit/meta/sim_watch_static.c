@@ -0,0 +1,23 @@
+// RUN: @cc @cflags @file -o @tempfile -O0 @ldflags && name=$(@avr_sim --mcu @mcu @tempfile --list-symbols | grep -o 'counter\.[0-9]\+' | head -n 1) && @avr_sim --mcu @mcu @tempfile -w "$name=u8" -w OUTPUT_VALUE=u8
+// REQUIRES: gcc
+
+#include <avrlit/boilerplate/unit_test.h>
+
+uint8_t OUTPUT_VALUE = 0;
+
+// GCC names function-static variables in C like 'counter.1'. The number
+// varies between versions, so it is taken from the symbol list. The dot is
+// part of the symbol name, not a member access.
+//
+// CHECK: after_execution(counter.[[\d+]]) = 3
+// CHECK: after_execution(OUTPUT_VALUE) = 3
+__attribute__((noinline)) uint8_t tick(void) {
+  static uint8_t counter = 0;
+  return ++counter;
+}
+
+void unit_test(void) {
+  tick();
+  tick();
+  OUTPUT_VALUE = tick();
+}